@@ -0,0 +1,84 @@
+//! Restock and remove latency on a warehouse with a million zones.
+//!
+//! The crate only builds a binary, so the modules placement depends on are
+//! compiled in directly. Run with `cargo bench`.
+#![allow(dead_code, unused_imports)]
+
+#[path = "../src/bitmap.rs"]
+mod bitmap;
+#[path = "../src/catalogue.rs"]
+mod catalogue;
+#[path = "../src/currency.rs"]
+mod currency;
+#[path = "../src/product.rs"]
+mod product;
+#[path = "../src/search.rs"]
+mod search;
+#[path = "../src/warehouse.rs"]
+mod warehouse;
+
+use product::{Product, ProductList, Quality};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+use warehouse::{PlacementStrategy, Warehouse};
+
+const ROWS: usize = 100;
+const SHELVES: usize = 10;
+const LEVELS: usize = 10;
+const ZONES: usize = 100;
+const RUNS: u32 = 20;
+
+fn warehouse(strategy: PlacementStrategy) -> Warehouse {
+    let mut warehouse = Warehouse::new();
+    warehouse.initialize_rows(ROWS, SHELVES, LEVELS, ZONES);
+    warehouse.strategy = strategy;
+    warehouse
+}
+
+fn report(name: &str, elapsed: Duration, runs: u32) {
+    println!("{:<48} {:>12.3?} per run ({} runs)", name, elapsed / runs, runs);
+}
+
+fn bench(name: &str, mut run: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run();
+    }
+    report(name, start.elapsed(), RUNS);
+}
+
+fn main() {
+    let mut list = ProductList::new();
+    let bolt = list.add(Product::new("Bolt", 10, 0, Quality::Normal)).unwrap();
+    let crate_id = list.add(Product::new("Crate", 500, 0, Quality::Oversized(3))).unwrap();
+    let capacity = ROWS * SHELVES * LEVELS * ZONES;
+    println!("Warehouse with {} zones", capacity);
+
+    for strategy in [PlacementStrategy::Contiguous, PlacementStrategy::RoundRobin] {
+        println!("\n{:?} placement", strategy);
+        let mut warehouse = warehouse(strategy);
+        bench("restock 10 units, empty warehouse", || {
+            warehouse.independent_restock(bolt, 10, &mut list, None).unwrap();
+        });
+
+        let fill = capacity * 9 / 10 - warehouse.occupied_zones();
+        let start = Instant::now();
+        warehouse.independent_restock(bolt, fill, &mut list, None).unwrap();
+        report("fill to 90%", start.elapsed(), 1);
+
+        bench("restock 10 units, 90% full", || {
+            warehouse.independent_restock(bolt, 10, &mut list, None).unwrap();
+        });
+        bench("remove 10 units, 90% full", || {
+            warehouse.remove_stock(bolt, 10).unwrap();
+        });
+        bench("restock 5 oversized units, 90% full", || {
+            warehouse.independent_restock(crate_id, 5, &mut list, None).unwrap();
+        });
+        bench("find 1000 contiguous free zones, 90% full", || {
+            black_box(warehouse.find_first_contiguous_space(black_box(1000)));
+        });
+    }
+}
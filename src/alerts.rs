@@ -0,0 +1,239 @@
+use crate::inventory::Storage;
+use chrono::NaiveDate;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display, Formatter, Write as _},
+    io::Write,
+    process::{Command, Stdio},
+};
+use ErrorMessage::*;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ItemAlert {
+    pub warehouse: String,
+    pub product_id: u64,
+    pub product_name: String,
+    pub placement: (usize, usize, usize, usize),
+    pub expiry_date: NaiveDate,
+    pub lot: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StockAlert {
+    pub product_id: u64,
+    pub product_name: String,
+    pub available: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertDigest {
+    pub date: NaiveDate,
+    pub expired: Vec<ItemAlert>,
+    pub expiring: BTreeMap<u64, Vec<ItemAlert>>,
+    pub low_stock: Vec<StockAlert>,
+    pub out_of_stock: Vec<StockAlert>,
+}
+
+pub trait Notifier {
+    fn notify(&self, digest: &AlertDigest) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct CommandNotifier {
+    pub command: String,
+}
+
+#[derive(Debug)]
+pub enum ErrorMessage {
+    CommandNotStarted,
+    CommandFailed,
+}
+
+#[derive(Debug)]
+struct AlertError {
+    message: String,
+}
+
+impl ErrorMessage {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            CommandNotStarted => "Could not start notifier command",
+            CommandFailed => "Notifier command failed",
+        }
+    }
+}
+
+impl Display for ErrorMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Display for AlertError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Alert Error: {}", self.message)
+    }
+}
+
+impl Error for AlertError {}
+
+impl AlertError {
+    pub fn boxed(message: String) -> Box<dyn Error> {
+        Box::new(AlertError { message })
+    }
+
+    pub fn notifier(message: ErrorMessage, details: String) -> Box<dyn Error> {
+        AlertError::boxed(format!("{} - {}", message, details))
+    }
+}
+
+impl Display for ItemAlert {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (ID {}) at {} {:?}, Expiry Date: {}",
+            self.product_name, self.product_id, self.warehouse, self.placement, self.expiry_date
+        )?;
+        if let Some(lot) = &self.lot {
+            write!(f, ", Lot: {}", lot)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for StockAlert {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (ID {}): {} available",
+            self.product_name, self.product_id, self.available
+        )
+    }
+}
+
+impl Display for AlertDigest {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(f, "Inventory alerts for {}", self.date)?;
+        writeln!(f, "\nExpired items: {}", self.expired.len())?;
+        self.expired.iter().try_for_each(|alert| writeln!(f, "  {}", alert))?;
+        for (horizon, alerts) in &self.expiring {
+            writeln!(f, "\nExpiring within {} days: {}", horizon, alerts.len())?;
+            alerts.iter().try_for_each(|alert| writeln!(f, "  {}", alert))?;
+        }
+        writeln!(f, "\nLow stock: {}", self.low_stock.len())?;
+        self.low_stock.iter().try_for_each(|alert| writeln!(f, "  {}", alert))?;
+        writeln!(f, "\nOut of stock: {}", self.out_of_stock.len())?;
+        self.out_of_stock.iter().try_for_each(|alert| writeln!(f, "  {}", alert))
+    }
+}
+
+impl AlertDigest {
+    pub fn scan(storage: &Storage, date: NaiveDate, horizons: &[u64], low_stock: usize) -> Self {
+        let mut horizons = horizons.to_vec();
+        horizons.sort();
+        horizons.dedup();
+        let mut digest = AlertDigest {
+            date,
+            expired: Vec::new(),
+            expiring: horizons.iter().map(|horizon| (*horizon, Vec::new())).collect(),
+            low_stock: Vec::new(),
+            out_of_stock: Vec::new(),
+        };
+
+        for (warehouse_name, warehouse) in &storage.warehouses {
+            for item in warehouse.items() {
+                let Some(expiry_date) = item.expiry_date else {
+                    continue;
+                };
+                let alert = ItemAlert {
+                    warehouse: warehouse_name.clone(),
+                    product_id: item.id,
+                    product_name: storage.find_product_name(item.id).unwrap_or("N/A").to_string(),
+                    placement: item.placement,
+                    expiry_date,
+                    lot: item.lot.clone(),
+                };
+                let days_left = (expiry_date - date).num_days();
+                if days_left < 0 {
+                    digest.expired.push(alert);
+                } else if let Some(horizon) =
+                    horizons.iter().find(|horizon| days_left <= **horizon as i64)
+                {
+                    digest.expiring.get_mut(horizon).unwrap().push(alert);
+                }
+            }
+        }
+
+        let mut products: Vec<_> = storage.list.products().collect();
+        products.sort_by(|a, b| a.name().cmp(b.name()));
+        for product in products {
+            let alert = StockAlert {
+                product_id: product.id,
+                product_name: product.name().to_string(),
+                available: product.available_quantity(),
+            };
+            match alert.available {
+                0 => digest.out_of_stock.push(alert),
+                available if available <= low_stock => digest.low_stock.push(alert),
+                _ => {}
+            }
+        }
+        digest
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_email(&self) -> String {
+        let expiring: usize = self.expiring.values().map(|alerts| alerts.len()).sum();
+        let mut email = String::new();
+        let _ = writeln!(email, "Subject: Inventory alerts for {}", self.date);
+        let _ = writeln!(email, "Content-Type: text/plain; charset=utf-8");
+        let _ = writeln!(email);
+        let _ = writeln!(
+            email,
+            "{} expired, {} expiring, {} low stock and {} out of stock.",
+            self.expired.len(),
+            expiring,
+            self.low_stock.len(),
+            self.out_of_stock.len()
+        );
+        let _ = writeln!(email);
+        let _ = write!(email, "{}", self);
+        email
+    }
+}
+
+impl CommandNotifier {
+    pub fn new(command: &str) -> Self {
+        CommandNotifier {
+            command: command.to_string(),
+        }
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, digest: &AlertDigest) -> Result<(), Box<dyn Error>> {
+        let json = digest.to_json()?;
+        let mut child = match Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => return Err(AlertError::notifier(CommandNotStarted, e.to_string())),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(json.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(AlertError::notifier(CommandFailed, status.to_string()))
+        }
+    }
+}
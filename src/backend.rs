@@ -0,0 +1,886 @@
+use crate::{
+    inventory::Storage,
+    product::{Product, ProductItem, ProductList},
+    warehouse::{ItemPart, Level, PlacementStrategy, Row, Shelf, Warehouse, Zone},
+};
+use rusqlite::{params_from_iter, types::Value, Connection, Transaction};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    error::Error,
+    fmt::{self, Debug, Display, Formatter},
+    fs::File,
+    hash::{Hash, Hasher},
+    io::{BufReader, Write},
+    path::Path,
+};
+use ErrorMessage::*;
+use ItemPart::*;
+
+const SQLITE_EXTENSIONS: [&str; 3] = ["db", "sqlite", "sqlite3"];
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS products (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        price INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        quality TEXT NOT NULL,
+        category TEXT,
+        sku TEXT,
+        barcode TEXT,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS warehouses (
+        name TEXT PRIMARY KEY,
+        available_space INTEGER NOT NULL,
+        strategy TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS rows (
+        warehouse TEXT,
+        row INTEGER,
+        available_space INTEGER NOT NULL,
+        PRIMARY KEY (warehouse, row)
+    );
+    CREATE TABLE IF NOT EXISTS shelves (
+        warehouse TEXT,
+        row INTEGER,
+        shelf INTEGER,
+        available_space INTEGER NOT NULL,
+        load_rating INTEGER,
+        PRIMARY KEY (warehouse, row, shelf)
+    );
+    CREATE TABLE IF NOT EXISTS levels (
+        warehouse TEXT,
+        row INTEGER,
+        shelf INTEGER,
+        level INTEGER,
+        available_space INTEGER NOT NULL,
+        load_rating INTEGER,
+        PRIMARY KEY (warehouse, row, shelf, level)
+    );
+    CREATE TABLE IF NOT EXISTS zones (
+        warehouse TEXT,
+        row INTEGER,
+        shelf INTEGER,
+        level INTEGER,
+        zone INTEGER,
+        part TEXT,
+        base_level INTEGER,
+        start_zone INTEGER,
+        last_zone INTEGER,
+        PRIMARY KEY (warehouse, row, shelf, level, zone)
+    );
+    CREATE TABLE IF NOT EXISTS items (
+        warehouse TEXT,
+        row INTEGER,
+        shelf INTEGER,
+        level INTEGER,
+        zone INTEGER,
+        product_id INTEGER NOT NULL,
+        lot TEXT,
+        serial TEXT,
+        expiry_date TEXT,
+        status TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (warehouse, row, shelf, level, zone)
+    );
+    CREATE INDEX IF NOT EXISTS items_by_product ON items (product_id);
+";
+
+/// Where a `Storage` is read from and written to. A backend may keep state
+/// between saves, so a storage holds on to the one it was loaded with.
+pub trait StorageBackend: Debug {
+    fn path(&self) -> &str;
+    fn load(&mut self) -> Result<Storage, Box<dyn Error>>;
+    fn save(&mut self, storage: &Storage) -> Result<(), Box<dyn Error>>;
+}
+
+/// The backend for a file, by extension: SQLite for `.db`, `.sqlite` and
+/// `.sqlite3`, a JSON document for anything else.
+pub fn for_path(path: &str) -> Box<dyn StorageBackend> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+    match extension {
+        Some(extension) if SQLITE_EXTENSIONS.contains(&extension.as_str()) => {
+            Box::new(SqliteBackend::new(path))
+        }
+        _ => Box::new(JsonBackend::new(path)),
+    }
+}
+
+#[derive(Debug)]
+pub struct JsonBackend {
+    path: String,
+}
+
+/// Products, warehouse layout, zones and items in their own tables, with the
+/// rest of the storage as JSON values in `meta`. Saves only write the rows,
+/// and the zones and items of the levels, that changed since the last load or
+/// save.
+#[derive(Debug)]
+pub struct SqliteBackend {
+    path: String,
+    connection: Option<Connection>,
+    written: Option<Fingerprints>,
+}
+
+/// Hashes of the rows known to be in the file, by table and by key.
+type Fingerprints = HashMap<&'static str, HashMap<u64, u64>>;
+
+type Values<'a> = dyn Fn() -> Result<Vec<Value>, Box<dyn Error>> + 'a;
+type Emit<'a> = dyn FnMut(Record) -> Result<(), Box<dyn Error>> + 'a;
+type EmitLevel<'a> = dyn FnMut(&[Key], &Level) -> Result<(), Box<dyn Error>> + 'a;
+type Rows = fn(&Storage, &mut Emit) -> Result<(), Box<dyn Error>>;
+
+struct Table {
+    name: &'static str,
+    keys: &'static [&'static str],
+    columns: &'static [&'static str],
+    rows: Rows,
+}
+
+/// A key column, borrowed from the storage so that unchanged rows are skipped
+/// without allocating.
+#[derive(Hash)]
+enum Key<'a> {
+    Integer(i64),
+    Text(&'a str),
+}
+
+/// A row of a table: its key, a hash of its content, and the remaining
+/// columns, which are only built when the row has to be written.
+struct Record<'a> {
+    key: &'a [Key<'a>],
+    content: u64,
+    values: &'a Values<'a>,
+}
+
+const TABLES: [Table; 6] = [
+    Table {
+        name: "meta",
+        keys: &["key"],
+        columns: &["value"],
+        rows: meta_rows,
+    },
+    Table {
+        name: "products",
+        keys: &["id"],
+        columns: &["name", "price", "quantity", "quality", "category", "sku", "barcode", "data"],
+        rows: product_rows,
+    },
+    Table {
+        name: "warehouses",
+        keys: &["name"],
+        columns: &["available_space", "strategy"],
+        rows: warehouse_rows,
+    },
+    Table {
+        name: "rows",
+        keys: &["warehouse", "row"],
+        columns: &["available_space"],
+        rows: row_rows,
+    },
+    Table {
+        name: "shelves",
+        keys: &["warehouse", "row", "shelf"],
+        columns: &["available_space", "load_rating"],
+        rows: shelf_rows,
+    },
+    Table {
+        name: "levels",
+        keys: &["warehouse", "row", "shelf", "level"],
+        columns: &["available_space", "load_rating"],
+        rows: level_rows,
+    },
+];
+
+/// Zones and items are written a level at a time, for the levels whose
+/// revision changed since they were last written; their fingerprints are the
+/// level revisions.
+const CONTENTS: &str = "contents";
+const LEVEL_SCOPE: &str = "warehouse = ?1 AND row = ?2 AND shelf = ?3 AND level = ?4";
+const INSERT_ZONE: &str = "INSERT INTO zones
+    (warehouse, row, shelf, level, zone, part, base_level, start_zone, last_zone)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)";
+const INSERT_ITEM: &str = "INSERT INTO items
+    (warehouse, row, shelf, level, zone, product_id, lot, serial, expiry_date, status, data)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)";
+
+#[derive(Debug)]
+pub enum ErrorMessage {
+    FileNotFound,
+    MissingParent,
+    MissingItem,
+    UnknownZonePart,
+}
+
+#[derive(Debug)]
+struct BackendError {
+    message: String,
+}
+
+impl Display for ErrorMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Backend error: {}", self.as_str())
+    }
+}
+
+impl ErrorMessage {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            FileNotFound => "Storage file not found",
+            MissingParent => "Layout entry without a parent",
+            MissingItem => "Zone without its item",
+            UnknownZonePart => "Unknown zone part",
+        }
+    }
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for BackendError {}
+
+impl BackendError {
+    pub fn boxed(message: ErrorMessage, details: &str) -> Box<dyn Error> {
+        Box::new(BackendError {
+            message: format!("{} - {}", message, details),
+        })
+    }
+}
+
+impl JsonBackend {
+    pub fn new(path: &str) -> Self {
+        JsonBackend {
+            path: path.to_string(),
+        }
+    }
+}
+
+impl StorageBackend for JsonBackend {
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn load(&mut self) -> Result<Storage, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(&self.path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    fn save(&mut self, storage: &Storage) -> Result<(), Box<dyn Error>> {
+        let json = serde_json::to_string_pretty(storage)?;
+        File::create(&self.path)?.write_all(json.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Key<'_> {
+    fn number(number: usize) -> Self {
+        Key::Integer(number as i64)
+    }
+
+    fn value(&self) -> Value {
+        match self {
+            Key::Integer(integer) => Value::Integer(*integer),
+            Key::Text(text) => Value::Text(text.to_string()),
+        }
+    }
+
+    fn from_values(values: &[Value]) -> Vec<Key<'_>> {
+        values
+            .iter()
+            .map(|value| match value {
+                Value::Integer(integer) => Key::Integer(*integer),
+                Value::Text(text) => Key::Text(text),
+                _ => Key::Text(""),
+            })
+            .collect()
+    }
+
+    fn fingerprint(key: &[Key]) -> u64 {
+        hash_of(&key)
+    }
+}
+
+impl Table {
+    fn upsert(&self) -> String {
+        let columns: Vec<&str> = self.keys.iter().chain(self.columns).copied().collect();
+        let values: Vec<String> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+        format!(
+            "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+            self.name,
+            columns.join(", "),
+            values.join(", ")
+        )
+    }
+
+    fn delete(&self) -> String {
+        let conditions: Vec<String> = self
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| format!("{} = ?{}", key, i + 1))
+            .collect();
+        format!("DELETE FROM {} WHERE {}", self.name, conditions.join(" AND "))
+    }
+
+    /// Writes the rows that differ from `previous` and deletes the ones that
+    /// are gone; without `previous` the table is rewritten from scratch.
+    fn sync(
+        &self,
+        transaction: &Transaction,
+        storage: &Storage,
+        previous: Option<&HashMap<u64, u64>>,
+    ) -> Result<HashMap<u64, u64>, Box<dyn Error>> {
+        if previous.is_none() {
+            transaction.execute(&format!("DELETE FROM {}", self.name), [])?;
+        }
+        let mut upsert = transaction.prepare(&self.upsert())?;
+        let mut fingerprints = HashMap::new();
+        let mut kept = 0;
+        (self.rows)(storage, &mut |record| {
+            let key = Key::fingerprint(record.key);
+            let unchanged = match previous.and_then(|previous| previous.get(&key)) {
+                Some(written) => {
+                    kept += 1;
+                    *written == record.content
+                }
+                None => false,
+            };
+            if !unchanged {
+                let mut values: Vec<Value> = record.key.iter().map(Key::value).collect();
+                values.extend((record.values)()?);
+                upsert.execute(params_from_iter(values))?;
+            }
+            fingerprints.insert(key, record.content);
+            Ok(())
+        })?;
+        if previous.is_some_and(|previous| kept < previous.len()) {
+            self.delete_stale(transaction, &fingerprints)?;
+        }
+        Ok(fingerprints)
+    }
+
+    fn delete_stale(
+        &self,
+        transaction: &Transaction,
+        fingerprints: &HashMap<u64, u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        let select = format!("SELECT {} FROM {}", self.keys.join(", "), self.name);
+        let keys = transaction
+            .prepare(&select)?
+            .query_map([], |row| {
+                (0..self.keys.len()).map(|i| row.get::<_, Value>(i)).collect()
+            })?
+            .collect::<Result<Vec<Vec<Value>>, _>>()?;
+        let mut delete = transaction.prepare(&self.delete())?;
+        for values in keys {
+            if !fingerprints.contains_key(&Key::fingerprint(&Key::from_values(&values))) {
+                delete.execute(params_from_iter(values))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn fingerprints(&self, storage: &Storage) -> Result<HashMap<u64, u64>, Box<dyn Error>> {
+        let mut fingerprints = HashMap::new();
+        (self.rows)(storage, &mut |record| {
+            fingerprints.insert(Key::fingerprint(record.key), record.content);
+            Ok(())
+        })?;
+        Ok(fingerprints)
+    }
+}
+
+impl SqliteBackend {
+    pub fn new(path: &str) -> Self {
+        SqliteBackend {
+            path: path.to_string(),
+            connection: None,
+            written: None,
+        }
+    }
+
+    fn connection(&mut self) -> Result<&mut Connection, Box<dyn Error>> {
+        let connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => {
+                let connection = Connection::open(&self.path)?;
+                connection.execute_batch(SCHEMA)?;
+                connection
+            }
+        };
+        Ok(self.connection.insert(connection))
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn load(&mut self) -> Result<Storage, Box<dyn Error>> {
+        if !Path::new(&self.path).exists() {
+            return Err(BackendError::boxed(FileNotFound, &self.path));
+        }
+        let storage = read_storage(self.connection()?)?;
+        let mut written = HashMap::new();
+        for table in &TABLES {
+            written.insert(table.name, table.fingerprints(&storage)?);
+        }
+        let mut revisions = HashMap::new();
+        each_level(&storage, &mut |key, level| {
+            revisions.insert(Key::fingerprint(key), level.revision());
+            Ok(())
+        })?;
+        written.insert(CONTENTS, revisions);
+        self.written = Some(written);
+        Ok(storage)
+    }
+
+    fn save(&mut self, storage: &Storage) -> Result<(), Box<dyn Error>> {
+        // Forget what was written until the commit succeeds, so a failed save
+        // is followed by a full rewrite rather than a partial one.
+        let previous = self.written.take();
+        let transaction = self.connection()?.transaction()?;
+        let mut written = HashMap::new();
+        for table in &TABLES {
+            let previous = previous.as_ref().and_then(|previous| previous.get(table.name));
+            written.insert(table.name, table.sync(&transaction, storage, previous)?);
+        }
+        let previous = previous.as_ref().and_then(|previous| previous.get(CONTENTS));
+        written.insert(CONTENTS, sync_contents(&transaction, storage, previous)?);
+        transaction.commit()?;
+        self.written = Some(written);
+        Ok(())
+    }
+}
+
+fn hash_of(value: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Ids and counts are stored as SQLite integers; ids above `i64::MAX` wrap
+// around and come back unchanged when read.
+fn integer(value: impl TryInto<u64>) -> Value {
+    Value::Integer(value.try_into().unwrap_or_default() as i64)
+}
+
+fn optional_integer(value: Option<impl TryInto<u64>>) -> Value {
+    value.map_or(Value::Null, integer)
+}
+
+fn text(value: &str) -> Value {
+    Value::Text(value.to_string())
+}
+
+fn optional_text(value: Option<&str>) -> Value {
+    value.map_or(Value::Null, text)
+}
+
+fn json(value: &impl Serialize) -> Result<Value, Box<dyn Error>> {
+    Ok(Value::Text(serde_json::to_string(value)?))
+}
+
+/// Unit variants like strategies as their bare name, anything else as JSON.
+fn label(value: &impl Serialize) -> Result<Value, Box<dyn Error>> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(name) => Ok(Value::Text(name)),
+        value => Ok(Value::Text(value.to_string())),
+    }
+}
+
+fn meta_rows(storage: &Storage, emit: &mut Emit) -> Result<(), Box<dyn Error>> {
+    let entries = [
+        ("name", serde_json::to_string(&storage.name)?),
+        ("file_path", serde_json::to_string(&storage.file_path)?),
+        ("active_warehouse", serde_json::to_string(&storage.active_warehouse)?),
+        ("currency", serde_json::to_string(&storage.currency)?),
+        ("locale", serde_json::to_string(&storage.locale)?),
+        ("quality_markdowns", serde_json::to_string(&storage.list.quality_markdowns)?),
+        ("id_allocator", serde_json::to_string(&storage.list.id_allocator)?),
+        ("write_offs", serde_json::to_string(&storage.write_offs)?),
+        ("suppliers", serde_json::to_string(&storage.suppliers)?),
+        ("purchase_orders", serde_json::to_string(&storage.purchase_orders)?),
+        ("returns", serde_json::to_string(&storage.returns)?),
+        ("journal", serde_json::to_string(&storage.journal)?),
+        ("cost_ledger", serde_json::to_string(&storage.cost_ledger)?),
+    ];
+    for (key, value) in &entries {
+        emit(Record {
+            key: &[Key::Text(key)],
+            content: hash_of(value),
+            values: &|| Ok(vec![text(value)]),
+        })?;
+    }
+    Ok(())
+}
+
+fn product_rows(storage: &Storage, emit: &mut Emit) -> Result<(), Box<dyn Error>> {
+    for product in storage.list.products() {
+        emit(Record {
+            key: &[Key::Integer(product.id as i64)],
+            content: hash_of(product),
+            values: &|| {
+                Ok(vec![
+                    text(product.name()),
+                    integer(product.price),
+                    integer(product.quantity),
+                    label(&product.quality)?,
+                    optional_text(product.category.as_deref()),
+                    optional_text(product.sku.as_deref()),
+                    optional_text(product.barcode.as_deref()),
+                    json(product)?,
+                ])
+            },
+        })?;
+    }
+    Ok(())
+}
+
+fn warehouse_rows(storage: &Storage, emit: &mut Emit) -> Result<(), Box<dyn Error>> {
+    for (name, warehouse) in &storage.warehouses {
+        emit(Record {
+            key: &[Key::Text(name)],
+            content: hash_of(&(warehouse.available_space, &warehouse.strategy)),
+            values: &|| Ok(vec![integer(warehouse.available_space), label(&warehouse.strategy)?]),
+        })?;
+    }
+    Ok(())
+}
+
+fn row_rows(storage: &Storage, emit: &mut Emit) -> Result<(), Box<dyn Error>> {
+    for (name, warehouse) in &storage.warehouses {
+        for row in warehouse.rows() {
+            emit(Record {
+                key: &[Key::Text(name), Key::number(row.number)],
+                content: hash_of(&row.available_space),
+                values: &|| Ok(vec![integer(row.available_space)]),
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn shelf_rows(storage: &Storage, emit: &mut Emit) -> Result<(), Box<dyn Error>> {
+    for (name, warehouse) in &storage.warehouses {
+        for row in warehouse.rows() {
+            for shelf in &row.shelves {
+                let key = [Key::Text(name), Key::number(row.number), Key::number(shelf.number)];
+                emit(Record {
+                    key: &key,
+                    content: hash_of(&(shelf.available_space, shelf.load_rating)),
+                    values: &|| {
+                        Ok(vec![integer(shelf.available_space), optional_integer(shelf.load_rating)])
+                    },
+                })?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn level_rows(storage: &Storage, emit: &mut Emit) -> Result<(), Box<dyn Error>> {
+    each_level(storage, &mut |key, level| {
+        emit(Record {
+            key,
+            content: hash_of(&(level.available_space, level.load_rating)),
+            values: &|| Ok(vec![integer(level.available_space), optional_integer(level.load_rating)]),
+        })
+    })
+}
+
+/// Calls `emit` with the key of every level in the storage and the level itself.
+fn each_level(storage: &Storage, emit: &mut EmitLevel) -> Result<(), Box<dyn Error>> {
+    for (name, warehouse) in &storage.warehouses {
+        for row in warehouse.rows() {
+            for shelf in &row.shelves {
+                for level in &shelf.levels {
+                    let key = [
+                        Key::Text(name),
+                        Key::number(row.number),
+                        Key::number(shelf.number),
+                        Key::number(level.number),
+                    ];
+                    emit(&key, level)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rewrites the zones and items of the levels whose revision differs from
+/// `previous`, and drops those of levels that are gone.
+fn sync_contents(
+    transaction: &Transaction,
+    storage: &Storage,
+    previous: Option<&HashMap<u64, u64>>,
+) -> Result<HashMap<u64, u64>, Box<dyn Error>> {
+    if previous.is_none() {
+        transaction.execute_batch("DELETE FROM zones; DELETE FROM items;")?;
+    }
+    let mut clear_zones = transaction.prepare(&format!("DELETE FROM zones WHERE {}", LEVEL_SCOPE))?;
+    let mut clear_items = transaction.prepare(&format!("DELETE FROM items WHERE {}", LEVEL_SCOPE))?;
+    let mut insert_zone = transaction.prepare(INSERT_ZONE)?;
+    let mut insert_item = transaction.prepare(INSERT_ITEM)?;
+    let mut revisions = HashMap::new();
+    let mut kept = 0;
+    each_level(storage, &mut |key, level| {
+        let fingerprint = Key::fingerprint(key);
+        let written = previous.and_then(|previous| previous.get(&fingerprint));
+        kept += usize::from(written.is_some());
+        if written != Some(&level.revision()) {
+            let scope: Vec<Value> = key.iter().map(Key::value).collect();
+            if written.is_some() {
+                clear_zones.execute(params_from_iter(&scope))?;
+                clear_items.execute(params_from_iter(&scope))?;
+            }
+            for zone in &level.zones {
+                let mut values = scope.clone();
+                values.push(integer(zone.number));
+                insert_zone.execute(params_from_iter(values.iter().chain(&zone_values(zone))))?;
+                if let Some(WholeProduct(item)) | Some(ProductStart(item, _)) = &zone.item {
+                    values.extend(item_values(item)?);
+                    insert_item.execute(params_from_iter(values))?;
+                }
+            }
+        }
+        revisions.insert(fingerprint, level.revision());
+        Ok(())
+    })?;
+    if previous.is_some_and(|previous| kept < previous.len()) {
+        let levels = transaction
+            .prepare("SELECT DISTINCT warehouse, row, shelf, level FROM zones")?
+            .query_map([], |row| (0..4).map(|i| row.get::<_, Value>(i)).collect())?
+            .collect::<Result<Vec<Vec<Value>>, _>>()?;
+        for scope in levels {
+            if !revisions.contains_key(&Key::fingerprint(&Key::from_values(&scope))) {
+                clear_zones.execute(params_from_iter(&scope))?;
+                clear_items.execute(params_from_iter(&scope))?;
+            }
+        }
+    }
+    Ok(revisions)
+}
+
+fn zone_values(zone: &Zone) -> [Value; 4] {
+    let (part, base_level, start_zone, last_zone) = match &zone.item {
+        None => (None, None, None, None),
+        Some(WholeProduct(_)) => (Some("whole"), None, None, None),
+        Some(ProductStart(_, last_zone)) => (Some("start"), None, None, Some(*last_zone)),
+        Some(ProductPart(start_zone, last_zone)) => {
+            (Some("part"), None, Some(*start_zone), Some(*last_zone))
+        }
+        Some(ProductEnd(start_zone)) => (Some("end"), None, Some(*start_zone), None),
+        Some(ProductAbove(base_level, start_zone)) => {
+            (Some("above"), Some(*base_level), Some(*start_zone), None)
+        }
+    };
+    [
+        optional_text(part),
+        optional_integer(base_level),
+        optional_integer(start_zone),
+        optional_integer(last_zone),
+    ]
+}
+
+fn item_values(item: &ProductItem) -> Result<[Value; 6], Box<dyn Error>> {
+    Ok([
+        integer(item.id),
+        optional_text(item.lot.as_deref()),
+        optional_text(item.serial.as_deref()),
+        optional_text(item.expiry_date.map(|date| date.to_string()).as_deref()),
+        text(item.status.name()),
+        json(item)?,
+    ])
+}
+
+type LevelKey = (String, usize, usize, usize);
+type ZoneKey = (String, usize, usize, usize, usize);
+
+fn read_storage(connection: &Connection) -> Result<Storage, Box<dyn Error>> {
+    let meta: HashMap<String, String> = connection
+        .prepare("SELECT key, value FROM meta")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+    let mut list = ProductList::with(read_products(connection)?);
+    list.quality_markdowns = entry(&meta, "quality_markdowns")?;
+    list.id_allocator = entry(&meta, "id_allocator")?;
+
+    let mut storage = Storage::new(entry(&meta, "name")?, Some(entry(&meta, "file_path")?));
+    storage.list = list;
+    storage.warehouses = read_warehouses(connection)?;
+    storage.active_warehouse = entry(&meta, "active_warehouse")?;
+    storage.currency = entry(&meta, "currency")?;
+    storage.locale = entry(&meta, "locale")?;
+    storage.write_offs = entry(&meta, "write_offs")?;
+    storage.suppliers = entry(&meta, "suppliers")?;
+    storage.purchase_orders = entry(&meta, "purchase_orders")?;
+    storage.returns = entry(&meta, "returns")?;
+    storage.journal = entry(&meta, "journal")?;
+    storage.cost_ledger = entry(&meta, "cost_ledger")?;
+    Ok(storage)
+}
+
+/// A `meta` value, or its default when the file predates the entry.
+fn entry<T: DeserializeOwned + Default>(
+    meta: &HashMap<String, String>,
+    key: &str,
+) -> Result<T, Box<dyn Error>> {
+    match meta.get(key) {
+        Some(value) => Ok(serde_json::from_str(value)?),
+        None => Ok(T::default()),
+    }
+}
+
+fn read_products(connection: &Connection) -> Result<HashMap<u64, Product>, Box<dyn Error>> {
+    let mut products = HashMap::new();
+    let mut statement = connection.prepare("SELECT data FROM products")?;
+    for data in statement.query_map([], |row| row.get::<_, String>(0))? {
+        let product: Product = serde_json::from_str(&data?)?;
+        products.insert(product.id, product);
+    }
+    Ok(products)
+}
+
+fn read_warehouses(connection: &Connection) -> Result<BTreeMap<String, Warehouse>, Box<dyn Error>> {
+    let mut items: HashMap<ZoneKey, ProductItem> = HashMap::new();
+    let mut statement =
+        connection.prepare("SELECT warehouse, row, shelf, level, zone, data FROM items")?;
+    let rows = statement.query_map([], |row| {
+        let key = (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?);
+        Ok((key, row.get::<_, String>(5)?))
+    })?;
+    for row in rows {
+        let (key, data) = row?;
+        items.insert(key, serde_json::from_str(&data)?);
+    }
+
+    let mut levels: BTreeMap<LevelKey, Level> = BTreeMap::new();
+    let mut statement = connection.prepare(
+        "SELECT warehouse, row, shelf, level, available_space, load_rating FROM levels",
+    )?;
+    let rows = statement.query_map([], |row| {
+        let mut level = Level::new(row.get(3)?);
+        level.available_space = row.get(4)?;
+        level.load_rating = row.get::<_, Option<i64>>(5)?.map(|rating| rating as u64);
+        Ok(((row.get(0)?, row.get(1)?, row.get(2)?, level.number), level))
+    })?;
+    for row in rows {
+        let (key, level) = row?;
+        levels.insert(key, level);
+    }
+
+    let mut statement = connection.prepare(
+        "SELECT warehouse, row, shelf, level, zone, part, base_level, start_zone, last_zone
+         FROM zones ORDER BY warehouse, row, shelf, level, zone",
+    )?;
+    let rows = statement.query_map([], |row| {
+        let key: ZoneKey = (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?);
+        let part: Option<String> = row.get(5)?;
+        let numbers: [Option<usize>; 3] = [row.get(6)?, row.get(7)?, row.get(8)?];
+        Ok((key, part, numbers))
+    })?;
+    for row in rows {
+        let (key, part, [base_level, start_zone, last_zone]) = row?;
+        let details = format!("{:?}", key);
+        let mut item = || items.remove(&key).ok_or_else(|| BackendError::boxed(MissingItem, &details));
+        let part = match (part.as_deref(), base_level, start_zone, last_zone) {
+            (None, ..) => None,
+            (Some("whole"), ..) => Some(WholeProduct(item()?)),
+            (Some("start"), _, _, Some(last_zone)) => Some(ProductStart(item()?, last_zone)),
+            (Some("part"), _, Some(start_zone), Some(last_zone)) => {
+                Some(ProductPart(start_zone, last_zone))
+            }
+            (Some("end"), _, Some(start_zone), _) => Some(ProductEnd(start_zone)),
+            (Some("above"), Some(base_level), Some(start_zone), _) => {
+                Some(ProductAbove(base_level, start_zone))
+            }
+            _ => return Err(BackendError::boxed(UnknownZonePart, &details)),
+        };
+        let (warehouse, row, shelf, level, zone) = key;
+        match levels.get_mut(&(warehouse, row, shelf, level)) {
+            Some(level) => level.zones.push(Zone::new(zone, part)),
+            None => return Err(BackendError::boxed(MissingParent, &details)),
+        }
+    }
+
+    let mut shelves: BTreeMap<(String, usize, usize), Shelf> = BTreeMap::new();
+    let mut statement = connection
+        .prepare("SELECT warehouse, row, shelf, available_space, load_rating FROM shelves")?;
+    let rows = statement.query_map([], |row| {
+        let mut shelf = Shelf::new(row.get(2)?);
+        shelf.available_space = row.get(3)?;
+        shelf.load_rating = row.get::<_, Option<i64>>(4)?.map(|rating| rating as u64);
+        Ok(((row.get(0)?, row.get(1)?, shelf.number), shelf))
+    })?;
+    for row in rows {
+        let (key, shelf) = row?;
+        shelves.insert(key, shelf);
+    }
+    for ((warehouse, row, shelf, level_number), mut level) in levels {
+        level.reindex();
+        match shelves.get_mut(&(warehouse, row, shelf)) {
+            Some(shelf) => shelf.levels.push(level),
+            None => return Err(BackendError::boxed(MissingParent, &format!("level {}", level_number))),
+        }
+    }
+
+    let mut rows: BTreeMap<(String, usize), Row> = BTreeMap::new();
+    let mut statement = connection.prepare("SELECT warehouse, row, available_space FROM rows")?;
+    let layout = statement.query_map([], |row| {
+        let mut warehouse_row = Row::new(row.get(1)?);
+        warehouse_row.available_space = row.get(2)?;
+        Ok(((row.get(0)?, warehouse_row.number), warehouse_row))
+    })?;
+    for row in layout {
+        let (key, row) = row?;
+        rows.insert(key, row);
+    }
+    for ((warehouse, row, _), shelf) in shelves {
+        match rows.get_mut(&(warehouse, row)) {
+            Some(row) => row.shelves.push(shelf),
+            None => return Err(BackendError::boxed(MissingParent, &format!("shelf {}", shelf.number))),
+        }
+    }
+
+    let mut warehouses: BTreeMap<String, (usize, Warehouse)> = BTreeMap::new();
+    let mut statement =
+        connection.prepare("SELECT name, available_space, strategy FROM warehouses")?;
+    let layout = statement.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?, row.get::<_, String>(2)?))
+    })?;
+    for warehouse in layout {
+        let (name, available_space, strategy) = warehouse?;
+        let strategy: PlacementStrategy =
+            serde_json::from_value(serde_json::Value::String(strategy))?;
+        let warehouse = Warehouse::new().with_strategy(strategy);
+        warehouses.insert(name, (available_space, warehouse));
+    }
+    for ((name, _), row) in rows {
+        match warehouses.get_mut(&name) {
+            Some((_, warehouse)) => warehouse.add_row(row),
+            None => return Err(BackendError::boxed(MissingParent, &format!("row {}", row.number))),
+        }
+    }
+    Ok(warehouses
+        .into_iter()
+        .map(|(name, (available_space, mut warehouse))| {
+            warehouse.available_space = available_space;
+            (name, warehouse)
+        })
+        .collect())
+}
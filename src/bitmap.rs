@@ -0,0 +1,187 @@
+const WORD: usize = 64;
+
+/// Occupancy of a level's zones, one bit per zone (set when occupied), with
+/// the free runs at either end and the longest free run kept up to date so
+/// placement can skip levels without scanning them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZoneBitmap {
+    words: Vec<u64>,
+    len: usize,
+    occupied: usize,
+    leading: usize,
+    trailing: usize,
+    longest: usize,
+}
+
+impl ZoneBitmap {
+    pub fn new(len: usize) -> Self {
+        let mut bitmap = ZoneBitmap {
+            words: vec![0; len.div_ceil(WORD)],
+            len,
+            ..ZoneBitmap::default()
+        };
+        bitmap.summarize();
+        bitmap
+    }
+
+    pub fn from_occupancy(occupancy: impl IntoIterator<Item = bool>) -> Self {
+        let mut bitmap = ZoneBitmap::default();
+        for occupied in occupancy {
+            bitmap.push(occupied);
+        }
+        bitmap.summarize();
+        bitmap
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn occupied(&self) -> usize {
+        self.occupied
+    }
+
+    pub fn free(&self) -> usize {
+        self.len - self.occupied
+    }
+
+    pub fn is_all_free(&self) -> bool {
+        self.occupied == 0
+    }
+
+    pub fn leading_free(&self) -> usize {
+        self.leading
+    }
+
+    pub fn trailing_free(&self) -> usize {
+        self.trailing
+    }
+
+    pub fn longest_free(&self) -> usize {
+        self.longest
+    }
+
+    pub fn is_occupied(&self, index: usize) -> bool {
+        index < self.len && self.words[index / WORD] & (1 << (index % WORD)) != 0
+    }
+
+    /// Appends a zone; call `summarize` once done pushing.
+    pub fn push(&mut self, occupied: bool) {
+        if self.len.is_multiple_of(WORD) {
+            self.words.push(0);
+        }
+        self.len += 1;
+        self.write(self.len - 1, occupied);
+    }
+
+    /// Sets a single bit; call `summarize` once a batch of changes is done.
+    pub fn set(&mut self, index: usize, occupied: bool) {
+        if index < self.len {
+            self.write(index, occupied);
+        }
+    }
+
+    fn write(&mut self, index: usize, occupied: bool) {
+        let (word, mask) = (index / WORD, 1u64 << (index % WORD));
+        match (self.words[word] & mask != 0, occupied) {
+            (false, true) => {
+                self.words[word] |= mask;
+                self.occupied += 1;
+            }
+            (true, false) => {
+                self.words[word] &= !mask;
+                self.occupied -= 1;
+            }
+            _ => (),
+        }
+    }
+
+    pub fn summarize(&mut self) {
+        self.leading = self.next_occupied(0).unwrap_or(self.len);
+        self.trailing = match self.last_occupied() {
+            Some(index) => self.len - index - 1,
+            None => self.len,
+        };
+        self.longest = self.free_runs().map(|(_, len)| len).max().unwrap_or(0);
+    }
+
+    /// First free zone at or after `from`.
+    pub fn next_free(&self, from: usize) -> Option<usize> {
+        self.scan(from, true)
+    }
+
+    /// First occupied zone at or after `from`.
+    pub fn next_occupied(&self, from: usize) -> Option<usize> {
+        self.scan(from, false)
+    }
+
+    fn scan(&self, from: usize, free: bool) -> Option<usize> {
+        if from >= self.len {
+            return None;
+        }
+        let mut word_index = from / WORD;
+        let mut word = self.word(word_index, free) & (u64::MAX << (from % WORD));
+        loop {
+            if word != 0 {
+                let index = word_index * WORD + word.trailing_zeros() as usize;
+                return Some(index).filter(|&index| index < self.len);
+            }
+            word_index += 1;
+            if word_index >= self.words.len() {
+                return None;
+            }
+            word = self.word(word_index, free);
+        }
+    }
+
+    fn word(&self, index: usize, free: bool) -> u64 {
+        match free {
+            true => !self.words[index],
+            false => self.words[index],
+        }
+    }
+
+    pub fn last_occupied(&self) -> Option<usize> {
+        self.words
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, word)| **word != 0)
+            .map(|(index, word)| index * WORD + (WORD - 1 - word.leading_zeros() as usize))
+    }
+
+    pub fn is_range_free(&self, start: usize, len: usize) -> bool {
+        start + len <= self.len
+            && (len == 0 || self.next_occupied(start).is_none_or(|index| index >= start + len))
+    }
+
+    /// First start at or after `from` of `len` consecutive free zones.
+    pub fn find_free_run(&self, from: usize, len: usize) -> Option<usize> {
+        if len > self.longest {
+            return None;
+        }
+        let mut start = self.next_free(from)?;
+        loop {
+            let end = self.next_occupied(start).unwrap_or(self.len);
+            if end - start >= len {
+                return Some(start);
+            }
+            start = self.next_free(end)?;
+        }
+    }
+
+    /// Free intervals as `(start, len)`, in order.
+    pub fn free_runs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut position = 0;
+        std::iter::from_fn(move || {
+            let start = self.next_free(position)?;
+            let end = self.next_occupied(start).unwrap_or(self.len);
+            position = end;
+            Some((start, end - start))
+        })
+    }
+}
@@ -0,0 +1,77 @@
+use crate::currency::{Currency, PriceFormat, Priced};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Formatter},
+};
+
+pub const SEPARATOR: char = '/';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryRollup {
+    pub category: String,
+    pub products: usize,
+    pub quantity: usize,
+    /// Stock value, kept apart by the currency products are priced in.
+    pub value: BTreeMap<Currency, u64>,
+    pub zones: usize,
+}
+
+impl Priced for CategoryRollup {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        let depth = self.category.matches(SEPARATOR).count();
+        let name = self.category.rsplit(SEPARATOR).next().unwrap_or_default();
+        write!(
+            f,
+            "{}{}: {} products, {} units, value {}, {} zones",
+            "  ".repeat(depth),
+            name,
+            self.products,
+            self.quantity,
+            format.format_totals(&self.value),
+            self.zones
+        )
+    }
+}
+
+impl CategoryRollup {
+    pub fn new(category: &str) -> Self {
+        CategoryRollup {
+            category: category.to_string(),
+            products: 0,
+            quantity: 0,
+            value: BTreeMap::new(),
+            zones: 0,
+        }
+    }
+}
+
+/// Trims every segment of a `/`-separated path and drops empty ones, so
+/// ` Produce // Fruit/` becomes `Produce/Fruit`.
+pub fn normalize(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path
+        .split(SEPARATOR)
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match segments.is_empty() {
+        true => None,
+        false => Some(segments.join(&SEPARATOR.to_string())),
+    }
+}
+
+/// Whether `path` is `parent` or lies below it, ignoring case.
+pub fn is_within(path: &str, parent: &str) -> bool {
+    let (path, parent) = (path.to_lowercase(), parent.to_lowercase());
+    match path.strip_prefix(&parent) {
+        Some(rest) => rest.is_empty() || rest.starts_with(SEPARATOR),
+        None => false,
+    }
+}
+
+/// Every prefix of a path, from the root down: `A`, `A/B`, `A/B/C`.
+pub fn ancestors(path: &str) -> Vec<String> {
+    path.match_indices(SEPARATOR)
+        .map(|(index, _)| path[..index].to_string())
+        .chain(std::iter::once(path.to_string()))
+        .collect()
+}
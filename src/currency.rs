@@ -0,0 +1,309 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+use ErrorMessage::*;
+
+const CURRENCIES: [(&str, &str, u32); 12] = [
+    ("USD", "$", 2),
+    ("EUR", "€", 2),
+    ("GBP", "£", 2),
+    ("BRL", "R$", 2),
+    ("CAD", "CA$", 2),
+    ("CHF", "CHF", 2),
+    ("JPY", "¥", 0),
+    ("KRW", "₩", 0),
+    ("CLP", "CLP$", 0),
+    ("KWD", "KD", 3),
+    ("BHD", "BD", 3),
+    ("TND", "DT", 3),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Currency {
+    pub code: &'static str,
+    pub symbol: &'static str,
+    pub minor_units: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Locale {
+    #[default]
+    #[serde(rename = "en-US")]
+    EnUs,
+    #[serde(rename = "pt-BR")]
+    PtBr,
+    #[serde(rename = "de-DE")]
+    DeDe,
+    #[serde(rename = "fr-FR")]
+    FrFr,
+}
+
+/// The storage's currency and locale, handed to whatever shows or reads prices.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PriceFormat {
+    pub currency: Currency,
+    pub locale: Locale,
+}
+
+/// A value whose text shows prices, and so needs the storage's format.
+pub trait Priced {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result;
+
+    fn priced<'a>(&'a self, format: &'a PriceFormat) -> WithFormat<'a, Self> {
+        WithFormat { value: self, format }
+    }
+}
+
+pub struct WithFormat<'a, T: ?Sized> {
+    value: &'a T,
+    format: &'a PriceFormat,
+}
+
+#[derive(Debug)]
+pub enum ErrorMessage {
+    UnknownCurrency,
+    UnknownLocale,
+    InvalidAmount,
+}
+
+#[derive(Debug)]
+pub(crate) struct CurrencyError {
+    message: String,
+}
+
+impl ErrorMessage {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            UnknownCurrency => "Unknown currency code",
+            UnknownLocale => "Unknown locale",
+            InvalidAmount => "Invalid amount for currency",
+        }
+    }
+}
+
+impl Display for ErrorMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Display for CurrencyError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Currency Error: {}", self.message)
+    }
+}
+
+impl Error for CurrencyError {}
+
+impl CurrencyError {
+    pub fn boxed(message: String) -> Box<dyn Error> {
+        Box::new(CurrencyError { message })
+    }
+
+    pub fn with_value(message: ErrorMessage, value: &str) -> Box<dyn Error> {
+        CurrencyError::boxed(format!("{} - {}", message, value))
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency {
+            code: "USD",
+            symbol: "$",
+            minor_units: 2,
+        }
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{} ({}, {} decimals)", self.code, self.symbol, self.minor_units)
+    }
+}
+
+impl<T: Priced + ?Sized> Display for WithFormat<'_, T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.value.fmt_priced(f, self.format)
+    }
+}
+
+// Stored by code, so an unknown code fails the load instead of surfacing later.
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Currency::from_code(&code).map_err(de::Error::custom)
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.tag())
+    }
+}
+
+impl Currency {
+    pub fn from_code(code: &str) -> Result<Self, Box<dyn Error>> {
+        let code = code.trim().to_uppercase();
+        match CURRENCIES.iter().find(|(known, _, _)| *known == code) {
+            Some((code, symbol, minor_units)) => Ok(Currency {
+                code,
+                symbol,
+                minor_units: *minor_units,
+            }),
+            None => Err(CurrencyError::with_value(UnknownCurrency, &code)),
+        }
+    }
+
+    pub fn format(&self, amount: u64, locale: Locale) -> String {
+        let (group, decimal) = locale.separators();
+        let scale = 10u64.pow(self.minor_units);
+        let digits = (amount / scale).to_string();
+        let mut number = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                number.push(group);
+            }
+            number.push(digit);
+        }
+        if self.minor_units > 0 {
+            let width = self.minor_units as usize;
+            number = format!("{}{}{:0width$}", number, decimal, amount % scale);
+        }
+        match locale {
+            Locale::EnUs => format!("{}{}", self.symbol, number),
+            Locale::PtBr => format!("{} {}", self.symbol, number),
+            Locale::DeDe | Locale::FrFr => format!("{} {}", number, self.symbol),
+        }
+    }
+
+    /// Parses an amount in major units into minor units. The locale's decimal
+    /// separator always wins; otherwise a single `.` or `,` followed by no more
+    /// digits than the currency allows is read as the decimal point.
+    pub fn parse(&self, input: &str, locale: Locale) -> Result<u64, Box<dyn Error>> {
+        let invalid = || CurrencyError::with_value(InvalidAmount, input);
+        let (_, decimal) = locale.separators();
+        let cleaned: String = input
+            .replace(self.symbol, "")
+            .replace(self.code, "")
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        if cleaned.is_empty() || cleaned.chars().any(|c| !c.is_ascii_digit() && c != '.' && c != ',') {
+            return Err(invalid());
+        }
+
+        let other = if decimal == '.' { ',' } else { '.' };
+        let separator = if cleaned.contains(decimal) {
+            Some(decimal)
+        } else {
+            match cleaned.split(other).collect::<Vec<_>>()[..] {
+                [_, fraction] if !fraction.is_empty()
+                    && fraction.len() <= self.minor_units as usize
+                    && fraction.len() != 3 =>
+                {
+                    Some(other)
+                }
+                _ => None,
+            }
+        };
+        let (integer, fraction) = match separator.and_then(|s| cleaned.rsplit_once(s)) {
+            Some((integer, fraction)) => (integer, fraction),
+            None => (cleaned.as_str(), ""),
+        };
+        let integer: String = integer.chars().filter(|c| c.is_ascii_digit()).collect();
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > self.minor_units as usize || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let scale = 10u64.pow(self.minor_units);
+        let major = match integer.as_str() {
+            "" => 0,
+            digits => digits.parse::<u64>().map_err(|_| invalid())?,
+        };
+        let width = self.minor_units as usize;
+        let minor = format!("{:0<width$}", fraction).parse::<u64>().unwrap_or(0);
+        major
+            .checked_mul(scale)
+            .and_then(|amount| amount.checked_add(minor))
+            .ok_or_else(invalid)
+    }
+}
+
+impl Locale {
+    pub fn from_tag(tag: &str) -> Result<Self, Box<dyn Error>> {
+        match tag.trim().replace('_', "-").to_lowercase().as_str() {
+            "en-us" | "en" => Ok(Locale::EnUs),
+            "pt-br" | "pt" => Ok(Locale::PtBr),
+            "de-de" | "de" => Ok(Locale::DeDe),
+            "fr-fr" | "fr" => Ok(Locale::FrFr),
+            _ => Err(CurrencyError::with_value(UnknownLocale, tag)),
+        }
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::EnUs => "en-US",
+            Locale::PtBr => "pt-BR",
+            Locale::DeDe => "de-DE",
+            Locale::FrFr => "fr-FR",
+        }
+    }
+
+    pub fn separators(&self) -> (char, char) {
+        match self {
+            Locale::EnUs => (',', '.'),
+            Locale::PtBr | Locale::DeDe => ('.', ','),
+            Locale::FrFr => (' ', ','),
+        }
+    }
+}
+
+impl PriceFormat {
+    pub fn new(currency: Currency, locale: Locale) -> Self {
+        PriceFormat { currency, locale }
+    }
+
+    /// The same format in a product's own currency, when it has one.
+    pub fn with_currency(&self, currency: Option<&Currency>) -> PriceFormat {
+        PriceFormat::new(currency.unwrap_or(&self.currency).clone(), self.locale)
+    }
+
+    pub fn format(&self, price: u64) -> String {
+        self.currency.format(price, self.locale)
+    }
+
+    /// Formats a price in a product's own currency, when it has one.
+    pub fn format_in(&self, price: u64, currency: Option<&Currency>) -> String {
+        currency.unwrap_or(&self.currency).format(price, self.locale)
+    }
+
+    /// Formats amounts kept apart by currency, as `¥500 + $1.00`.
+    pub fn format_totals(&self, totals: &BTreeMap<Currency, u64>) -> String {
+        match totals.is_empty() {
+            true => self.format(0),
+            false => totals
+                .iter()
+                .map(|(currency, amount)| currency.format(*amount, self.locale))
+                .collect::<Vec<String>>()
+                .join(" + "),
+        }
+    }
+
+    pub fn parse(&self, input: &str) -> Result<u64, Box<dyn Error>> {
+        self.currency.parse(input, self.locale)
+    }
+
+    pub fn parse_in(&self, input: &str, currency: Option<&Currency>) -> Result<u64, Box<dyn Error>> {
+        currency.unwrap_or(&self.currency).parse(input, self.locale)
+    }
+}
@@ -1,31 +1,176 @@
 use crate::{
-    product::{Product, ProductList, Quality},
+    backend::{self, StorageBackend},
+    catalogue::{self, CategoryRollup},
+    currency::{Currency, Locale, PriceFormat, Priced},
+    product::{
+        Delivery, IdAllocator, ItemStatus, MarkdownRule, Product, ProductItem, ProductList,
+        Quality,
+    },
+    purchasing::{
+        self, ErrorMessage::*, PurchaseOrder, PurchaseOrderLine, PurchasingError, Supplier,
+    },
+    journal::{Movement, MovementKind},
+    query::Query,
+    returns::{ErrorMessage::ReturnNotFound, InspectionOutcome, ReturnAuthorization, ReturnError},
+    valuation::{CostEntry, CostMethod, ProductValuation, ValuationReport},
     warehouse::Warehouse,
 };
 use chrono::NaiveDate;
 use log::info;
 use serde::{Deserialize, Serialize};
-use serde_json;
 use std::{
+    collections::{BTreeMap, BTreeSet},
     error::Error,
     fmt::{self, Display, Formatter},
-    fs::File,
-    io::{self, BufReader, Write},
 };
 use ErrorMessage::*;
 
+pub const DEFAULT_WAREHOUSE: &str = "main";
+const UNCATEGORIZED: &str = "Uncategorized";
+const SUGGESTIONS: usize = 3;
+const QUALITY_CLASSES: [&str; 4] = ["normal", "fragile", "oversized", "oversized and fragile"];
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "StorageData")]
 pub struct Storage {
     pub name: String,
     pub list: ProductList,
     pub file_path: String,
-    pub warehouse: Warehouse,
+    pub warehouses: BTreeMap<String, Warehouse>,
+    pub active_warehouse: String,
+    pub write_offs: Vec<WriteOff>,
+    pub suppliers: BTreeMap<u64, Supplier>,
+    pub purchase_orders: BTreeMap<u64, PurchaseOrder>,
+    pub returns: BTreeMap<u64, ReturnAuthorization>,
+    pub journal: Vec<Movement>,
+    pub cost_ledger: BTreeMap<u64, Vec<CostEntry>>,
+    pub currency: Currency,
+    pub locale: Locale,
+    #[serde(skip)]
+    pub(crate) backend: Option<Box<dyn StorageBackend>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WriteOff {
+    pub date: NaiveDate,
+    pub product_id: u64,
+    pub product_name: String,
+    pub lot: Option<String>,
+    pub quantity: usize,
+    pub value: u64,
+    /// Currency of the value, when the product has its own.
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replenishment {
+    pub product_id: u64,
+    pub product_name: String,
+    pub on_hand: usize,
+    pub on_order: usize,
+    pub reorder_point: usize,
+    pub suggested: usize,
+    pub capacity: usize,
+}
+
+#[derive(Deserialize)]
+struct StorageData {
+    name: String,
+    list: ProductList,
+    file_path: String,
+    #[serde(default)]
+    warehouse: Option<Warehouse>,
+    #[serde(default)]
+    warehouses: BTreeMap<String, Warehouse>,
+    #[serde(default)]
+    active_warehouse: Option<String>,
+    #[serde(default)]
+    write_offs: Vec<WriteOff>,
+    #[serde(default)]
+    suppliers: BTreeMap<u64, Supplier>,
+    #[serde(default)]
+    purchase_orders: BTreeMap<u64, PurchaseOrder>,
+    #[serde(default)]
+    returns: BTreeMap<u64, ReturnAuthorization>,
+    #[serde(default)]
+    journal: Vec<Movement>,
+    #[serde(default)]
+    cost_ledger: BTreeMap<u64, Vec<CostEntry>>,
+    #[serde(default)]
+    currency: Currency,
+    #[serde(default)]
+    locale: Locale,
+}
+
+impl From<StorageData> for Storage {
+    fn from(data: StorageData) -> Self {
+        let mut warehouses = data.warehouses;
+        if let Some(warehouse) = data.warehouse {
+            warehouses.insert(DEFAULT_WAREHOUSE.to_string(), warehouse);
+        }
+        let active_warehouse = match data.active_warehouse {
+            Some(name) if warehouses.contains_key(&name) => name,
+            _ => match warehouses.keys().next() {
+                Some(name) => name.clone(),
+                None => {
+                    warehouses.insert(DEFAULT_WAREHOUSE.to_string(), Warehouse::new());
+                    DEFAULT_WAREHOUSE.to_string()
+                }
+            },
+        };
+        Storage {
+            name: data.name,
+            list: data.list,
+            file_path: data.file_path,
+            warehouses,
+            active_warehouse,
+            write_offs: data.write_offs,
+            suppliers: data.suppliers,
+            purchase_orders: data.purchase_orders,
+            returns: data.returns,
+            journal: data.journal,
+            cost_ledger: data.cost_ledger,
+            currency: data.currency,
+            locale: data.locale,
+            backend: None,
+        }
+    }
+}
+
+impl Priced for WriteOff {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} units of {} (ID {}), Lot: {}, Value: {} - {}",
+            self.date,
+            self.quantity,
+            self.product_name,
+            self.product_id,
+            self.lot.as_deref().unwrap_or("N/A"),
+            format.format_in(self.value, self.currency.as_ref()),
+            self.reason
+        )
+    }
 }
 
 #[derive(Debug)]
 pub enum ErrorMessage {
     ProductNotFound,
     HasStock,
+    WarehouseNotFound,
+    WarehouseExists,
+    WarehouseActive,
+    SameWarehouse,
+    LotNotFound,
+    SerialNotFound,
+    UnknownQuality,
+    SkuNotFound,
+    BarcodeNotFound,
+    InsufficientSpace,
+    InsufficientQuantity,
+    ForeignCurrency,
 }
 
 #[derive(Debug)]
@@ -45,6 +190,18 @@ impl ErrorMessage {
         match self {
             ProductNotFound => "Product Not Found",
             HasStock => "Product has stock",
+            WarehouseNotFound => "Warehouse Not Found",
+            WarehouseExists => "Warehouse with this name already exists",
+            WarehouseActive => "Warehouse is in use",
+            SameWarehouse => "Source and destination warehouses are the same",
+            LotNotFound => "No items found for lot",
+            SerialNotFound => "No item found with serial",
+            UnknownQuality => "Unknown quality class",
+            SkuNotFound => "No product with this SKU",
+            BarcodeNotFound => "No product with this barcode",
+            InsufficientSpace => "Not enough free space",
+            InsufficientQuantity => "Not enough quantity on the product list",
+            ForeignCurrency => "Product is priced in another currency than the storage",
         }
     }
 }
@@ -67,6 +224,10 @@ impl StorageError {
     pub fn list(message: ErrorMessage) -> Box<dyn Error> {
         StorageError::boxed(format!("List error: {}", message))
     }
+
+    pub fn warehouse(message: ErrorMessage, name: &str) -> Box<dyn Error> {
+        StorageError::boxed(format!("Warehouse error: {} - {}", message, name))
+    }
 }
 
 #[allow(dead_code)]
@@ -76,147 +237,488 @@ impl Storage {
         Storage {
             name,
             list: ProductList::new(),
-            warehouse: Warehouse::new(),
+            warehouses: BTreeMap::from([(DEFAULT_WAREHOUSE.to_string(), Warehouse::new())]),
+            active_warehouse: DEFAULT_WAREHOUSE.to_string(),
             file_path: file_path.unwrap_or(default_path),
+            write_offs: Vec::new(),
+            suppliers: BTreeMap::new(),
+            purchase_orders: BTreeMap::new(),
+            returns: BTreeMap::new(),
+            journal: Vec::new(),
+            cost_ledger: BTreeMap::new(),
+            currency: Currency::default(),
+            locale: Locale::default(),
+            backend: None,
         }
     }
 
-    pub fn save(&self) -> io::Result<()> {
-        match File::create(&self.file_path) {
-            Ok(mut file) => match serde_json::to_string_pretty(self) {
-                Ok(json) => file.write_all(json.as_bytes()),
-                Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
-            },
-            Err(e) => Err(e),
+    pub fn set_currency(&mut self, code: &str) -> Result<(), Box<dyn Error>> {
+        self.currency = Currency::from_code(code)?;
+        Ok(())
+    }
+
+    pub fn set_locale(&mut self, tag: &str) -> Result<(), Box<dyn Error>> {
+        self.locale = Locale::from_tag(tag)?;
+        Ok(())
+    }
+
+    pub fn price_format(&self) -> PriceFormat {
+        PriceFormat::new(self.currency.clone(), self.locale)
+    }
+
+    pub fn product_currency(&self, id: u64) -> Option<&Currency> {
+        self.list.product(id).and_then(|product| product.currency.as_ref())
+    }
+
+    pub fn change_currency(&mut self, id: u64, code: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let code = match code {
+            Some(code) => Some(Currency::from_code(code)?),
+            None => None,
+        };
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.currency = code;
+                info!("Currency of product {} changed", id);
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
         }
     }
 
-    pub fn save_as(&self, file_path: &str) -> io::Result<()> {
-        match File::create(file_path) {
-            Ok(mut file) => match serde_json::to_string_pretty(self) {
-                Ok(json) => file.write_all(json.as_bytes()),
-                Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
-            },
-            Err(e) => Err(e),
+    pub fn print_currency(&self) {
+        println!("Currency: {}, Locale: {}", self.currency, self.locale);
+    }
+
+    pub fn warehouse(&self) -> &Warehouse {
+        &self.warehouses[&self.active_warehouse]
+    }
+
+    pub fn warehouse_mut(&mut self) -> &mut Warehouse {
+        self.warehouses.get_mut(&self.active_warehouse).unwrap()
+    }
+
+    fn site<'a>(
+        warehouses: &'a mut BTreeMap<String, Warehouse>,
+        name: &str,
+    ) -> Result<&'a mut Warehouse, Box<dyn Error>> {
+        match warehouses.get_mut(name) {
+            Some(warehouse) => Ok(warehouse),
+            None => Err(StorageError::warehouse(WarehouseNotFound, name)),
+        }
+    }
+
+    pub fn add_warehouse(&mut self, name: &str, warehouse: Warehouse) -> Result<(), Box<dyn Error>> {
+        if self.warehouses.contains_key(name) {
+            return Err(StorageError::warehouse(WarehouseExists, name));
+        }
+        self.warehouses.insert(name.to_string(), warehouse);
+        info!("Warehouse {} added", name);
+        Ok(())
+    }
+
+    pub fn remove_warehouse(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        match self.warehouses.get(name) {
+            Some(_) if name == self.active_warehouse => {
+                Err(StorageError::warehouse(WarehouseActive, name))
+            }
+            Some(warehouse) if !warehouse.is_empty() => {
+                Err(StorageError::warehouse(HasStock, name))
+            }
+            Some(_) => {
+                self.warehouses.remove(name);
+                info!("Warehouse {} removed", name);
+                Ok(())
+            }
+            None => Err(StorageError::warehouse(WarehouseNotFound, name)),
         }
     }
 
+    pub fn use_warehouse(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        if self.warehouses.contains_key(name) {
+            self.active_warehouse = name.to_string();
+            Ok(())
+        } else {
+            Err(StorageError::warehouse(WarehouseNotFound, name))
+        }
+    }
+
+    pub fn list_warehouses(&self) {
+        for (name, warehouse) in &self.warehouses {
+            let marker = if *name == self.active_warehouse { "*" } else { " " };
+            println!(
+                "{} {}: {}/{} zones occupied, {} items",
+                marker,
+                name,
+                warehouse.occupied_zones(),
+                warehouse.check_capacity(),
+                warehouse.items().len()
+            );
+        }
+    }
+
+    pub fn product_quantities(&self, id: u64) -> BTreeMap<String, usize> {
+        self.warehouses
+            .iter()
+            .map(|(name, warehouse)| (name.clone(), warehouse.items_with_id(id).len()))
+            .collect()
+    }
+
+    pub fn print_product_quantities(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        match self.list.product(id) {
+            Some(product) => {
+                println!("{}", product.priced(&self.price_format()));
+                for (name, quantity) in self.product_quantities(id) {
+                    println!("  {}: {}", name, quantity);
+                }
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
+        }
+    }
+
+    pub fn move_item(
+        &mut self,
+        from: (usize, usize, usize, usize),
+        to: (usize, usize, usize, usize),
+    ) -> Result<(), Box<dyn Error>> {
+        Storage::site(&mut self.warehouses, &self.active_warehouse)?.move_item(&self.list, from, to)
+    }
+
+    pub fn transfer_stock(
+        &mut self,
+        id: u64,
+        quantity: usize,
+        from: &str,
+        to: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        if from == to {
+            return Err(StorageError::warehouse(SameWarehouse, from));
+        }
+        let product = match self.list.product(id) {
+            Some(product) => product,
+            None => return Err(StorageError::list(ProductNotFound)),
+        };
+        if Storage::site(&mut self.warehouses, to)?.capacity_for(product) < quantity {
+            return Err(StorageError::warehouse(InsufficientSpace, to));
+        }
+        let units = Storage::site(&mut self.warehouses, from)?.pick_stock(id, quantity)?;
+        let mut delivery = Delivery::of_units(units.clone());
+        let destination = Storage::site(&mut self.warehouses, to)?;
+        let existing: BTreeSet<_> = destination.find_all_item_occurences(id).into_iter().collect();
+        match destination.restock(id, quantity, &mut self.list, &mut delivery) {
+            Ok(_) => {
+                info!("Transferred {} units of {} from {} to {}", quantity, id, from, to);
+                Ok(())
+            }
+            Err(e) => {
+                // Free space can still fall short of a contiguous run or a load
+                // rating, so undo the move and leave the units where they were.
+                destination.undo_restock(id, &existing)?;
+                Storage::site(&mut self.warehouses, from)?.put_back(units)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Saves through the backend the storage was loaded with, as long as it
+    /// still points at `file_path`.
+    pub fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut backend = match self.backend.take() {
+            Some(backend) if backend.path() == self.file_path => backend,
+            _ => backend::for_path(&self.file_path),
+        };
+        let result = backend.save(self);
+        self.backend = Some(backend);
+        result
+    }
+
+    pub fn save_as(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        backend::for_path(file_path).save(self)
+    }
+
     pub fn load<'a>(
         file_path: &str,
         storage: &'a mut Storage,
     ) -> Result<&'a mut Storage, Box<dyn Error>> {
-        let path = file_path;
-        match File::open(path) {
-            Ok(file) => {
-                let reader = BufReader::new(file);
-                match serde_json::from_reader::<BufReader<File>, Storage>(reader) {
-                    Ok(new_storage) => {
-                        storage.name = new_storage.name;
-                        storage.list = new_storage.list;
-                        storage.warehouse = new_storage.warehouse;
-                        storage.file_path = new_storage.file_path;
-
-                        Ok(storage)
-                    }
-                    Err(e) => Err(Box::new(e)),
-                }
+        let mut backend = backend::for_path(file_path);
+        match backend.load() {
+            Ok(new_storage) => {
+                storage.name = new_storage.name;
+                storage.list = new_storage.list;
+                storage.warehouses = new_storage.warehouses;
+                storage.active_warehouse = new_storage.active_warehouse;
+                storage.write_offs = new_storage.write_offs;
+                storage.suppliers = new_storage.suppliers;
+                storage.purchase_orders = new_storage.purchase_orders;
+                storage.returns = new_storage.returns;
+                storage.journal = new_storage.journal;
+                storage.cost_ledger = new_storage.cost_ledger;
+                storage.currency = new_storage.currency;
+                storage.locale = new_storage.locale;
+                storage.file_path = file_path.to_string();
+                storage.backend = Some(backend);
+                storage.apply_scheduled_prices(today());
+
+                Ok(storage)
             }
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(e),
         }
     }
 
     pub fn check_capacity(&self) -> usize {
-        self.warehouse.check_capacity()
+        self.warehouse().check_capacity()
     }
 
     pub fn check_available_space(&self) -> usize {
-        self.warehouse.available_space
+        self.warehouse().available_space
     }
 
-    pub fn list_products(&self) {
-        for product in self.list.products.values() {
-            println!("{}", product);
+    pub fn capacity_report(&self) {
+        self.warehouse().print_capacity_report();
+    }
+
+    pub fn set_load_rating(
+        &mut self,
+        row: usize,
+        shelf: usize,
+        level: Option<usize>,
+        load_rating: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        match level {
+            Some(level) => self
+                .warehouse_mut()
+                .set_level_load_rating(row, shelf, level, load_rating),
+            None => self.warehouse_mut().set_shelf_load_rating(row, shelf, load_rating),
         }
     }
 
+    pub fn list_products(&self) {
+        self.list.list(&self.price_format());
+    }
+
     pub fn search_product_name(&self, name: &str) {
-        self.list.search_by_name(name);
+        let format = self.price_format();
+        self.list.search_by_name(name).iter().for_each(|product| {
+            println!("{}", product.priced(&format));
+        });
     }
 
     pub fn list_items(&self) {
-        self.warehouse.print_items();
+        self.warehouse().print_items();
     }
 
     pub fn list_items_with_id(&self, id: u64) {
-        self.warehouse.print_items_with_id(id);
+        self.warehouse().print_items_with_id(id);
     }
 
     pub fn list_items_with_name(&self, name: &str) {
-        self.warehouse.print_items_with_name(name, &self.list);
+        self.warehouse().print_items_with_name(name, &self.list);
     }
 
     pub fn list_expiring_items(&self, days: u64) {
         let date = chrono::Local::now().naive_local().date();
         let expiring_date = date + chrono::Duration::days(days as i64);
-        self.warehouse.print_expiring_items(&self.list, expiring_date);
+        self.warehouse().print_expiring_items(&self.list, expiring_date);
     }
 
     pub fn list_expiring_with_id(&self, id: u64, days: u64) {
         let date = chrono::Local::now().naive_local().date();
         let expiring_date = date + chrono::Duration::days(days as i64);
-        self.warehouse.print_expiring_with_id(id, &self.list, expiring_date);
+        self.warehouse().print_expiring_with_id(id, &self.list, expiring_date);
     }
 
     pub fn list_expiring_with_name(&self, name: &str, days: u64) {
         let date = chrono::Local::now().naive_local().date();
         let expiring_date = date + chrono::Duration::days(days as i64);
-        self.warehouse
+        self.warehouse()
             .print_expiring_with_name(name, &self.list, expiring_date);
     }
 
     pub fn list_expired_items(&self) {
         let date = chrono::Local::now().naive_local().date();
-        self.warehouse.print_expired_items(&self.list, date);
+        self.warehouse().print_expired_items(&self.list, date);
     }
 
     pub fn list_expired_with_id(&self, id: u64) {
         let date = chrono::Local::now().naive_local().date();
-        self.warehouse.print_expired_with_id(id, &self.list, date);
+        self.warehouse().print_expired_with_id(id, &self.list, date);
     }
 
     pub fn list_expired_with_name(&self, name: &str) {
         let date = chrono::Local::now().naive_local().date();
-        self.warehouse.print_expired_with_name(name, &self.list, date);
+        self.warehouse().print_expired_with_name(name, &self.list, date);
     }
 
     pub fn list_with_max_price(&self, price: u64) {
+        let format = self.price_format();
         self.list.filter_by_max_price(price).iter().for_each(|product| {
-            println!("{}", product);
+            println!("{}", product.priced(&format));
         });
     }
 
     pub fn list_with_min_price(&self, price: u64) {
+        let format = self.price_format();
         self.list.filter_by_min_price(price).iter().for_each(|product| {
-            println!("{}", product);
+            println!("{}", product.priced(&format));
         });
     }
 
     pub fn list_with_quality(&self, quality: String) {
+        let format = self.price_format();
         self.list.filter_by_quality(quality).iter().for_each(|product| {
-            println!("{}", product);
+            println!("{}", product.priced(&format));
         });
     }
 
+    pub fn print_search(&self, name: &str, limit: usize) -> Result<(), Box<dyn Error>> {
+        let results = self.list.search(name);
+        if results.is_empty() {
+            return Err(self.product_not_found(name));
+        }
+        let format = self.price_format();
+        results.iter().take(limit).for_each(|(product, score)| {
+            println!("[{}] {}", score, product.priced(&format));
+        });
+        Ok(())
+    }
+
+    pub fn query_products(&self, query: &Query) -> Result<Vec<&Product>, Box<dyn Error>> {
+        query.products(&self.list, &self.price_format())
+    }
+
+    pub fn query_items(&self, query: &Query) -> Vec<ProductItem> {
+        query.items(&self.list, self.warehouse().items(), &self.price_format())
+    }
+
+    pub fn print_product_query(&self, query: &Query) -> Result<(), Box<dyn Error>> {
+        let format = self.price_format();
+        self.query_products(query)?.iter().for_each(|product| {
+            println!("{}", product.priced(&format));
+        });
+        Ok(())
+    }
+
+    pub fn print_item_query(&self, query: &Query) {
+        println!("Listing items on warehouse matching {}", query.filter);
+        Warehouse::print_item_list(&self.query_items(query));
+        println!();
+    }
+
+    pub fn change_name(&mut self, id: u64, name: &str) -> Result<(), Box<dyn Error>> {
+        self.list.rename(id, name)
+    }
+
+    pub fn change_category(&mut self, id: u64, category: Option<&str>) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.set_category(category);
+                info!("Category of product {} changed", id);
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
+        }
+    }
+
+    pub fn change_tags(
+        &mut self,
+        id: u64,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.remove_tags(remove);
+                product.add_tags(add);
+                info!("Tags of product {} changed", id);
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
+        }
+    }
+
+    /// Quantity, stock value and occupied zones for every category and each
+    /// of its ancestors, across all warehouses.
+    pub fn category_rollups(&self) -> Vec<CategoryRollup> {
+        let mut zones: BTreeMap<u64, usize> = BTreeMap::new();
+        for item in self.warehouses.values().flat_map(|warehouse| warehouse.items()) {
+            *zones.entry(item.id).or_default() += item.zones_required * item.levels_required;
+        }
+        let mut rollups: BTreeMap<String, CategoryRollup> = BTreeMap::new();
+        for product in self.list.products() {
+            let category = product.category.as_deref().unwrap_or(UNCATEGORIZED);
+            for path in catalogue::ancestors(category) {
+                let rollup = rollups
+                    .entry(path.to_lowercase())
+                    .or_insert_with(|| CategoryRollup::new(&path));
+                rollup.products += 1;
+                rollup.quantity += product.quantity;
+                let currency = product.currency.clone().unwrap_or(self.currency.clone());
+                *rollup.value.entry(currency).or_insert(0) += product.price * product.quantity as u64;
+                rollup.zones += zones.get(&product.id).copied().unwrap_or(0);
+            }
+        }
+        rollups.into_values().collect()
+    }
+
+    pub fn print_category_rollups(&self, category: Option<&str>) {
+        let category = category.and_then(catalogue::normalize);
+        let rollups: Vec<CategoryRollup> = self
+            .category_rollups()
+            .into_iter()
+            .filter(|rollup| {
+                category
+                    .as_deref()
+                    .is_none_or(|category| catalogue::is_within(&rollup.category, category))
+            })
+            .collect();
+        if rollups.is_empty() {
+            println!("No products in this category");
+        }
+        let format = self.price_format();
+        rollups.iter().for_each(|rollup| println!("{}", rollup.priced(&format)));
+    }
+
     pub fn new_product(
         &mut self,
         name: String,
         price: u64,
         quality: Quality,
-    ) -> Result<(), Box<dyn Error>> {
-        let product = Product::new(&name, price, 0, quality);
-        match self.list.add(product) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
+        weight: Option<u64>,
+    ) -> Result<u64, Box<dyn Error>> {
+        let mut product = Product::new(&name, price, 0, quality);
+        product.set_weight(weight);
+        self.add_product(product)
+    }
+
+    pub fn add_product(&mut self, product: Product) -> Result<u64, Box<dyn Error>> {
+        self.list.add(product)
+    }
+
+    pub fn add_product_with_id(&mut self, product: Product) -> Result<u64, Box<dyn Error>> {
+        self.list.add_with_id(product)
+    }
+
+    pub fn set_id_allocator(&mut self, id_allocator: IdAllocator) {
+        info!("Product ids are now allocated by {}", id_allocator);
+        self.list.set_id_allocator(id_allocator);
+    }
+
+    /// A `ProductNotFound` error that suggests the closest product names.
+    pub fn product_not_found(&self, name: &str) -> Box<dyn Error> {
+        let suggestions: Vec<String> = self
+            .list
+            .suggestions(name, SUGGESTIONS)
+            .iter()
+            .map(|name| format!("\"{}\"", name))
+            .collect();
+        match suggestions.is_empty() {
+            true => StorageError::boxed(format!("List error: {} - {}", ProductNotFound, name)),
+            false => StorageError::boxed(format!(
+                "List error: {} - {}. Did you mean {}?",
+                ProductNotFound,
+                name,
+                suggestions.join(", ")
+            )),
         }
     }
 
@@ -224,16 +726,63 @@ impl Storage {
         self.list.id_from_name(name)
     }
 
+    pub fn find_product_by_barcode(&self, barcode: &str) -> Result<u64, Box<dyn Error>> {
+        match self.list.id_from_barcode(barcode) {
+            Some(id) => Ok(id),
+            None => Err(StorageError::boxed(format!("{} - {}", BarcodeNotFound, barcode))),
+        }
+    }
+
+    pub fn find_product_by_sku(&self, sku: &str) -> Result<u64, Box<dyn Error>> {
+        match self.list.id_from_sku(sku) {
+            Some(id) => Ok(id),
+            None => Err(StorageError::boxed(format!("{} - {}", SkuNotFound, sku))),
+        }
+    }
+
+    pub fn change_sku(&mut self, id: u64, sku: Option<String>) -> Result<(), Box<dyn Error>> {
+        self.list.set_sku(id, sku)?;
+        info!("SKU of product {} changed", id);
+        Ok(())
+    }
+
+    pub fn change_barcode(&mut self, id: u64, barcode: Option<String>) -> Result<(), Box<dyn Error>> {
+        self.list.set_barcode(id, barcode)?;
+        info!("Barcode of product {} changed", id);
+        Ok(())
+    }
+
+    /// Restocks one unit from `delivery`, taking its next serial, or removes one
+    /// unit when there is no delivery.
+    pub fn scan(&mut self, barcode: &str, delivery: Option<&mut Delivery>) -> Result<u64, Box<dyn Error>> {
+        let id = self.find_product_by_barcode(barcode)?;
+        match delivery {
+            Some(delivery) => {
+                // Scans may be of different products, so one product's shelf life
+                // must not become the expiry date of the next.
+                let expiry_date = delivery.expiry_date;
+                let rest = delivery.serials.split_off(delivery.serials.len().min(1));
+                let result = self.restock_with(id, 1, delivery);
+                delivery.serials.extend(rest);
+                delivery.expiry_date = expiry_date;
+                result?
+            }
+            None => self.remove_stock(id, 1)?,
+        }
+        Ok(id)
+    }
+
     pub fn find_product_name(&self, id: u64) -> Option<&str> {
-        match self.list.products.get(&id) {
-            Some(product) => Some(product.name.as_str()),
+        match self.list.product(id) {
+            Some(product) => Some(product.name()),
             None => None,
         }
     }
 
     pub fn delete_product_by_id(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
         if let Some(product) = self.list.product(id) {
-            if product.quantity > 0 {
+            let stocked = self.warehouses.values().any(|warehouse| warehouse.contains_product(id));
+            if product.quantity > 0 || stocked {
                 Err(StorageError::list(HasStock))
             } else {
                 self.list.remove_by_id(id)?;
@@ -251,7 +800,7 @@ impl Storage {
                 Ok(_) => Ok(()),
                 Err(e) => Err(e),
             },
-            None => Err(StorageError::list(ProductNotFound)),
+            None => Err(self.product_not_found(name)),
         }
     }
 
@@ -261,13 +810,82 @@ impl Storage {
         quantity: usize,
         expiry_date: Option<NaiveDate>,
     ) -> Result<(), Box<dyn Error>> {
-        match self
-            .warehouse
-            .independent_restock(id, quantity, &mut self.list, expiry_date)
+        self.restock_with(id, quantity, &mut Delivery::new(expiry_date))
+    }
+
+    pub fn restock_with(
+        &mut self,
+        id: u64,
+        quantity: usize,
+        delivery: &mut Delivery,
+    ) -> Result<(), Box<dyn Error>> {
+        let serials = Vec::from(delivery.serials.clone());
+        for warehouse in self.warehouses.values() {
+            warehouse.check_serials(&serials)?;
+        }
+        let warehouse = Storage::site(&mut self.warehouses, &self.active_warehouse)?;
+        let existing: BTreeSet<_> = warehouse.find_all_item_occurences(id).into_iter().collect();
+        if let Err(e) = warehouse.restock(id, quantity, &mut self.list, delivery) {
+            // Units placed before the failure would have no receipt in the cost
+            // ledger, so take them back out and leave the stock as it was.
+            let placed = warehouse.undo_restock(id, &existing)?;
+            self.list.step_qty(id, -(placed as isize))?;
+            delivery.serials = serials.into();
+            return Err(e);
+        }
+        self.record_cost(id, CostEntry::receipt(today(), quantity, delivery.unit_cost));
+        Ok(())
+    }
+
+    fn record_cost(&mut self, id: u64, entry: CostEntry) {
+        self.cost_ledger.entry(id).or_default().push(entry);
+    }
+
+    fn record_sale(&mut self, id: u64, quantity: usize) {
+        if let Some(price) = self.list.product(id).map(|product| product.price) {
+            if quantity > 0 {
+                self.record_cost(id, CostEntry::sale(today(), quantity, price));
+            }
+        }
+    }
+
+    fn record_write_off(&mut self, id: u64, quantity: usize) {
+        if quantity > 0 {
+            self.record_cost(id, CostEntry::write_off(today(), quantity));
+        }
+    }
+
+    /// Unit costs are entered in the storage currency, so products priced in
+    /// another currency cannot be valued against them.
+    pub fn valuation(&self, method: CostMethod, id: Option<u64>) -> Result<ValuationReport, Box<dyn Error>> {
+        let mut products: Vec<&Product> = self
+            .list
+            .products()
+            .filter(|product| id.is_none_or(|id| product.id == id))
+            .collect();
+        if let Some(product) = products
+            .iter()
+            .find(|product| product.currency.as_ref().is_some_and(|currency| *currency != self.currency))
         {
-            Ok(_) => self.list.step_qty(id, quantity as isize),
-            Err(e) => Err(e),
+            return Err(StorageError::boxed(format!("{} - {}", ForeignCurrency, product.name())));
         }
+        products.sort_by(|a, b| a.name().cmp(b.name()));
+        let products = products
+            .into_iter()
+            .map(|product| {
+                let entries = self.cost_ledger.get(&product.id).map_or(&[][..], |e| e.as_slice());
+                ProductValuation::new(product, product.quantity, entries, method)
+            })
+            .collect();
+        Ok(ValuationReport { method, products })
+    }
+
+    pub fn print_valuation(&self, method: CostMethod, id: Option<u64>) -> Result<(), Box<dyn Error>> {
+        if id.is_some_and(|id| self.list.product(id).is_none()) {
+            return Err(StorageError::list(ProductNotFound));
+        }
+        println!("{}", self.valuation(method, id)?.priced(&self.price_format()));
+        Ok(())
     }
 
     pub fn restock_by_name(
@@ -276,23 +894,276 @@ impl Storage {
         quantity: usize,
         expiry_date: Option<NaiveDate>,
     ) -> Result<(), Box<dyn Error>> {
-        let step = quantity as isize;
         match self.find_product_id(name) {
-            Some(id) => match self.restock_product(id, quantity, expiry_date) {
-                Ok(_) => self.list.step_qty(id, -step),
-                Err(e) => Err(e),
-            },
-            None => Err(StorageError::list(ProductNotFound)),
+            Some(id) => self.restock_product(id, quantity, expiry_date),
+            None => Err(self.product_not_found(name)),
+        }
+    }
+
+    pub fn restock_with_by_name(
+        &mut self,
+        name: &str,
+        quantity: usize,
+        delivery: &mut Delivery,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.find_product_id(name) {
+            Some(id) => self.restock_with(id, quantity, delivery),
+            None => Err(self.product_not_found(name)),
+        }
+    }
+
+    pub fn lot_locations(&self, lot: &str) -> Vec<(&str, ProductItem)> {
+        self.warehouses
+            .iter()
+            .flat_map(|(name, warehouse)| {
+                warehouse
+                    .items_with_lot(lot)
+                    .into_iter()
+                    .map(move |item| (name.as_str(), item))
+            })
+            .collect()
+    }
+
+    pub fn print_lot(&self, lot: &str) -> Result<(), Box<dyn Error>> {
+        let locations = self.lot_locations(lot);
+        if locations.is_empty() {
+            return Err(StorageError::boxed(format!("{} - {}", LotNotFound, lot)));
+        }
+        println!("Listing items of lot {}", lot);
+        let format = self.price_format();
+        for (warehouse, item) in locations {
+            let name = self.find_product_name(item.id).unwrap_or("N/A");
+            println!("{}: {} - {}", warehouse, name, item.priced(&format));
+        }
+        Ok(())
+    }
+
+    pub fn refresh_held(&mut self, id: u64) {
+        let held = self
+            .warehouses
+            .values()
+            .map(|warehouse| warehouse.held_quantity(id))
+            .sum();
+        if let Some(product) = self.list.product_mut(id) {
+            product.held = held;
+        }
+    }
+
+    pub fn set_item_status(
+        &mut self,
+        placement: (usize, usize, usize, usize),
+        status: ItemStatus,
+    ) -> Result<(), Box<dyn Error>> {
+        let id = self.warehouse_mut().set_item_status(placement, status)?;
+        self.refresh_held(id);
+        Ok(())
+    }
+
+    pub fn list_items_with_status(&self, status: &str) {
+        println!("Listing {} items on warehouse", ItemStatus::normalize(status));
+        let items = self.warehouse().items_with_status(status);
+        Warehouse::print_item_list(&items);
+    }
+
+    pub fn recall_lot(&mut self, lot: &str, remove: bool) -> Result<usize, Box<dyn Error>> {
+        let ids: BTreeSet<u64> = self.lot_locations(lot).iter().map(|(_, item)| item.id).collect();
+        if ids.is_empty() {
+            return Err(StorageError::boxed(format!("{} - {}", LotNotFound, lot)));
+        }
+        let recalled = match remove {
+            true => {
+                let selected: Vec<(String, Vec<ProductItem>)> = self
+                    .warehouses
+                    .iter()
+                    .map(|(name, warehouse)| (name.clone(), warehouse.items_with_lot(lot)))
+                    .collect();
+                self.check_quantities(selected.iter().flat_map(|(_, items)| items))?;
+                let taken = self.take_from_sites(selected)?;
+                let write_offs = self.write_offs_for(&taken, "Recall");
+                for write_off in &write_offs {
+                    self.list.step_qty(write_off.product_id, -(write_off.quantity as isize))?;
+                    self.record_write_off(write_off.product_id, write_off.quantity);
+                }
+                info!("Removed {} items of lot {}", taken.len(), lot);
+                self.write_offs.extend(write_offs);
+                taken.len()
+            }
+            false => self.warehouses.values_mut().map(|warehouse| warehouse.quarantine_lot(lot)).sum(),
+        };
+        ids.into_iter().for_each(|id| self.refresh_held(id));
+        Ok(recalled)
+    }
+
+    pub fn find_serial(&self, serial: &str) -> Option<(&str, &ProductItem)> {
+        self.warehouses.iter().find_map(|(name, warehouse)| {
+            warehouse
+                .item_with_serial(serial)
+                .map(|item| (name.as_str(), item))
+        })
+    }
+
+    pub fn print_serial(&self, serial: &str) -> Result<(), Box<dyn Error>> {
+        match self.find_serial(serial) {
+            Some((warehouse, item)) => {
+                let name = self.find_product_name(item.id).unwrap_or("N/A");
+                println!("{}: {} - {}", warehouse, name, item.priced(&self.price_format()));
+                Ok(())
+            }
+            None => Err(StorageError::boxed(format!("{} - {}", SerialNotFound, serial))),
+        }
+    }
+
+    pub fn remove_serial(&mut self, serial: &str) -> Result<(), Box<dyn Error>> {
+        let name = match self.find_serial(serial) {
+            Some((name, _)) => name.to_string(),
+            None => return Err(StorageError::boxed(format!("{} - {}", SerialNotFound, serial))),
+        };
+        let item = Storage::site(&mut self.warehouses, &name)?.remove_serial(serial)?;
+        self.list.step_qty(item.id, -1)?;
+        self.record_sale(item.id, 1);
+        self.refresh_held(item.id);
+        Ok(())
+    }
+
+    pub fn dispose_expired(
+        &mut self,
+        before: NaiveDate,
+        dry_run: bool,
+    ) -> Result<Vec<WriteOff>, Box<dyn Error>> {
+        let expired: Vec<(String, Vec<ProductItem>)> = self
+            .warehouses
+            .iter()
+            .map(|(name, warehouse)| (name.clone(), warehouse.filter_expired_items(before)))
+            .collect();
+        self.check_quantities(expired.iter().flat_map(|(_, items)| items))?;
+        if dry_run {
+            let items: Vec<ProductItem> = expired.into_iter().flat_map(|(_, items)| items).collect();
+            return Ok(self.write_offs_for(&items, &format!("Expired before {}", before)));
+        }
+
+        let taken = self.take_from_sites(expired)?;
+        let write_offs = self.write_offs_for(&taken, &format!("Expired before {}", before));
+        for write_off in &write_offs {
+            self.list.step_qty(write_off.product_id, -(write_off.quantity as isize))?;
+            self.record_write_off(write_off.product_id, write_off.quantity);
+            self.refresh_held(write_off.product_id);
+        }
+        info!("Disposed {} expired items", taken.len());
+        self.write_offs.extend(write_offs.iter().cloned());
+        Ok(write_offs)
+    }
+
+    /// Checks that the product list covers the items about to be taken out.
+    fn check_quantities<'a>(&self, items: impl Iterator<Item = &'a ProductItem>) -> Result<(), Box<dyn Error>> {
+        let mut quantities: BTreeMap<u64, usize> = BTreeMap::new();
+        for item in items {
+            *quantities.entry(item.id).or_insert(0) += 1;
+        }
+        for (&id, &quantity) in &quantities {
+            match self.list.product(id) {
+                Some(product) if product.quantity >= quantity => {}
+                Some(_) => return Err(StorageError::list(InsufficientQuantity)),
+                None => return Err(StorageError::list(ProductNotFound)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the given items out of each warehouse, or none of them: when one
+    /// warehouse fails, the items already taken from the others are put back.
+    fn take_from_sites(
+        &mut self,
+        selected: Vec<(String, Vec<ProductItem>)>,
+    ) -> Result<Vec<ProductItem>, Box<dyn Error>> {
+        let mut taken: Vec<(String, Vec<ProductItem>)> = Vec::new();
+        for (name, items) in selected {
+            let result = Storage::site(&mut self.warehouses, &name)
+                .and_then(|warehouse| warehouse.take_stock(items.len(), items));
+            match result {
+                Ok(items) => taken.push((name, items)),
+                Err(e) => {
+                    for (name, items) in taken {
+                        Storage::site(&mut self.warehouses, &name)?.put_back(items)?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(taken.into_iter().flat_map(|(_, items)| items).collect())
+    }
+
+    // One write-off per product and lot among the disposed items.
+    fn write_offs_for(&self, items: &[ProductItem], reason: &str) -> Vec<WriteOff> {
+        let mut disposed: BTreeMap<(u64, Option<String>), usize> = BTreeMap::new();
+        for item in items {
+            *disposed.entry((item.id, item.lot.clone())).or_insert(0) += 1;
+        }
+        let date = chrono::Local::now().naive_local().date();
+        disposed
+            .into_iter()
+            .map(|((product_id, lot), quantity)| {
+                let product = self.list.product(product_id);
+                WriteOff {
+                    date,
+                    product_id,
+                    product_name: product.map_or(String::new(), |p| p.name().to_string()),
+                    lot,
+                    quantity,
+                    value: product.map_or(0, |p| p.price) * quantity as u64,
+                    currency: product.and_then(|p| p.currency.clone()),
+                    reason: reason.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn write_off_report(&self, month: Option<NaiveDate>) {
+        let mut months: BTreeMap<String, Vec<&WriteOff>> = BTreeMap::new();
+        for write_off in &self.write_offs {
+            let key = write_off.date.format("%Y-%m").to_string();
+            if month.is_none_or(|month| month.format("%Y-%m").to_string() == key) {
+                months.entry(key).or_default().push(write_off);
+            }
+        }
+        if months.is_empty() {
+            println!("No write-offs recorded");
+            return;
+        }
+        let format = self.price_format();
+        for (month, write_offs) in months {
+            let quantity: usize = write_offs.iter().map(|w| w.quantity).sum();
+            let mut values: BTreeMap<Currency, u64> = BTreeMap::new();
+            for write_off in &write_offs {
+                let currency = format.with_currency(write_off.currency.as_ref()).currency;
+                *values.entry(currency).or_insert(0) += write_off.value;
+            }
+            let value = format.format_totals(&values);
+            println!("{}: {} units written off, total value {}", month, quantity, value);
+            write_offs
+                .iter()
+                .for_each(|write_off| println!("  {}", write_off.priced(&format)));
         }
     }
 
     pub fn change_price(&mut self, id: u64, price: u64) -> Result<(), Box<dyn Error>> {
-        let current_price = self.list.products.get(&id).unwrap().price;
-        if let Some(product) = self.list.products.get_mut(&id) {
-            product.set_price(price);
+        self.change_price_with(id, price, None)
+    }
+
+    pub fn change_price_with(
+        &mut self,
+        id: u64,
+        price: u64,
+        reason: Option<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        let format = self.price_format();
+        if let Some(product) = self.list.product_mut(id) {
+            let current_price = product.change_price(price, reason);
+            let format = format.with_currency(product.currency.as_ref());
             println!(
                 "Price for product {} changed from {} to {}",
-                id, current_price, price
+                id,
+                format.format(current_price),
+                format.format(price)
             );
             Ok(())
         } else {
@@ -303,15 +1174,466 @@ impl Storage {
     pub fn change_price_by_name(&mut self, name: &str, price: u64) -> Result<(), Box<dyn Error>> {
         match self.find_product_id(name) {
             Some(id) => self.change_price(id, price),
+            None => Err(self.product_not_found(name)),
+        }
+    }
+
+    pub fn schedule_price(
+        &mut self,
+        id: u64,
+        effective: NaiveDate,
+        price: u64,
+        reason: Option<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.schedule_price(effective, price, reason);
+                info!("Price for product {} scheduled to {} from {}", id, price, effective);
+            }
+            None => return Err(StorageError::list(ProductNotFound)),
+        }
+        self.apply_scheduled_prices(today());
+        Ok(())
+    }
+
+    pub fn apply_scheduled_prices(&mut self, date: NaiveDate) -> usize {
+        let applied = self
+            .list
+            .products_mut()
+            .map(|product| product.apply_scheduled_prices(date))
+            .sum();
+        if applied > 0 {
+            info!("Applied {} scheduled price changes", applied);
+        }
+        applied
+    }
+
+    pub fn print_price_history(&self, id: u64) -> Result<(), Box<dyn Error>> {
+        let product = match self.list.product(id) {
+            Some(product) => product,
+            None => return Err(StorageError::list(ProductNotFound)),
+        };
+        let format = self.price_format().with_currency(product.currency.as_ref());
+        println!("Price history for {} (current price {})", product.name(), format.format(product.price));
+        if product.price_history.is_empty() {
+            println!("  No price changes recorded");
+        }
+        product
+            .price_history
+            .iter()
+            .for_each(|change| println!("  {}", change.priced(&format)));
+        if !product.scheduled_prices.is_empty() {
+            println!("Scheduled price changes:");
+            product
+                .scheduled_prices
+                .iter()
+                .for_each(|scheduled| println!("  {}", scheduled.priced(&format)));
+        }
+        Ok(())
+    }
+
+    pub fn change_weight(&mut self, id: u64, weight: Option<u64>) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.set_weight(weight);
+                info!("Weight for product {} changed", id);
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
+        }
+    }
+
+    pub fn change_weight_by_name(
+        &mut self,
+        name: &str,
+        weight: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.find_product_id(name) {
+            Some(id) => self.change_weight(id, weight),
+            None => Err(self.product_not_found(name)),
+        }
+    }
+
+    pub fn change_shelf_life(
+        &mut self,
+        id: u64,
+        shelf_life_days: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.set_shelf_life(shelf_life_days);
+                info!("Shelf life for product {} changed", id);
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
+        }
+    }
+
+    pub fn change_shelf_life_by_name(
+        &mut self,
+        name: &str,
+        shelf_life_days: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.find_product_id(name) {
+            Some(id) => self.change_shelf_life(id, shelf_life_days),
+            None => Err(self.product_not_found(name)),
+        }
+    }
+
+    pub fn add_markdown(&mut self, id: u64, rule: MarkdownRule) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.markdowns.push(rule);
+                info!("Markdown rule added to product {}: {}", id, rule);
+                Ok(())
+            }
             None => Err(StorageError::list(ProductNotFound)),
         }
     }
 
+    pub fn add_quality_markdown(
+        &mut self,
+        quality: &str,
+        rule: MarkdownRule,
+    ) -> Result<(), Box<dyn Error>> {
+        let quality = quality.to_lowercase().replace('-', " ");
+        if !QUALITY_CLASSES.contains(&quality.as_str()) {
+            return Err(StorageError::boxed(format!("{} - {}", UnknownQuality, quality)));
+        }
+        info!("Markdown rule added to {} products: {}", quality, rule);
+        self.list.quality_markdowns.entry(quality).or_default().push(rule);
+        Ok(())
+    }
+
+    pub fn clear_markdowns(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.markdowns.clear();
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
+        }
+    }
+
+    pub fn clear_quality_markdowns(&mut self, quality: &str) {
+        let quality = quality.to_lowercase().replace('-', " ");
+        self.list.quality_markdowns.remove(&quality);
+    }
+
+    pub fn list_markdowns(&self) {
+        println!("Markdown rules");
+        for (quality, rules) in &self.list.quality_markdowns {
+            rules.iter().for_each(|rule| println!("  {} products: {}", quality, rule));
+        }
+        for product in self.list.products() {
+            product
+                .markdowns
+                .iter()
+                .for_each(|rule| println!("  {} (ID {}): {}", product.name(), product.id, rule));
+        }
+    }
+
+    pub fn list_expiring_with_price(&self, days: u64, id: Option<u64>) {
+        let today = chrono::Local::now().naive_local().date();
+        let expiring_date = today + chrono::Duration::days(days as i64);
+        println!("Listing items on warehouse expiring on {} with prices", expiring_date);
+        let items = self.warehouse().items().into_iter().filter(|item| {
+            item.expiry_date.is_some_and(|date| date <= expiring_date) && id.is_none_or(|id| item.id == id)
+        });
+        let format = self.price_format();
+        for item in items {
+            if let (Some(product), Some(price)) =
+                (self.list.product(item.id), self.list.effective_price(&item, today))
+            {
+                let format = format.with_currency(product.currency.as_ref());
+                println!(
+                    "Product: {}, ID: {}, Placement: {:?}, Expiry Date: {}, List Price: {}, Markdown Price: {}",
+                    product.name(),
+                    item.id,
+                    item.placement,
+                    item.expiry_date.unwrap(),
+                    format.format(product.price),
+                    format.format(price)
+                );
+            }
+        }
+        println!();
+    }
+
+    pub fn set_reorder_policy(
+        &mut self,
+        id: u64,
+        reorder_point: Option<usize>,
+        reorder_quantity: Option<usize>,
+        max_stock: Option<usize>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.list.product_mut(id) {
+            Some(product) => {
+                product.set_reorder_policy(reorder_point, reorder_quantity, max_stock);
+                info!("Reorder policy for product {} changed", id);
+                Ok(())
+            }
+            None => Err(StorageError::list(ProductNotFound)),
+        }
+    }
+
+    /// Stock on hand counts every warehouse, so the room to restock does too.
+    pub fn replenishment(&self, on_order: &BTreeMap<u64, usize>) -> Vec<Replenishment> {
+        let mut products: Vec<&Product> = self.list.products().collect();
+        products.sort_by(|a, b| a.name().cmp(b.name()));
+        products
+            .into_iter()
+            .filter_map(|product| {
+                let on_hand = product.available_quantity();
+                let on_order = on_order.get(&product.id).copied().unwrap_or(0);
+                let suggested = product.suggested_order(on_hand + on_order)?;
+                let capacity = self.warehouses.values().map(|warehouse| warehouse.capacity_for(product)).sum();
+                Some(Replenishment {
+                    product_id: product.id,
+                    product_name: product.name().to_string(),
+                    on_hand,
+                    on_order,
+                    reorder_point: product.reorder_point?,
+                    suggested: suggested.min(capacity),
+                    capacity,
+                })
+            })
+            .collect()
+    }
+
+    pub fn print_replenishment(&self) {
+        let report = self.replenishment(&self.on_order());
+        if report.is_empty() {
+            println!("No products below their reorder point");
+            return;
+        }
+        println!("Replenishment suggestions across all warehouses");
+        for line in report {
+            println!(
+                "{} (ID {}): on hand {}, on order {}, reorder point {}, suggested order {} (room for {})",
+                line.product_name,
+                line.product_id,
+                line.on_hand,
+                line.on_order,
+                line.reorder_point,
+                line.suggested,
+                line.capacity
+            );
+        }
+    }
+
+    pub fn add_supplier(&mut self, name: &str, contact: Option<String>) -> u64 {
+        let id = purchasing::next_id(&self.suppliers);
+        self.suppliers.insert(id, Supplier::new(id, name, contact));
+        info!("Supplier {} added", id);
+        id
+    }
+
+    pub fn create_purchase_order(&mut self, supplier_id: u64) -> Result<u64, Box<dyn Error>> {
+        if !self.suppliers.contains_key(&supplier_id) {
+            return Err(PurchasingError::with_id(SupplierNotFound, supplier_id));
+        }
+        let id = purchasing::next_id(&self.purchase_orders);
+        let date = chrono::Local::now().naive_local().date();
+        self.purchase_orders.insert(id, PurchaseOrder::new(id, supplier_id, date));
+        info!("Purchase order {} created", id);
+        Ok(id)
+    }
+
+    pub fn add_purchase_order_line(
+        &mut self,
+        po_id: u64,
+        line: PurchaseOrderLine,
+    ) -> Result<usize, Box<dyn Error>> {
+        if self.list.product(line.product_id).is_none() {
+            return Err(StorageError::list(ProductNotFound));
+        }
+        match self.purchase_orders.get_mut(&po_id) {
+            Some(po) => po.add_line(line),
+            None => Err(PurchasingError::with_id(PurchaseOrderNotFound, po_id)),
+        }
+    }
+
+    pub fn on_order(&self) -> BTreeMap<u64, usize> {
+        let mut on_order = BTreeMap::new();
+        for po in self.purchase_orders.values().filter(|po| po.is_open()) {
+            for line in &po.lines {
+                *on_order.entry(line.product_id).or_insert(0) += line.outstanding();
+            }
+        }
+        on_order
+    }
+
+    pub fn receive(
+        &mut self,
+        po_id: u64,
+        line: Option<usize>,
+        quantity: Option<usize>,
+        expiry_date: Option<NaiveDate>,
+        lot: Option<String>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let po = match self.purchase_orders.get(&po_id) {
+            Some(po) if po.is_open() => po,
+            Some(_) => return Err(PurchasingError::with_id(PurchaseOrderClosed, po_id)),
+            None => return Err(PurchasingError::with_id(PurchaseOrderNotFound, po_id)),
+        };
+        let receipts: Vec<(usize, u64, u64, usize)> = match line {
+            Some(number) => {
+                let line = po.line(number)?;
+                let quantity = quantity.unwrap_or(line.outstanding());
+                vec![(number, line.product_id, line.unit_cost, quantity)]
+            }
+            None => po
+                .lines
+                .iter()
+                .enumerate()
+                .map(|(index, line)| (index + 1, line.product_id, line.unit_cost, line.outstanding()))
+                .filter(|(_, _, _, quantity)| *quantity > 0)
+                .collect(),
+        };
+        let total: usize = receipts.iter().map(|(_, _, _, quantity)| quantity).sum();
+        if total == 0 {
+            return Err(PurchasingError::with_id(NothingToReceive, po_id));
+        }
+
+        // A receipt covers every line or none of them, so lines restocked before
+        // a failing one are taken back out along with their cost entries.
+        let warehouse = Storage::site(&mut self.warehouses, &self.active_warehouse)?;
+        let existing: BTreeMap<u64, BTreeSet<_>> = receipts
+            .iter()
+            .map(|(_, id, _, _)| (*id, warehouse.find_all_item_occurences(*id).into_iter().collect()))
+            .collect();
+        for (received, (_, product_id, unit_cost, quantity)) in receipts.iter().enumerate() {
+            let mut delivery = Delivery::new(expiry_date)
+                .with_lot(lot.clone())
+                .with_unit_cost(Some(*unit_cost));
+            if let Err(e) = self.restock_with(*product_id, *quantity, &mut delivery) {
+                for (_, id, _, _) in &receipts[..received] {
+                    if let Some(entries) = self.cost_ledger.get_mut(id) {
+                        entries.pop();
+                    }
+                }
+                let warehouse = Storage::site(&mut self.warehouses, &self.active_warehouse)?;
+                for (id, existing) in &existing {
+                    let placed = warehouse.undo_restock(*id, existing)?;
+                    self.list.step_qty(*id, -(placed as isize))?;
+                }
+                return Err(e);
+            }
+        }
+        if let Some(po) = self.purchase_orders.get_mut(&po_id) {
+            for (number, _, _, quantity) in receipts {
+                po.record_receipt(number, quantity)?;
+            }
+        }
+        info!("Received {} units against purchase order {}", total, po_id);
+        Ok(total)
+    }
+
+    pub fn create_return(
+        &mut self,
+        product_id: u64,
+        quantity: usize,
+        expiry_date: Option<NaiveDate>,
+        lot: Option<String>,
+        reason: Option<String>,
+    ) -> Result<u64, Box<dyn Error>> {
+        if self.list.product(product_id).is_none() {
+            return Err(StorageError::list(ProductNotFound));
+        }
+        let id = purchasing::next_id(&self.returns);
+        let date = chrono::Local::now().naive_local().date();
+        let mut rma = ReturnAuthorization::new(id, product_id, quantity, date);
+        rma.expiry_date = expiry_date;
+        rma.lot = lot;
+        rma.reason = reason;
+        self.returns.insert(id, rma);
+        info!("Return {} registered for {} units of {}", id, quantity, product_id);
+        Ok(id)
+    }
+
+    pub fn inspect_return(
+        &mut self,
+        rma_id: u64,
+        outcome: InspectionOutcome,
+        quantity: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let rma = match self.returns.get(&rma_id) {
+            Some(rma) => rma.clone(),
+            None => return Err(ReturnError::with_id(ReturnNotFound, rma_id)),
+        };
+        rma.check_pending(quantity)?;
+        let reference = format!("RMA {}", rma_id);
+        let delivery = Delivery::new(rma.expiry_date).with_lot(rma.lot.clone());
+        let (kind, warehouse) = match outcome {
+            InspectionOutcome::Restockable => {
+                self.restock_with(rma.product_id, quantity, &mut delivery.clone())?;
+                (MovementKind::ReturnRestocked, Some(self.active_warehouse.clone()))
+            }
+            InspectionOutcome::Quarantine => {
+                let mut delivery = delivery.with_status(ItemStatus::Quarantined);
+                self.restock_with(rma.product_id, quantity, &mut delivery)?;
+                self.refresh_held(rma.product_id);
+                (MovementKind::ReturnQuarantined, Some(self.active_warehouse.clone()))
+            }
+            InspectionOutcome::Scrap => {
+                let product = self.list.product(rma.product_id);
+                self.write_offs.push(WriteOff {
+                    date: chrono::Local::now().naive_local().date(),
+                    product_id: rma.product_id,
+                    product_name: product.map_or(String::new(), |p| p.name().to_string()),
+                    lot: rma.lot.clone(),
+                    quantity,
+                    value: product.map_or(0, |p| p.price) * quantity as u64,
+                    currency: product.and_then(|p| p.currency.clone()),
+                    reason: format!("Scrapped on {}", reference),
+                });
+                (MovementKind::ReturnScrapped, None)
+            }
+        };
+        if let Some(rma) = self.returns.get_mut(&rma_id) {
+            rma.record(outcome, quantity);
+        }
+        self.journal
+            .push(Movement::new(kind, rma.product_id, quantity, warehouse, reference));
+        info!("{} units of return {} inspected as {}", quantity, rma_id, outcome);
+        Ok(())
+    }
+
+    pub fn list_returns(&self, open_only: bool) {
+        self.returns
+            .values()
+            .filter(|rma| !open_only || rma.pending() > 0)
+            .for_each(|rma| println!("{}", rma));
+    }
+
+    pub fn print_journal(&self, product_id: Option<u64>) {
+        self.journal
+            .iter()
+            .filter(|movement| product_id.is_none_or(|id| movement.product_id == id))
+            .for_each(|movement| println!("{}", movement));
+    }
+
+    pub fn list_suppliers(&self) {
+        self.suppliers.values().for_each(|supplier| println!("{}", supplier));
+    }
+
+    pub fn list_purchase_orders(&self, open_only: bool) {
+        let format = self.price_format();
+        self.purchase_orders
+            .values()
+            .filter(|po| !open_only || po.is_open())
+            .for_each(|po| println!("{}", po.priced(&format)));
+    }
+
     pub fn remove_stock(&mut self, id: u64, quantity: usize) -> Result<(), Box<dyn Error>> {
         let step = quantity as isize;
         match self.list.product(id) {
-            Some(_) => match self.warehouse.remove_stock(id, quantity) {
-                Ok(_) => self.list.step_qty(id, -step),
+            Some(_) => match self.warehouse_mut().remove_stock(id, quantity) {
+                Ok(_) => {
+                    self.record_sale(id, quantity);
+                    self.list.step_qty(id, -step)
+                }
                 Err(e) => Err(e),
             },
             None => Err(StorageError::list(ProductNotFound)),
@@ -325,16 +1647,20 @@ impl Storage {
     ) -> Result<(), Box<dyn Error>> {
         match self.find_product_id(name) {
             Some(id) => self.remove_stock(id, quantity),
-            None => Err(StorageError::list(ProductNotFound)),
+            None => Err(self.product_not_found(name)),
         }
     }
 
     pub fn empty_stock(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
         match self.list.product(id) {
-            Some(_) => match self.warehouse.remove_all_stock(id) {
-                Ok(_) => self.list.empty_qty(id),
-                Err(e) => Err(e),
-            },
+            Some(_) => {
+                let step = self.warehouse().items_with_id(id).len() as isize;
+                self.warehouse_mut().remove_all_stock(id)?;
+                self.list.step_qty(id, -step)?;
+                self.record_write_off(id, step as usize);
+                self.refresh_held(id);
+                Ok(())
+            }
             None => Err(StorageError::list(ProductNotFound)),
         }
     }
@@ -345,11 +1671,15 @@ impl Storage {
                 Ok(_) => Ok(()),
                 Err(e) => Err(e),
             },
-            None => Err(StorageError::list(ProductNotFound)),
+            None => Err(self.product_not_found(name)),
         }
     }
 }
 
+fn today() -> NaiveDate {
+    chrono::Local::now().naive_local().date()
+}
+
 impl Default for Storage {
     fn default() -> Self {
         Storage::new("default".to_string(), None)
@@ -0,0 +1,66 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MovementKind {
+    ReturnRestocked,
+    ReturnQuarantined,
+    ReturnScrapped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Movement {
+    pub timestamp: DateTime<Utc>,
+    pub kind: MovementKind,
+    pub product_id: u64,
+    pub quantity: usize,
+    pub warehouse: Option<String>,
+    pub reference: String,
+}
+
+impl Display for MovementKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            MovementKind::ReturnRestocked => write!(f, "return restocked"),
+            MovementKind::ReturnQuarantined => write!(f, "return quarantined"),
+            MovementKind::ReturnScrapped => write!(f, "return scrapped"),
+        }
+    }
+}
+
+impl Display for Movement {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} units of {} {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.quantity,
+            self.product_id,
+            self.kind
+        )?;
+        if let Some(warehouse) = &self.warehouse {
+            write!(f, " at {}", warehouse)?;
+        }
+        write!(f, " ({})", self.reference)
+    }
+}
+
+impl Movement {
+    pub fn new(
+        kind: MovementKind,
+        product_id: u64,
+        quantity: usize,
+        warehouse: Option<String>,
+        reference: String,
+    ) -> Self {
+        Movement {
+            timestamp: Utc::now(),
+            kind,
+            product_id,
+            quantity,
+            warehouse,
+            reference,
+        }
+    }
+}
@@ -1,6 +1,17 @@
+pub mod alerts;
+pub mod backend;
+pub mod bitmap;
+pub mod catalogue;
+pub mod currency;
 pub mod inventory;
+pub mod journal;
 pub mod product;
+pub mod purchasing;
+pub mod query;
 pub mod repl;
+pub mod returns;
+pub mod search;
+pub mod valuation;
 pub mod warehouse;
 pub mod test;
 
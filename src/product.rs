@@ -1,9 +1,14 @@
+use crate::{
+    catalogue,
+    currency::{Currency, PriceFormat, Priced},
+    search,
+};
 use chrono::{DateTime, NaiveDate};
 use log::info;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, VecDeque},
     error::Error,
     fmt::{self, Display, Formatter},
 };
@@ -17,24 +22,146 @@ pub enum Quality {
     OversizedAndFragile(usize, usize),
 }
 
-impl Quality {
-    pub fn to_string(&self) -> String {
+impl Display for Quality {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Quality::Normal => write!(f, "normal"),
+            Quality::Fragile(_) => write!(f, "fragile"),
+            Quality::Oversized(_) => write!(f, "oversized"),
+            Quality::OversizedAndFragile(_, _) => write!(f, "oversized and fragile"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ItemStatus {
+    #[default]
+    Available,
+    Quarantined,
+    Damaged,
+    OnHold(String),
+}
+
+impl Display for ItemStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
-            Quality::Normal => "normal".to_string(),
-            Quality::Fragile(_) => "fragile".to_string(),
-            Quality::Oversized(_) => "oversized".to_string(),
-            Quality::OversizedAndFragile(_, _) => "oversized and fragile".to_string(),
+            ItemStatus::OnHold(reason) => write!(f, "{} ({})", self.name(), reason),
+            _ => write!(f, "{}", self.name()),
         }
     }
 }
 
+impl ItemStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemStatus::Available => "available",
+            ItemStatus::Quarantined => "quarantined",
+            ItemStatus::Damaged => "damaged",
+            ItemStatus::OnHold(_) => "on-hold",
+        }
+    }
+
+    /// Turns a status as typed, such as `On Hold` or `on_hold`, into the form
+    /// `name` returns.
+    pub fn normalize(input: &str) -> String {
+        input.trim().to_lowercase().replace([' ', '_'], "-")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct MarkdownRule {
+    pub days_before_expiry: u64,
+    pub percent_off: u64,
+}
+
+impl Display for MarkdownRule {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}% off within {} days of expiry",
+            self.percent_off, self.days_before_expiry
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct PriceChange {
+    pub timestamp: DateTime<chrono::Utc>,
+    pub old_price: u64,
+    pub new_price: u64,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ScheduledPrice {
+    pub effective: NaiveDate,
+    pub price: u64,
+    pub reason: Option<String>,
+}
+
+impl Priced for PriceChange {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            format.format(self.old_price),
+            format.format(self.new_price)
+        )?;
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl Priced for ScheduledPrice {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        write!(f, "From {}: {}", self.effective, format.format(self.price))?;
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Product {
     pub id: u64,
-    pub name: String,
+    name: String,
     pub price: u64,
     pub quantity: usize,
     pub quality: Quality,
+    #[serde(default)]
+    pub weight: Option<u64>,
+    #[serde(default = "single_level")]
+    pub levels_required: usize,
+    #[serde(default)]
+    pub held: usize,
+    #[serde(default)]
+    pub shelf_life_days: Option<u64>,
+    #[serde(default)]
+    pub markdowns: Vec<MarkdownRule>,
+    #[serde(default)]
+    pub reorder_point: Option<usize>,
+    #[serde(default)]
+    pub reorder_quantity: Option<usize>,
+    #[serde(default)]
+    pub max_stock: Option<usize>,
+    #[serde(default)]
+    pub price_history: Vec<PriceChange>,
+    #[serde(default)]
+    pub scheduled_prices: Vec<ScheduledPrice>,
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    #[serde(default)]
+    pub sku: Option<String>,
+    #[serde(default)]
+    pub barcode: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -42,23 +169,180 @@ pub struct ProductItem {
     pub id: u64,
     pub placement: (usize, usize, usize, usize),
     pub zones_required: usize,
+    #[serde(default = "single_level")]
+    pub levels_required: usize,
     pub expiry_date: Option<NaiveDate>,
     pub timestamp: DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub weight: Option<u64>,
+    #[serde(default)]
+    pub lot: Option<String>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    #[serde(default)]
+    pub status: ItemStatus,
+    #[serde(default)]
+    pub unit_cost: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Delivery {
+    pub expiry_date: Option<NaiveDate>,
+    pub lot: Option<String>,
+    pub serials: VecDeque<String>,
+    pub status: ItemStatus,
+    pub unit_cost: Option<u64>,
+    pub units: VecDeque<ProductItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdAllocator {
+    Random,
+    Sequential { next: u64 },
+    Uuid,
+    Manual,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "ProductListData")]
 pub struct ProductList {
-    pub products: HashMap<u64, Product>,
+    products: HashMap<u64, Product>,
+    #[serde(default)]
+    pub quality_markdowns: HashMap<String, Vec<MarkdownRule>>,
+    #[serde(default)]
+    pub id_allocator: IdAllocator,
+    #[serde(skip)]
+    names: NameIndex,
+}
+
+#[derive(Deserialize)]
+struct ProductListData {
+    products: HashMap<u64, Product>,
+    #[serde(default)]
+    quality_markdowns: HashMap<String, Vec<MarkdownRule>>,
+    #[serde(default)]
+    id_allocator: IdAllocator,
+}
+
+/// Name lookups for a `ProductList`: exact names, plus names folded to
+/// lowercase with collapsed whitespace for case-insensitive matches.
+#[derive(Debug, Default)]
+struct NameIndex {
+    exact: HashMap<String, u64>,
+    folded: HashMap<String, BTreeSet<u64>>,
+}
+
+impl From<ProductListData> for ProductList {
+    fn from(data: ProductListData) -> Self {
+        ProductList {
+            names: NameIndex::build(&data.products),
+            products: data.products,
+            quality_markdowns: data.quality_markdowns,
+            id_allocator: data.id_allocator,
+        }
+    }
+}
+
+impl NameIndex {
+    fn build(products: &HashMap<u64, Product>) -> Self {
+        let mut index = NameIndex::default();
+        products.values().for_each(|product| index.insert(&product.name, product.id));
+        index
+    }
+
+    fn fold(name: &str) -> String {
+        name.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+    }
+
+    fn insert(&mut self, name: &str, id: u64) {
+        self.exact.insert(name.to_string(), id);
+        self.folded.entry(NameIndex::fold(name)).or_default().insert(id);
+    }
+
+    fn remove(&mut self, name: &str, id: u64) {
+        self.exact.remove(name);
+        let folded = NameIndex::fold(name);
+        if let Some(ids) = self.folded.get_mut(&folded) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.folded.remove(&folded);
+            }
+        }
+    }
+}
+
+impl Default for IdAllocator {
+    fn default() -> Self {
+        IdAllocator::Sequential { next: 1 }
+    }
 }
 
-impl Display for Product {
+impl Display for IdAllocator {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let price = format_price(self.price);
+        match self {
+            IdAllocator::Random => write!(f, "random"),
+            IdAllocator::Sequential { next } => write!(f, "sequential (next {})", next),
+            IdAllocator::Uuid => write!(f, "uuid"),
+            IdAllocator::Manual => write!(f, "manual"),
+        }
+    }
+}
+
+impl IdAllocator {
+    /// Returns the next free id, or `None` when ids are supplied by the caller.
+    pub fn allocate(&mut self, taken: impl Fn(u64) -> bool) -> Option<u64> {
+        match self {
+            IdAllocator::Random => loop {
+                let id = generate_id();
+                if !taken(id) {
+                    return Some(id);
+                }
+            },
+            IdAllocator::Sequential { next } => {
+                let mut id = (*next).max(1);
+                while taken(id) {
+                    id += 1;
+                }
+                *next = id + 1;
+                Some(id)
+            }
+            // Masked to 63 bits so the id also fits a signed SQLite integer.
+            IdAllocator::Uuid => loop {
+                let id = uuid::Uuid::new_v4().as_u64_pair().0 & i64::MAX as u64;
+                if id != 0 && !taken(id) {
+                    return Some(id);
+                }
+            },
+            IdAllocator::Manual => None,
+        }
+    }
+}
+
+impl Priced for Product {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        let price = format.format_in(self.price, self.currency.as_ref());
         write!(
             f,
             "Product: {}\n ID: {}, Price: {}, Quantity: {}",
             self.name, self.id, price, self.quantity,
-        )
+        )?;
+        if self.held > 0 {
+            write!(f, " ({} available, {} held)", self.available_quantity(), self.held)?;
+        }
+        if let Some(sku) = &self.sku {
+            write!(f, ", SKU: {}", sku)?;
+        }
+        if let Some(barcode) = &self.barcode {
+            write!(f, ", Barcode: {}", barcode)?;
+        }
+        if let Some(category) = &self.category {
+            write!(f, ", Category: {}", category)?;
+        }
+        if !self.tags.is_empty() {
+            let tags: Vec<&str> = self.tags.iter().map(String::as_str).collect();
+            write!(f, ", Tags: {}", tags.join(", "))?;
+        }
+        Ok(())
     }
 }
 impl Display for ProductItem {
@@ -71,7 +355,24 @@ impl Display for ProductItem {
             f,
             "ID: {}, Location: {:?}, Expiry Date: {}",
             self.id, self.placement, date
-        )
+        )?;
+        if let Some(lot) = &self.lot {
+            write!(f, ", Lot: {}", lot)?;
+        }
+        if let Some(serial) = &self.serial {
+            write!(f, ", Serial: {}", serial)?;
+        }
+        Ok(())
+    }
+}
+
+impl Priced for ProductItem {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        write!(f, "{}", self)?;
+        if let Some(unit_cost) = self.unit_cost {
+            write!(f, ", Unit Cost: {}", format.format(unit_cost))?;
+        }
+        Ok(())
     }
 }
 
@@ -126,6 +427,10 @@ pub enum ErrorMessage {
     InvalidInput,
     LevelTooHigh,
     FragileObjectWithoutExpiration,
+    IdExists,
+    SkuExists,
+    BarcodeExists,
+    InvalidBarcode,
 }
 
 impl ErrorMessage {
@@ -137,6 +442,10 @@ impl ErrorMessage {
             InvalidInput => "Invalid input",
             LevelTooHigh => "Level too high",
             FragileObjectWithoutExpiration => "Fragile object without expiration",
+            IdExists => "Product with this ID already exists",
+            SkuExists => "Product with this SKU already exists",
+            BarcodeExists => "Product with this barcode already exists",
+            InvalidBarcode => "Invalid GTIN/EAN/UPC barcode",
         }
     }
 }
@@ -159,25 +468,46 @@ impl Display for ErrorMessage {
     }
 }
 
-fn format_price(price: u64) -> String {
-    let numeral = price / 100;
-    let decimal = price % 100;
+pub fn format_weight(weight: u64) -> String {
+    let numeral = weight / 1000;
+    let decimal = weight % 1000;
 
-    format!("${}.{}", numeral, format_args!("{:02}", decimal))
+    format!("{}.{} kg", numeral, format_args!("{:03}", decimal))
 }
 
 #[allow(dead_code)]
 impl Product {
     pub fn new(name: &str, price: u64, quantity: usize, quality: Quality) -> Self {
         Product {
-            id: generate_id(),
+            id: 0,
             name: name.to_string(),
             price,
             quantity,
             quality,
+            weight: None,
+            levels_required: 1,
+            held: 0,
+            shelf_life_days: None,
+            markdowns: Vec::new(),
+            reorder_point: None,
+            reorder_quantity: None,
+            max_stock: None,
+            price_history: Vec::new(),
+            scheduled_prices: Vec::new(),
+            currency: None,
+            sku: None,
+            barcode: None,
+            category: None,
+            tags: BTreeSet::new(),
         }
     }
 
+    /// Names change through `ProductList::rename`, which keeps the name index
+    /// up to date.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn add_quantity(&mut self, quantity: usize) {
         self.quantity += quantity;
     }
@@ -195,14 +525,143 @@ impl Product {
         }
     }
 
+    pub fn available_quantity(&self) -> usize {
+        self.quantity.saturating_sub(self.held)
+    }
+
     pub fn set_price(&mut self, price: u64) {
         self.price = price;
     }
 
+    pub fn change_price(&mut self, price: u64, reason: Option<String>) -> u64 {
+        let old_price = self.price;
+        self.price_history.push(PriceChange {
+            timestamp: chrono::Utc::now(),
+            old_price,
+            new_price: price,
+            reason,
+        });
+        self.set_price(price);
+        old_price
+    }
+
+    pub fn schedule_price(&mut self, effective: NaiveDate, price: u64, reason: Option<String>) {
+        self.scheduled_prices.retain(|scheduled| scheduled.effective != effective);
+        self.scheduled_prices.push(ScheduledPrice {
+            effective,
+            price,
+            reason,
+        });
+        self.scheduled_prices.sort_by_key(|scheduled| scheduled.effective);
+    }
+
+    pub fn apply_scheduled_prices(&mut self, date: NaiveDate) -> usize {
+        let due: Vec<ScheduledPrice> = self
+            .scheduled_prices
+            .iter()
+            .filter(|scheduled| scheduled.effective <= date)
+            .cloned()
+            .collect();
+        self.scheduled_prices.retain(|scheduled| scheduled.effective > date);
+        for scheduled in &due {
+            let reason = scheduled
+                .reason
+                .clone()
+                .unwrap_or(format!("Scheduled for {}", scheduled.effective));
+            self.change_price(scheduled.price, Some(reason));
+        }
+        due.len()
+    }
+
     pub fn set_quality(&mut self, quality: Quality) {
         self.quality = quality;
     }
 
+    pub fn set_weight(&mut self, weight: Option<u64>) {
+        self.weight = weight;
+    }
+
+    pub fn set_category(&mut self, category: Option<&str>) {
+        self.category = category.and_then(catalogue::normalize);
+    }
+
+    pub fn in_category(&self, category: &str) -> bool {
+        match (&self.category, catalogue::normalize(category)) {
+            (Some(own), Some(category)) => catalogue::is_within(own, &category),
+            _ => false,
+        }
+    }
+
+    pub fn add_tags(&mut self, tags: &[String]) {
+        let tags = tags.iter().filter_map(|tag| catalogue::normalize(&tag.to_lowercase()));
+        self.tags.extend(tags);
+    }
+
+    pub fn remove_tags(&mut self, tags: &[String]) {
+        for tag in tags.iter().filter_map(|tag| catalogue::normalize(&tag.to_lowercase())) {
+            self.tags.remove(&tag);
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        match catalogue::normalize(tag) {
+            Some(tag) => self.tags.iter().any(|own| catalogue::is_within(own, &tag)),
+            None => false,
+        }
+    }
+
+    pub fn set_shelf_life(&mut self, shelf_life_days: Option<u64>) {
+        self.shelf_life_days = shelf_life_days;
+    }
+
+    pub fn expiry_from(&self, received: NaiveDate) -> Option<NaiveDate> {
+        self.shelf_life_days
+            .and_then(|days| received.checked_add_days(chrono::Days::new(days)))
+    }
+
+    pub fn set_reorder_policy(
+        &mut self,
+        reorder_point: Option<usize>,
+        reorder_quantity: Option<usize>,
+        max_stock: Option<usize>,
+    ) {
+        self.reorder_point = reorder_point;
+        self.reorder_quantity = reorder_quantity;
+        self.max_stock = max_stock;
+    }
+
+    pub fn suggested_order(&self, position: usize) -> Option<usize> {
+        let reorder_point = self.reorder_point?;
+        if position >= reorder_point {
+            return None;
+        }
+        let mut quantity = self.reorder_quantity.unwrap_or(reorder_point - position);
+        if let Some(max_stock) = self.max_stock {
+            quantity = quantity.min(max_stock.saturating_sub(position));
+        }
+        Some(quantity)
+    }
+
+    pub fn set_levels_required(&mut self, levels_required: usize) {
+        self.levels_required = levels_required.max(1);
+    }
+
+    pub fn footprint(&self) -> (usize, usize) {
+        match self.quality {
+            Quality::Oversized(zones) | Quality::OversizedAndFragile(zones, _) => {
+                (zones, self.levels_required)
+            }
+            _ => (1, self.levels_required),
+        }
+    }
+
+    pub fn is_oversized(&self) -> bool {
+        match self.quality {
+            Quality::Oversized(_) | Quality::OversizedAndFragile(_, _) => true,
+            _ => self.levels_required > 1,
+        }
+    }
+
     pub fn max_level(&self) -> Option<usize> {
         match self.quality {
             Quality::Fragile(maxlevel) => Some(maxlevel),
@@ -211,21 +670,23 @@ impl Product {
         }
     }
 
-    pub fn print_price(&self) {
-        println!("Price: {}", format_price(self.price));
+    pub fn print_price(&self, format: &PriceFormat) {
+        println!("Price: {}", format.format_in(self.price, self.currency.as_ref()));
     }
 }
 
 #[allow(dead_code)]
 impl ProductItem {
+    /// Builds a unit without counting it; the product's quantity only grows once
+    /// the unit is placed.
     pub fn new(
         id: u64,
-        list: &mut ProductList,
+        list: &ProductList,
         placement: (usize, usize, usize, usize),
         expiry_date: Option<NaiveDate>,
     ) -> Result<Self, Box<dyn Error>> {
         use Quality::*;
-        match list.product_mut(id) {
+        match list.product(id) {
             Some(product) => match product.quality {
                 Fragile(maxlevel) => {
                     if expiry_date.is_none() {
@@ -236,23 +697,33 @@ impl ProductItem {
                         let message = ProductError::message(LevelTooHigh, None);
                         return Err(ProductError::item(message));
                     }
-                    product.add_quantity(1);
                     Ok(ProductItem {
                         id,
                         zones_required: 1,
+                        levels_required: product.levels_required,
                         placement,
                         expiry_date,
                         timestamp: chrono::Utc::now(),
+                        weight: product.weight,
+                        lot: None,
+                        serial: None,
+                        status: ItemStatus::Available,
+                        unit_cost: None,
                     })
                 }
                 Oversized(zones_required) => {
-                    product.add_quantity(1);
                     Ok(ProductItem {
                         id,
                         placement,
                         zones_required,
+                        levels_required: product.levels_required,
                         expiry_date,
                         timestamp: chrono::Utc::now(),
+                        weight: product.weight,
+                        lot: None,
+                        serial: None,
+                        status: ItemStatus::Available,
+                        unit_cost: None,
                     })
                 }
                 OversizedAndFragile(zones_required, maxlevel) => {
@@ -264,23 +735,33 @@ impl ProductItem {
                         let message = ProductError::message(LevelTooHigh, None);
                         return Err(ProductError::item(message));
                     }
-                    product.add_quantity(1);
                     Ok(ProductItem {
                         id,
                         placement,
                         zones_required,
+                        levels_required: product.levels_required,
                         expiry_date,
                         timestamp: chrono::Utc::now(),
+                        weight: product.weight,
+                        lot: None,
+                        serial: None,
+                        status: ItemStatus::Available,
+                        unit_cost: None,
                     })
                 }
                 _ => {
-                    product.add_quantity(1);
                     Ok(ProductItem {
                         id,
                         placement,
                         zones_required: 1,
+                        levels_required: product.levels_required,
                         expiry_date,
                         timestamp: chrono::Utc::now(),
+                        weight: product.weight,
+                        lot: None,
+                        serial: None,
+                        status: ItemStatus::Available,
+                        unit_cost: None,
                     })
                 }
             },
@@ -298,6 +779,124 @@ impl ProductItem {
     pub fn set_expiration(&mut self, expiry_date: Option<NaiveDate>) {
         self.expiry_date = expiry_date;
     }
+
+    pub fn relocate(
+        &mut self,
+        list: &ProductList,
+        placement: (usize, usize, usize, usize),
+    ) -> Result<(), Box<dyn Error>> {
+        match list.product(self.id) {
+            Some(product) => {
+                if product.max_level().is_some_and(|maxlevel| placement.2 > maxlevel) {
+                    let message = ProductError::message(LevelTooHigh, None);
+                    return Err(ProductError::item(message));
+                }
+                self.placement = placement;
+                Ok(())
+            }
+            None => {
+                let message = ProductError::message(ProductNotFound, None);
+                Err(ProductError::item(message))
+            }
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.status == ItemStatus::Available
+    }
+
+    pub fn zones_occupied(&self) -> usize {
+        self.zones_required * self.levels_required
+    }
+
+    pub fn is_oversized(&self) -> bool {
+        self.zones_occupied() > 1
+    }
+}
+
+impl Delivery {
+    pub fn new(expiry_date: Option<NaiveDate>) -> Self {
+        Delivery {
+            expiry_date,
+            ..Delivery::default()
+        }
+    }
+
+    pub fn with_lot(mut self, lot: Option<String>) -> Self {
+        self.lot = lot;
+        self
+    }
+
+    pub fn with_status(mut self, status: ItemStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_serials(mut self, serials: Vec<String>) -> Self {
+        self.serials = serials.into();
+        self
+    }
+
+    pub fn with_unit_cost(mut self, unit_cost: Option<u64>) -> Self {
+        self.unit_cost = unit_cost;
+        self
+    }
+
+    pub fn of_units(units: Vec<ProductItem>) -> Self {
+        Delivery {
+            units: units.into(),
+            ..Delivery::default()
+        }
+    }
+
+    pub fn item(
+        &mut self,
+        id: u64,
+        list: &ProductList,
+        placement: (usize, usize, usize, usize),
+    ) -> Result<ProductItem, Box<dyn Error>> {
+        match self.units.pop_front() {
+            Some(mut unit) => match unit.relocate(list, placement) {
+                Ok(_) => Ok(unit),
+                Err(e) => {
+                    self.units.push_front(unit);
+                    Err(e)
+                }
+            },
+            None => {
+                let mut item = ProductItem::new(id, list, placement, self.expiry_date)?;
+                item.lot = self.lot.clone();
+                item.serial = self.serials.pop_front();
+                item.status = self.status.clone();
+                item.unit_cost = self.unit_cost;
+                Ok(item)
+            }
+        }
+    }
+
+    /// Places the next unit through `add`. New units are counted on the product
+    /// only once `add` accepts them; moved units are already counted.
+    pub fn place(
+        &mut self,
+        id: u64,
+        list: &mut ProductList,
+        placement: (usize, usize, usize, usize),
+        add: impl FnOnce(ProductItem) -> Result<(), Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let new = self.units.is_empty();
+        let item = self.item(id, list, placement)?;
+        let serial = item.serial.clone();
+        match add(item) {
+            Ok(_) if new => list.step_qty(id, 1),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                if let Some(serial) = serial.filter(|_| new) {
+                    self.serials.push_front(serial);
+                }
+                Err(e)
+            }
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -305,32 +904,89 @@ impl ProductList {
     pub fn new() -> Self {
         ProductList {
             products: HashMap::new(),
+            quality_markdowns: HashMap::new(),
+            id_allocator: IdAllocator::default(),
+            names: NameIndex::default(),
         }
     }
 
     pub fn with(products: HashMap<u64, Product>) -> Self {
-        ProductList { products }
+        ProductList {
+            names: NameIndex::build(&products),
+            products,
+            quality_markdowns: HashMap::new(),
+            id_allocator: IdAllocator::default(),
+        }
     }
 
-    pub fn add(&mut self, mut product: Product) -> Result<(), Box<dyn Error>> {
-        loop {
-            if self.products.contains_key(&product.id) {
-                product.id = generate_id();
-            } else {
-                break;
-            }
+    pub fn set_id_allocator(&mut self, id_allocator: IdAllocator) {
+        self.id_allocator = id_allocator;
+    }
+
+    pub fn add(&mut self, mut product: Product) -> Result<u64, Box<dyn Error>> {
+        let products = &self.products;
+        match self.id_allocator.allocate(|id| products.contains_key(&id)) {
+            Some(id) => product.id = id,
+            None => return self.add_with_id(product),
+        }
+        self.insert(product)
+    }
+
+    /// Adds a product keeping the id set by the caller, whatever the allocator.
+    pub fn add_with_id(&mut self, product: Product) -> Result<u64, Box<dyn Error>> {
+        if product.id == 0 {
+            let message = ProductError::message(InvalidInput, Some("- product id required".to_string()));
+            return Err(ProductError::list(message));
         }
-        if self.products.values().any(|p| p.name == product.name) {
+        if self.products.contains_key(&product.id) {
+            let message = ProductError::message(IdExists, Some(format!("- {}", product.id)));
+            return Err(ProductError::list(message));
+        }
+        self.insert(product)
+    }
+
+    fn insert(&mut self, mut product: Product) -> Result<u64, Box<dyn Error>> {
+        if self.names.exact.contains_key(&product.name) {
             let message = ProductError::message(NameExists, Some(format!("- {}", product.name)));
             return Err(ProductError::list(message));
         }
+        product.sku = normalize_sku(product.sku);
+        product.barcode = match product.barcode {
+            Some(barcode) => Some(validate_barcode(&barcode)?),
+            None => None,
+        };
+        self.check_identifiers(&product)?;
         info!("Product {} added", product.id);
-        self.products.insert(product.id, product);
+        let id = product.id;
+        self.names.insert(&product.name, id);
+        self.products.insert(id, product);
+        Ok(id)
+    }
+
+    pub fn rename(&mut self, id: u64, name: &str) -> Result<(), Box<dyn Error>> {
+        let name = name.trim();
+        if name.is_empty() {
+            let message = ProductError::message(InvalidInput, Some("- name required".to_string()));
+            return Err(ProductError::list(message));
+        }
+        if self.names.exact.get(name).is_some_and(|&other| other != id) {
+            let message = ProductError::message(NameExists, Some(format!("- {}", name)));
+            return Err(ProductError::list(message));
+        }
+        let Some(product) = self.products.get_mut(&id) else {
+            let message = ProductError::message(ProductNotFound, Some(format!("- {}", id)));
+            return Err(ProductError::list(message));
+        };
+        self.names.remove(&product.name, id);
+        self.names.insert(name, id);
+        info!("Product {} renamed from {} to {}", id, product.name, name);
+        product.name = name.to_string();
         Ok(())
     }
 
     pub fn remove_by_id(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
-        if self.products.remove(&id).is_some() {
+        if let Some(product) = self.products.remove(&id) {
+            self.names.remove(&product.name, id);
             info!("Product {} removed", id);
             Ok(())
         } else {
@@ -340,8 +996,8 @@ impl ProductList {
     }
 
     pub fn remove_by_name(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
-        let id = match self.products.values().find(|p| p.name == name) {
-            Some(product) => product.id,
+        let id = match self.names.exact.get(name) {
+            Some(&id) => id,
             None => {
                 let message = ProductError::message(ProductNotFound, Some(format!("- {}", name)));
                 return Err(ProductError::list(message));
@@ -357,6 +1013,41 @@ impl ProductList {
         self.products.get(&id)
     }
 
+    pub fn products(&self) -> impl Iterator<Item = &Product> {
+        self.products.values()
+    }
+
+    pub fn products_mut(&mut self) -> impl Iterator<Item = &mut Product> {
+        self.products.values_mut()
+    }
+
+    pub fn markdown_rules<'a>(&'a self, product: &'a Product) -> &'a [MarkdownRule] {
+        if !product.markdowns.is_empty() {
+            return &product.markdowns;
+        }
+        match self.quality_markdowns.get(&product.quality.to_string()) {
+            Some(rules) => rules,
+            None => &[],
+        }
+    }
+
+    pub fn effective_price(&self, item: &ProductItem, today: NaiveDate) -> Option<u64> {
+        let product = self.product(item.id)?;
+        let percent_off = match item.expiry_date {
+            Some(expiry_date) => {
+                let days_left = (expiry_date - today).num_days();
+                self.markdown_rules(product)
+                    .iter()
+                    .filter(|rule| days_left <= rule.days_before_expiry as i64)
+                    .map(|rule| rule.percent_off.min(100))
+                    .max()
+                    .unwrap_or(0)
+            }
+            None => 0,
+        };
+        Some(product.price * (100 - percent_off) / 100)
+    }
+
     pub fn product_mut(&mut self, id: u64) -> Option<&mut Product> {
         self.products.get_mut(&id)
     }
@@ -392,15 +1083,84 @@ impl ProductList {
     }
 
     pub fn id_from_name(&self, name: &str) -> Option<u64> {
+        self.names.exact.get(name).copied()
+    }
+
+    /// The product whose name differs only in case or spacing, as long as no
+    /// other product shares it.
+    pub fn id_from_name_ignore_case(&self, name: &str) -> Option<u64> {
+        match self.ids_from_name_ignore_case(name)[..] {
+            [id] => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn ids_from_name_ignore_case(&self, name: &str) -> Vec<u64> {
+        match self.names.folded.get(&NameIndex::fold(name)) {
+            Some(ids) => ids.iter().copied().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn id_from_sku(&self, sku: &str) -> Option<u64> {
         self.products
             .values()
-            .find(|p| p.name == name)
+            .find(|p| p.sku.as_deref().is_some_and(|own| own.eq_ignore_ascii_case(sku.trim())))
             .map(|product| product.id)
     }
 
-    pub fn list(&self) {
+    pub fn id_from_barcode(&self, barcode: &str) -> Option<u64> {
+        let key = barcode_key(barcode);
+        self.products
+            .values()
+            .find(|p| p.barcode.as_deref().is_some_and(|own| barcode_key(own) == key))
+            .map(|product| product.id)
+    }
+
+    fn check_identifiers(&self, product: &Product) -> Result<(), Box<dyn Error>> {
+        if let Some(sku) = &product.sku {
+            if self.id_from_sku(sku).is_some_and(|id| id != product.id) {
+                let message = ProductError::message(SkuExists, Some(format!("- {}", sku)));
+                return Err(ProductError::list(message));
+            }
+        }
+        if let Some(barcode) = &product.barcode {
+            if self.id_from_barcode(barcode).is_some_and(|id| id != product.id) {
+                let message = ProductError::message(BarcodeExists, Some(format!("- {}", barcode)));
+                return Err(ProductError::list(message));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_sku(&mut self, id: u64, sku: Option<String>) -> Result<(), Box<dyn Error>> {
+        let mut product = match self.products.get(&id) {
+            Some(product) => product.clone(),
+            None => return Err(ProductError::list(ProductError::message(ProductNotFound, None))),
+        };
+        product.sku = normalize_sku(sku);
+        self.check_identifiers(&product)?;
+        self.products.insert(id, product);
+        Ok(())
+    }
+
+    pub fn set_barcode(&mut self, id: u64, barcode: Option<String>) -> Result<(), Box<dyn Error>> {
+        let mut product = match self.products.get(&id) {
+            Some(product) => product.clone(),
+            None => return Err(ProductError::list(ProductError::message(ProductNotFound, None))),
+        };
+        product.barcode = match barcode {
+            Some(barcode) => Some(validate_barcode(&barcode)?),
+            None => None,
+        };
+        self.check_identifiers(&product)?;
+        self.products.insert(id, product);
+        Ok(())
+    }
+
+    pub fn list(&self, format: &PriceFormat) {
         for product in self.products.values() {
-            println!("{}", product);
+            println!("{}", product.priced(format));
         }
     }
 
@@ -411,6 +1171,20 @@ impl ProductList {
             .collect()
     }
 
+    pub fn filter_by_category(&self, category: &str) -> Vec<&Product> {
+        self.products
+            .values()
+            .filter(|product| product.in_category(category))
+            .collect()
+    }
+
+    pub fn filter_by_tag(&self, tag: &str) -> Vec<&Product> {
+        self.products
+            .values()
+            .filter(|product| product.has_tag(tag))
+            .collect()
+    }
+
     pub fn filter_by_max_price(&self, price: u64) -> Vec<&Product> {
         self.products
             .values()
@@ -426,13 +1200,31 @@ impl ProductList {
     }
 
     pub fn search_by_name(&self, string: &str) -> Vec<&Product> {
-        let string = string.to_lowercase();
-        let words: Vec<&str> = string.split_whitespace().collect();
-        self.products
-            .values()
-            .filter(|product| {
-                words.iter().all(|word| product.name.to_lowercase().contains(word))
-            })
+        self.search(string).into_iter().map(|(product, _)| product).collect()
+    }
+
+    /// Products ranked by how well their name matches, best first. Ties go to
+    /// the shorter name, then alphabetically.
+    pub fn search(&self, string: &str) -> Vec<(&Product, u32)> {
+        let mut results: Vec<(&Product, u32)> = self
+            .products()
+            .filter_map(|product| search::score(string, &product.name).map(|score| (product, score)))
+            .collect();
+        results.sort_by(|(a, a_score), (b, b_score)| {
+            b_score
+                .cmp(a_score)
+                .then(a.name.len().cmp(&b.name.len()))
+                .then(a.name.cmp(&b.name))
+                .then(a.id.cmp(&b.id))
+        });
+        results
+    }
+
+    pub fn suggestions(&self, name: &str, limit: usize) -> Vec<&str> {
+        self.search(name)
+            .into_iter()
+            .take(limit)
+            .map(|(product, _)| product.name.as_str())
             .collect()
     }
 }
@@ -453,6 +1245,48 @@ impl Default for ProductList {
     }
 }
 
+fn single_level() -> usize {
+    1
+}
+
+/// Checks a GTIN-8, UPC-A (GTIN-12), EAN-13 or GTIN-14 code and its mod-10
+/// check digit, returning the code without surrounding whitespace.
+pub fn validate_barcode(barcode: &str) -> Result<String, Box<dyn Error>> {
+    let barcode = barcode.trim();
+    let invalid = || {
+        let message = ProductError::message(InvalidBarcode, Some(format!("- {}", barcode)));
+        ProductError::product(message)
+    };
+    if !matches!(barcode.len(), 8 | 12 | 13 | 14) {
+        return Err(invalid());
+    }
+    let digits = match barcode.chars().map(|c| c.to_digit(10)).collect::<Option<Vec<u32>>>() {
+        Some(digits) => digits,
+        None => return Err(invalid()),
+    };
+    let (check, body) = digits.split_last().unwrap();
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(position, digit)| if position % 2 == 0 { digit * 3 } else { *digit })
+        .sum();
+    match (10 - sum % 10) % 10 == *check {
+        true => Ok(barcode.to_string()),
+        false => Err(invalid()),
+    }
+}
+
+// UPC-A and EAN-13 are the same GTIN once padded to 14 digits.
+fn barcode_key(barcode: &str) -> String {
+    format!("{:0>14}", barcode.trim())
+}
+
+// SKUs are stored without surrounding whitespace, and a blank one is no SKU.
+fn normalize_sku(sku: Option<String>) -> Option<String> {
+    sku.map(|sku| sku.trim().to_string()).filter(|sku| !sku.is_empty())
+}
+
 fn generate_id() -> u64 {
     let mut random = rand::thread_rng();
     let id: u64 = random.gen_range(100000..999999);
@@ -0,0 +1,234 @@
+use crate::currency::{PriceFormat, Priced};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+use ErrorMessage::*;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Supplier {
+    pub id: u64,
+    pub name: String,
+    pub contact: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PurchaseOrderLine {
+    pub product_id: u64,
+    pub quantity: usize,
+    pub unit_cost: u64,
+    pub expected_date: Option<NaiveDate>,
+    pub received: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PurchaseOrderStatus {
+    Open,
+    PartiallyReceived,
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PurchaseOrder {
+    pub id: u64,
+    pub supplier_id: u64,
+    pub created: NaiveDate,
+    pub lines: Vec<PurchaseOrderLine>,
+    pub status: PurchaseOrderStatus,
+}
+
+#[derive(Debug)]
+pub enum ErrorMessage {
+    SupplierNotFound,
+    PurchaseOrderNotFound,
+    LineNotFound,
+    PurchaseOrderClosed,
+    NothingToReceive,
+}
+
+#[derive(Debug)]
+pub(crate) struct PurchasingError {
+    message: String,
+}
+
+impl ErrorMessage {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SupplierNotFound => "Supplier not found",
+            PurchaseOrderNotFound => "Purchase order not found",
+            LineNotFound => "Purchase order line not found",
+            PurchaseOrderClosed => "Purchase order is closed",
+            NothingToReceive => "Nothing left to receive",
+        }
+    }
+}
+
+impl Display for ErrorMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Display for PurchasingError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Purchasing Error: {}", self.message)
+    }
+}
+
+impl Error for PurchasingError {}
+
+impl PurchasingError {
+    pub fn boxed(message: String) -> Box<dyn Error> {
+        Box::new(PurchasingError { message })
+    }
+
+    pub fn with_id(message: ErrorMessage, id: u64) -> Box<dyn Error> {
+        PurchasingError::boxed(format!("{} - {}", message, id))
+    }
+}
+
+impl Display for Supplier {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Supplier {}: {}", self.id, self.name)?;
+        if let Some(contact) = &self.contact {
+            write!(f, " ({})", contact)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for PurchaseOrderStatus {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PurchaseOrderStatus::Open => write!(f, "open"),
+            PurchaseOrderStatus::PartiallyReceived => write!(f, "partially received"),
+            PurchaseOrderStatus::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+impl Priced for PurchaseOrderLine {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        let expected = match self.expected_date {
+            Some(date) => date.to_string(),
+            None => "N/A".to_string(),
+        };
+        write!(
+            f,
+            "Product {}: {}/{} received, Unit Cost: {}, Expected: {}",
+            self.product_id,
+            self.received,
+            self.quantity,
+            format.format(self.unit_cost),
+            expected
+        )?;
+        match self.variance() {
+            variance if variance > 0 => write!(f, ", over by {}", variance),
+            variance if variance < 0 && self.received > 0 => write!(f, ", short by {}", -variance),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Priced for PurchaseOrder {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        write!(
+            f,
+            "Purchase order {} from supplier {}, created {}, {}",
+            self.id, self.supplier_id, self.created, self.status
+        )?;
+        for (number, line) in self.lines.iter().enumerate() {
+            write!(f, "\n  {}. {}", number + 1, line.priced(format))?;
+        }
+        Ok(())
+    }
+}
+
+impl Supplier {
+    pub fn new(id: u64, name: &str, contact: Option<String>) -> Self {
+        Supplier {
+            id,
+            name: name.to_string(),
+            contact,
+        }
+    }
+}
+
+impl PurchaseOrderLine {
+    pub fn new(
+        product_id: u64,
+        quantity: usize,
+        unit_cost: u64,
+        expected_date: Option<NaiveDate>,
+    ) -> Self {
+        PurchaseOrderLine {
+            product_id,
+            quantity,
+            unit_cost,
+            expected_date,
+            received: 0,
+        }
+    }
+
+    pub fn outstanding(&self) -> usize {
+        self.quantity.saturating_sub(self.received)
+    }
+
+    pub fn variance(&self) -> isize {
+        self.received as isize - self.quantity as isize
+    }
+}
+
+impl PurchaseOrder {
+    pub fn new(id: u64, supplier_id: u64, created: NaiveDate) -> Self {
+        PurchaseOrder {
+            id,
+            supplier_id,
+            created,
+            lines: Vec::new(),
+            status: PurchaseOrderStatus::Open,
+        }
+    }
+
+    pub fn add_line(&mut self, line: PurchaseOrderLine) -> Result<usize, Box<dyn Error>> {
+        if self.status == PurchaseOrderStatus::Closed {
+            return Err(PurchasingError::with_id(PurchaseOrderClosed, self.id));
+        }
+        self.lines.push(line);
+        Ok(self.lines.len())
+    }
+
+    pub fn line(&self, number: usize) -> Result<&PurchaseOrderLine, Box<dyn Error>> {
+        match number.checked_sub(1).and_then(|index| self.lines.get(index)) {
+            Some(line) => Ok(line),
+            None => Err(PurchasingError::with_id(LineNotFound, number as u64)),
+        }
+    }
+
+    pub fn record_receipt(&mut self, number: usize, quantity: usize) -> Result<(), Box<dyn Error>> {
+        if self.status == PurchaseOrderStatus::Closed {
+            return Err(PurchasingError::with_id(PurchaseOrderClosed, self.id));
+        }
+        match number.checked_sub(1).and_then(|index| self.lines.get_mut(index)) {
+            Some(line) => line.received += quantity,
+            None => return Err(PurchasingError::with_id(LineNotFound, number as u64)),
+        }
+        self.status = if self.lines.iter().all(|line| line.outstanding() == 0) {
+            PurchaseOrderStatus::Closed
+        } else {
+            PurchaseOrderStatus::PartiallyReceived
+        };
+        Ok(())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.status != PurchaseOrderStatus::Closed
+    }
+}
+
+pub fn next_id<T>(records: &BTreeMap<u64, T>) -> u64 {
+    records.keys().next_back().map_or(1, |id| id + 1)
+}
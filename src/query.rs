@@ -0,0 +1,637 @@
+use crate::{
+    currency::PriceFormat,
+    product::{ItemStatus, Product, ProductItem, ProductList},
+};
+use chrono::NaiveDate;
+use std::{
+    cmp::Ordering,
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+use ErrorMessage::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    Name,
+    Price,
+    Quantity,
+    Available,
+    Held,
+    Quality,
+    Category,
+    Tag,
+    Sku,
+    Barcode,
+    Weight,
+    Expiry,
+    Lot,
+    Serial,
+    Status,
+    Row,
+    Shelf,
+    Level,
+    Zone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    All,
+    Compare(Field, Op, String),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+/// A filter over products or warehouse items, with an optional sort and limit.
+/// Item queries can also use product fields, which refer to the item's product.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub filter: Expr,
+    pub sort: Option<(Field, bool)>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Value {
+    Number(Option<u64>),
+    Date(Option<NaiveDate>),
+    Text(Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum ErrorMessage {
+    UnknownField,
+    UnknownOperator,
+    UnknownSortOrder,
+    InvalidValue,
+    UnexpectedToken,
+    UnexpectedEnd,
+    ItemField,
+}
+
+#[derive(Debug)]
+pub(crate) struct QueryError {
+    message: String,
+}
+
+impl ErrorMessage {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            UnknownField => "Unknown field",
+            UnknownOperator => "Unknown operator",
+            UnknownSortOrder => "Sort order must be asc or desc",
+            InvalidValue => "Invalid value for field",
+            UnexpectedToken => "Unexpected token",
+            UnexpectedEnd => "Unexpected end of query",
+            ItemField => "Field only applies to items",
+        }
+    }
+}
+
+impl Display for ErrorMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Query Error: {}", self.message)
+    }
+}
+
+impl Error for QueryError {}
+
+impl QueryError {
+    pub fn boxed(message: String) -> Box<dyn Error> {
+        Box::new(QueryError { message })
+    }
+
+    pub fn with_value(message: ErrorMessage, value: &str) -> Box<dyn Error> {
+        QueryError::boxed(format!("{} - {}", message, value))
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let op = match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Contains => "~",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Expr::All => write!(f, "all"),
+            Expr::Compare(field, op, value) => write!(f, "{}{}\"{}\"", field, op, value),
+            Expr::And(left, right) => write!(f, "({} and {})", left, right),
+            Expr::Or(left, right) => write!(f, "({} or {})", left, right),
+            Expr::Not(expr) => write!(f, "not {}", expr),
+        }
+    }
+}
+
+impl Field {
+    pub fn from_name(name: &str) -> Result<Self, Box<dyn Error>> {
+        match name.to_lowercase().as_str() {
+            "id" => Ok(Field::Id),
+            "name" => Ok(Field::Name),
+            "price" => Ok(Field::Price),
+            "qty" | "quantity" => Ok(Field::Quantity),
+            "available" => Ok(Field::Available),
+            "held" => Ok(Field::Held),
+            "quality" => Ok(Field::Quality),
+            "category" => Ok(Field::Category),
+            "tag" | "tags" => Ok(Field::Tag),
+            "sku" => Ok(Field::Sku),
+            "barcode" => Ok(Field::Barcode),
+            "weight" => Ok(Field::Weight),
+            "expiry" | "expires" => Ok(Field::Expiry),
+            "lot" => Ok(Field::Lot),
+            "serial" => Ok(Field::Serial),
+            "status" => Ok(Field::Status),
+            "row" => Ok(Field::Row),
+            "shelf" => Ok(Field::Shelf),
+            "level" => Ok(Field::Level),
+            "zone" => Ok(Field::Zone),
+            _ => Err(QueryError::with_value(UnknownField, name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Name => "name",
+            Field::Price => "price",
+            Field::Quantity => "qty",
+            Field::Available => "available",
+            Field::Held => "held",
+            Field::Quality => "quality",
+            Field::Category => "category",
+            Field::Tag => "tag",
+            Field::Sku => "sku",
+            Field::Barcode => "barcode",
+            Field::Weight => "weight",
+            Field::Expiry => "expiry",
+            Field::Lot => "lot",
+            Field::Serial => "serial",
+            Field::Status => "status",
+            Field::Row => "row",
+            Field::Shelf => "shelf",
+            Field::Level => "level",
+            Field::Zone => "zone",
+        }
+    }
+
+    pub fn is_item_field(&self) -> bool {
+        matches!(
+            self,
+            Field::Expiry
+                | Field::Lot
+                | Field::Serial
+                | Field::Status
+                | Field::Row
+                | Field::Shelf
+                | Field::Level
+                | Field::Zone
+        )
+    }
+
+    fn value(&self, product: &Product, item: Option<&ProductItem>) -> Value {
+        let placement = item.map(|item| item.placement);
+        let text = |text: Option<&str>| Value::Text(text.map(str::to_lowercase));
+        match self {
+            Field::Id => Value::Number(Some(product.id)),
+            Field::Name => text(Some(product.name())),
+            Field::Price => Value::Number(Some(product.price)),
+            Field::Quantity => Value::Number(Some(product.quantity as u64)),
+            Field::Available => Value::Number(Some(product.available_quantity() as u64)),
+            Field::Held => Value::Number(Some(product.held as u64)),
+            Field::Quality => text(Some(&product.quality.to_string())),
+            Field::Category => text(product.category.as_deref()),
+            Field::Tag => text(product.tags.iter().next().map(String::as_str)),
+            Field::Sku => text(product.sku.as_deref()),
+            Field::Barcode => text(product.barcode.as_deref()),
+            Field::Weight => Value::Number(item.and_then(|item| item.weight).or(product.weight)),
+            Field::Expiry => Value::Date(item.and_then(|item| item.expiry_date)),
+            Field::Lot => text(item.and_then(|item| item.lot.as_deref())),
+            Field::Serial => text(item.and_then(|item| item.serial.as_deref())),
+            Field::Status => text(item.map(|item| item.status.name())),
+            Field::Row => Value::Number(placement.map(|p| p.0 as u64)),
+            Field::Shelf => Value::Number(placement.map(|p| p.1 as u64)),
+            Field::Level => Value::Number(placement.map(|p| p.2 as u64)),
+            Field::Zone => Value::Number(placement.map(|p| p.3 as u64)),
+        }
+    }
+
+    // Prices are read in the product's own currency, so `price<=5` means five
+    // units of whatever the product is priced in.
+    fn literal(&self, input: &str, format: &PriceFormat) -> Result<Value, Box<dyn Error>> {
+        let invalid = || QueryError::with_value(InvalidValue, &format!("{}={}", self, input));
+        match self {
+            Field::Price => Ok(Value::Number(Some(format.parse(input).map_err(|_| invalid())?))),
+            Field::Id
+            | Field::Quantity
+            | Field::Available
+            | Field::Held
+            | Field::Weight
+            | Field::Row
+            | Field::Shelf
+            | Field::Level
+            | Field::Zone => Ok(Value::Number(Some(input.parse().map_err(|_| invalid())?))),
+            Field::Expiry => Ok(Value::Date(Some(parse_date(input).ok_or_else(invalid)?))),
+            Field::Quality => Ok(Value::Text(Some(input.to_lowercase().replace(['-', '_'], " ")))),
+            Field::Status => Ok(Value::Text(Some(ItemStatus::normalize(input)))),
+            _ => Ok(Value::Text(Some(input.to_lowercase()))),
+        }
+    }
+}
+
+impl Op {
+    fn from_symbol(symbol: &str) -> Result<Self, Box<dyn Error>> {
+        match symbol {
+            "=" | "==" => Ok(Op::Eq),
+            "!=" => Ok(Op::Ne),
+            "<" => Ok(Op::Lt),
+            "<=" => Ok(Op::Le),
+            ">" => Ok(Op::Gt),
+            ">=" => Ok(Op::Ge),
+            "~" => Ok(Op::Contains),
+            _ => Err(QueryError::with_value(UnknownOperator, symbol)),
+        }
+    }
+
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq | Op::Contains => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl Expr {
+    pub fn compare(field: Field, op: Op, value: &str, format: &PriceFormat) -> Result<Self, Box<dyn Error>> {
+        field.literal(value, format)?;
+        Ok(Expr::Compare(field, op, value.to_string()))
+    }
+
+    pub fn and(self, other: Expr) -> Self {
+        match (self, other) {
+            (Expr::All, expr) | (expr, Expr::All) => expr,
+            (left, right) => Expr::And(Box::new(left), Box::new(right)),
+        }
+    }
+
+    pub fn or(self, other: Expr) -> Self {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+
+    pub fn negate(self) -> Self {
+        Expr::Not(Box::new(self))
+    }
+
+    pub fn fields(&self) -> Vec<Field> {
+        match self {
+            Expr::All => Vec::new(),
+            Expr::Compare(field, _, _) => vec![*field],
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                let mut fields = left.fields();
+                fields.extend(right.fields());
+                fields
+            }
+            Expr::Not(expr) => expr.fields(),
+        }
+    }
+
+    pub fn matches(&self, product: &Product, item: Option<&ProductItem>, format: &PriceFormat) -> bool {
+        match self {
+            Expr::All => true,
+            Expr::Compare(field, op, input) => compare(*field, *op, input, product, item, format),
+            Expr::And(left, right) => {
+                left.matches(product, item, format) && right.matches(product, item, format)
+            }
+            Expr::Or(left, right) => {
+                left.matches(product, item, format) || right.matches(product, item, format)
+            }
+            Expr::Not(expr) => !expr.matches(product, item, format),
+        }
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query::new()
+    }
+}
+
+impl Query {
+    pub fn new() -> Self {
+        Query {
+            filter: Expr::All,
+            sort: None,
+            limit: None,
+        }
+    }
+
+    /// Parses expressions such as `price<=5 and (quality=fragile or tag=cold)`.
+    /// Values with spaces can be quoted; `and`, `or` and `not` are keywords.
+    pub fn parse(input: &str, format: &PriceFormat) -> Result<Self, Box<dyn Error>> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Ok(Query::new());
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            format,
+        };
+        let filter = parser.or()?;
+        match parser.next() {
+            Some(token) => Err(unexpected(Some(token))),
+            None => Ok(Query {
+                filter,
+                ..Query::new()
+            }),
+        }
+    }
+
+    pub fn and(mut self, expr: Expr) -> Self {
+        self.filter = std::mem::replace(&mut self.filter, Expr::All).and(expr);
+        self
+    }
+
+    pub fn sort(mut self, field: Field, descending: bool) -> Self {
+        self.sort = Some((field, descending));
+        self
+    }
+
+    pub fn sort_by(self, field: &str, order: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let descending = match order.map(str::to_lowercase).as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(order) => return Err(QueryError::with_value(UnknownSortOrder, order)),
+        };
+        Ok(self.sort(Field::from_name(field)?, descending))
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn products<'a>(
+        &self,
+        list: &'a ProductList,
+        format: &PriceFormat,
+    ) -> Result<Vec<&'a Product>, Box<dyn Error>> {
+        let sort_fields = self.sort.iter().map(|(field, _)| *field);
+        if let Some(field) = self.filter.fields().into_iter().chain(sort_fields).find(Field::is_item_field) {
+            return Err(QueryError::with_value(ItemField, field.name()));
+        }
+        let mut products: Vec<&Product> = list
+            .products()
+            .filter(|product| self.filter.matches(product, None, format))
+            .collect();
+        products.sort_by_key(|product| product.id);
+        if let Some((field, descending)) = self.sort {
+            products.sort_by(|a, b| order(field.value(a, None).cmp(&field.value(b, None)), descending));
+        }
+        products.truncate(self.limit.unwrap_or(usize::MAX));
+        Ok(products)
+    }
+
+    pub fn items(&self, list: &ProductList, items: Vec<ProductItem>, format: &PriceFormat) -> Vec<ProductItem> {
+        let mut items: Vec<(&Product, ProductItem)> = items
+            .into_iter()
+            .filter_map(|item| list.product(item.id).map(|product| (product, item)))
+            .filter(|(product, item)| self.filter.matches(product, Some(item), format))
+            .collect();
+        if let Some((field, descending)) = self.sort {
+            items.sort_by(|(a, a_item), (b, b_item)| {
+                order(field.value(a, Some(a_item)).cmp(&field.value(b, Some(b_item))), descending)
+            });
+        }
+        items
+            .into_iter()
+            .map(|(_, item)| item)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+fn order(ordering: Ordering, descending: bool) -> Ordering {
+    match descending {
+        true => ordering.reverse(),
+        false => ordering,
+    }
+}
+
+fn compare(
+    field: Field,
+    op: Op,
+    input: &str,
+    product: &Product,
+    item: Option<&ProductItem>,
+    format: &PriceFormat,
+) -> bool {
+    let format = format.with_currency(product.currency.as_ref());
+    let Ok(literal) = field.literal(input, &format) else {
+        return false;
+    };
+    match (field, op, field.value(product, item), literal) {
+        (Field::Category, Op::Eq | Op::Ne, _, _) => {
+            (op == Op::Eq) == product.in_category(input)
+        }
+        (Field::Tag, Op::Eq | Op::Ne, _, _) => (op == Op::Eq) == product.has_tag(input),
+        (Field::Tag, Op::Contains, _, Value::Text(Some(word))) => {
+            product.tags.iter().any(|tag| tag.to_lowercase().contains(&word))
+        }
+        (_, Op::Contains, Value::Text(Some(text)), Value::Text(Some(words))) => {
+            words.split_whitespace().all(|word| text.contains(word))
+        }
+        (_, Op::Contains, _, _) => false,
+        (_, Op::Ne, Value::Text(None), _) => true,
+        (_, _, Value::Number(None) | Value::Date(None) | Value::Text(None), _) => false,
+        (_, op, value, literal) => op.holds(value.cmp(&literal)),
+    }
+}
+
+/// Accepts `YYYY-MM-DD`, `today`, or an offset such as `today+7`.
+fn parse_date(input: &str) -> Option<NaiveDate> {
+    let today = chrono::Local::now().naive_local().date();
+    let input = input.to_lowercase();
+    match input.strip_prefix("today") {
+        Some("") => Some(today),
+        Some(offset) => {
+            let days: i64 = offset.strip_prefix('+').unwrap_or(offset).parse().ok()?;
+            Some(today + chrono::Duration::days(days))
+        }
+        None => NaiveDate::parse_from_str(&input, "%Y-%m-%d").ok(),
+    }
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let is_op = |c: char| matches!(c, '=' | '!' | '<' | '>' | '~');
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let word: String = chars.by_ref().take_while(|&next| next != c).collect();
+                tokens.push(Token::Word(word));
+            }
+            c if is_op(c) => {
+                let mut symbol = String::new();
+                while let Some(&next) = chars.peek().filter(|&&next| is_op(next)) {
+                    symbol.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Op(Op::from_symbol(&symbol)?));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&next) = chars
+                    .peek()
+                    .filter(|&&next| !next.is_whitespace() && !is_op(next) && next != '(' && next != ')')
+                {
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    format: &'a PriceFormat,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = expr.or(self.and()?);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, Box<dyn Error>> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, Box<dyn Error>> {
+        if self.keyword("not") {
+            return Ok(self.unary()?.negate());
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    token => Err(unexpected(token)),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let field = Field::from_name(&field)?;
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    token => return Err(unexpected(token)),
+                };
+                let value = match self.next() {
+                    Some(Token::Word(value)) => value,
+                    token => return Err(unexpected(token)),
+                };
+                Expr::compare(field, op, &value, self.format)
+            }
+            token => Err(unexpected(token)),
+        }
+    }
+}
+
+fn unexpected(token: Option<Token>) -> Box<dyn Error> {
+    match token {
+        Some(token) => QueryError::with_value(UnexpectedToken, &token.to_string()),
+        None => QueryError::boxed(UnexpectedEnd.to_string()),
+    }
+}
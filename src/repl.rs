@@ -1,16 +1,24 @@
 use {
     crate::{
+        alerts::{AlertDigest, CommandNotifier, Notifier},
+        currency::{Currency, PriceFormat, Priced},
         inventory::Storage,
-        product::Quality,
-        warehouse::Warehouse,
+        product::{Delivery, IdAllocator, ItemStatus, MarkdownRule, Product, Quality},
+        purchasing::PurchaseOrderLine,
+        query::{Expr, Field, Op, Query},
+        returns::InspectionOutcome,
+        valuation::CostMethod,
+        warehouse::{self, Warehouse},
     },
     chrono::NaiveDate,
-    clap::{crate_name, Args, Parser, Subcommand},
+    clap::{crate_name, Args, Parser, Subcommand, ValueEnum},
     std::{
         error::Error,
         fmt::{self, Display, Formatter},
+        fs,
         io::{stdin, stdout, Write},
         path::Path,
+        str::FromStr,
     },
     ErrorMessage::*,
 };
@@ -37,38 +45,162 @@ enum Commands {
     #[command(subcommand_required = true)]
     Add {
         name: String,
-        price: u64,
+        price: String,
+        #[arg(long, short)]
+        currency: Option<String>,
+        #[arg(long)]
+        id: Option<u64>,
+        #[arg(long)]
+        sku: Option<String>,
+        #[arg(long)]
+        barcode: Option<String>,
+        #[arg(long)]
+        category: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        #[arg(long, short)]
+        weight: Option<u64>,
+        #[arg(long, short)]
+        levels: Option<usize>,
+        #[arg(long)]
+        shelf_life: Option<u64>,
         #[clap(subcommand)]
         quality: QualityOptions,
     },
     Delete {
-        #[arg(required_unless_present = "name")]
-        id: Option<u64>,
-        #[arg(long, short)]
-        name: Option<String>,
+        #[command(flatten)]
+        product: ProductSelector,
     },
-    #[command(subcommand_required = true)]
     Remove {
-        #[arg(required_unless_present = "name")]
-        id: Option<u64>,
-        #[arg(long, short)]
-        name: Option<String>,
-        #[arg(required = true)]
-        quantity: usize,
+        #[command(flatten)]
+        product: ProductSelector,
+        quantity: Option<usize>,
+        #[clap(short, long, conflicts_with_all = ["id", "name", "sku", "barcode", "quantity"])]
+        serial: Option<String>,
     },
     #[command(subcommand_required = true)]
     Change(ChangeCommands),
     Restock {
-        #[arg(required_unless_present = "name")]
-        id: Option<u64>,
-        #[arg(long, short)]
-        name: Option<String>,
-        #[arg(required = true)]
-        quantity: usize,
+        #[command(flatten)]
+        product: ProductSelector,
+        quantity: Option<usize>,
+        #[clap(short, long)]
+        expiration_date: Option<NaiveDate>,
+        #[clap(short, long)]
+        lot: Option<String>,
+        #[clap(short, long)]
+        serials: Option<String>,
+        #[clap(short, long)]
+        cost: Option<String>,
+    },
+    Find(FindCommands),
+    #[command(subcommand_required = true)]
+    Price(PriceCommands),
+    #[command(subcommand_required = true)]
+    Markdown(MarkdownCommands),
+    SetStatus {
+        row: usize,
+        shelf: usize,
+        level: usize,
+        zone: usize,
+        #[clap(subcommand)]
+        status: StatusOptions,
+    },
+    Dispose {
+        #[clap(long, required = true)]
+        expired: bool,
+        #[clap(long)]
+        before: Option<NaiveDate>,
+        #[clap(long)]
+        dry_run: bool,
+    },
+    WriteOffs {
+        #[clap(long)]
+        month: Option<String>,
+    },
+    Replenish,
+    #[command(subcommand_required = true)]
+    Supplier(SupplierCommands),
+    #[command(subcommand_required = true)]
+    Po(PurchaseOrderCommands),
+    #[command(subcommand_required = true)]
+    Rma(ReturnCommands),
+    Journal {
+        #[command(flatten)]
+        product: ProductSelector,
+    },
+    Currency {
+        code: Option<String>,
+    },
+    #[command(name = "id-allocator")]
+    Allocator {
+        #[clap(value_enum)]
+        allocator: Option<AllocatorOptions>,
+        #[clap(long, requires = "allocator")]
+        next: Option<u64>,
+    },
+    Scan {
+        #[clap(value_enum)]
+        action: ScanAction,
         #[clap(short, long)]
         expiration_date: Option<NaiveDate>,
+        #[clap(short, long)]
+        lot: Option<String>,
+    },
+    Locale {
+        tag: Option<String>,
+    },
+    Valuation {
+        #[command(flatten)]
+        product: ProductSelector,
+        #[clap(short, long, value_enum, default_value = "fifo")]
+        method: CostMethodOptions,
+    },
+    Receive {
+        po: u64,
+        #[clap(long)]
+        line: Option<usize>,
+        #[clap(long, requires = "line")]
+        qty: Option<usize>,
+        #[clap(long)]
+        expiry: Option<NaiveDate>,
+        #[clap(long)]
+        lot: Option<String>,
+    },
+    Alerts {
+        #[clap(long, value_delimiter = ',', default_values_t = [1, 7, 30])]
+        horizons: Vec<u64>,
+        #[clap(long, default_value_t = 5)]
+        low_stock: usize,
+        #[clap(short, long, value_enum, default_value_t = AlertFormat::Text)]
+        format: AlertFormat,
+        #[clap(long)]
+        notify: Option<String>,
+    },
+    Recall {
+        lot: String,
+        #[clap(short, long)]
+        remove: bool,
     },
     List(ListCommands),
+    Capacity,
+    #[command(subcommand_required = true)]
+    Warehouse(WarehouseCommands),
+    Transfer {
+        #[command(flatten)]
+        product: ProductSelector,
+        quantity: Option<usize>,
+        #[arg(long, short)]
+        from: Option<String>,
+        #[arg(long, short)]
+        to: String,
+    },
+    Move {
+        #[arg(num_args = 4, value_names = ["ROW", "SHELF", "LEVEL", "ZONE"])]
+        from: Vec<usize>,
+        #[arg(long, short, num_args = 4, value_names = ["ROW", "SHELF", "LEVEL", "ZONE"])]
+        to: Vec<usize>,
+    },
     CreateStorage,
     Load {
         file_path: String,
@@ -80,6 +212,13 @@ enum Commands {
     ForceExit,
 }
 
+#[derive(Debug, Clone, ValueEnum)]
+enum AlertFormat {
+    Text,
+    Json,
+    Email,
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum ErrorMessage {
@@ -172,9 +311,64 @@ pub struct ListCommands {
     cmd: ListSubcommands,
 }
 
+#[derive(Debug, Args)]
+pub struct FindCommands {
+    #[clap(subcommand)]
+    cmd: FindSubcommands,
+}
+
+#[derive(Debug, Args)]
+pub struct PriceCommands {
+    #[clap(subcommand)]
+    cmd: PriceSubcommands,
+}
+
+#[derive(Debug, Args)]
+pub struct MarkdownCommands {
+    #[clap(subcommand)]
+    cmd: MarkdownSubcommands,
+}
+
+#[derive(Debug, Args)]
+pub struct SupplierCommands {
+    #[clap(subcommand)]
+    cmd: SupplierSubcommands,
+}
+
+#[derive(Debug, Args)]
+pub struct PurchaseOrderCommands {
+    #[clap(subcommand)]
+    cmd: PurchaseOrderSubcommands,
+}
+
+#[derive(Debug, Args)]
+pub struct ReturnCommands {
+    #[clap(subcommand)]
+    cmd: ReturnSubcommands,
+}
+
+#[derive(Debug, Args)]
+pub struct WarehouseCommands {
+    #[clap(subcommand)]
+    cmd: WarehouseSubcommands,
+}
+
 #[derive(Debug, Args)]
 struct ProductArgs {}
 
+// A product given by ID (or name), or with `--name`, `--sku` or `--barcode`.
+#[derive(Debug, Args)]
+struct ProductSelector {
+    #[arg(value_name = "ID")]
+    id: Option<String>,
+    #[arg(long, short, conflicts_with_all = ["sku", "barcode"])]
+    name: Option<String>,
+    #[arg(long, conflicts_with = "barcode")]
+    sku: Option<String>,
+    #[arg(long)]
+    barcode: Option<String>,
+}
+
 #[derive(Debug, Args)]
 struct RowArgs {
     #[clap(short, long)]
@@ -192,14 +386,25 @@ struct ShelfArgs {
 enum ChangeSubcommands {
     Name(NameArgs),
     Price(PriceArgs),
+    Currency(CurrencyArgs),
+    Sku(SkuArgs),
+    Barcode(BarcodeArgs),
+    Category(CategoryArgs),
+    Tags(TagsArgs),
     #[clap(subcommand)]
     Quality(QualityOptions),
+    Weight(WeightArgs),
+    ShelfLife(ShelfLifeArgs),
+    Reorder(ReorderArgs),
+    LoadRating(LoadRatingArgs),
 }
 
 #[derive(Debug, Args)]
 struct NameArgs {
-    id: u64,
-    name: String,
+    #[command(flatten)]
+    product: ProductSelector,
+    #[arg(value_name = "NEW_NAME")]
+    new_name: Vec<String>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -210,6 +415,17 @@ enum QualityOptions {
     OversizedAndFragile(QualityArgs),
 }
 
+#[derive(Debug, Subcommand)]
+enum StatusOptions {
+    Available,
+    Quarantined,
+    Damaged,
+    OnHold {
+        #[arg(required = true, num_args = 1..)]
+        reason: Vec<String>,
+    },
+}
+
 #[derive(Debug, Args)]
 struct OversizedArgs {
     zones: usize,
@@ -228,17 +444,246 @@ struct QualityArgs {
 
 #[derive(Debug, Args)]
 struct PriceArgs {
-    #[arg(required_unless_present = "name")]
-    id: Option<u64>,
+    #[command(flatten)]
+    product: ProductSelector,
+    price: Option<String>,
     #[arg(long, short)]
-    name: Option<String>,
-    price: u64,
+    effective: Option<NaiveDate>,
+    #[arg(long, short, num_args = 1..)]
+    reason: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct WeightArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    weight: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+struct CurrencyArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    code: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct SkuArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    #[arg(value_name = "SKU")]
+    new_sku: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct BarcodeArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    #[arg(value_name = "BARCODE")]
+    new_barcode: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct CategoryArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    category: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct TagsArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    #[clap(long, value_delimiter = ',')]
+    add: Vec<String>,
+    #[clap(long, value_delimiter = ',')]
+    remove: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+struct ShelfLifeArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    days: Option<u64>,
+}
+
+#[derive(Debug, Args)]
+struct ReorderArgs {
+    #[command(flatten)]
+    product: ProductSelector,
+    #[arg(long, short)]
+    point: Option<usize>,
+    #[arg(long, short)]
+    quantity: Option<usize>,
+    #[arg(long, short)]
+    max: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct LoadRatingArgs {
+    row: usize,
+    shelf: usize,
+    #[arg(long, short)]
+    level: Option<usize>,
+    load_rating: Option<u64>,
+}
+
+#[derive(Debug, Subcommand)]
+enum WarehouseSubcommands {
+    Add(WarehouseArgs),
+    Remove { name: String },
+    Use { name: String },
+    List,
+    Stock {
+        #[command(flatten)]
+        product: ProductSelector,
+    },
+}
+
+#[derive(Debug, Args)]
+struct WarehouseArgs {
+    name: String,
+    #[clap(long, requires_all = ["shelves", "levels", "zones"])]
+    rows: Option<usize>,
+    #[clap(long)]
+    shelves: Option<usize>,
+    #[clap(long)]
+    levels: Option<usize>,
+    #[clap(long)]
+    zones: Option<usize>,
+}
+
+#[derive(Debug, Subcommand)]
+enum FindSubcommands {
+    Serial {
+        serial: String,
+    },
+    Product {
+        #[clap(required = true)]
+        name: Vec<String>,
+        #[clap(short, long, default_value_t = 10)]
+        limit: usize,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum PriceSubcommands {
+    History {
+        #[command(flatten)]
+        product: ProductSelector,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum MarkdownSubcommands {
+    Add {
+        #[command(flatten)]
+        product: ProductSelector,
+        #[clap(short, long)]
+        quality: Option<String>,
+        #[clap(short, long)]
+        percent: u64,
+        #[clap(short, long)]
+        days: u64,
+    },
+    Clear {
+        #[command(flatten)]
+        product: ProductSelector,
+        #[clap(short, long)]
+        quality: Option<String>,
+    },
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+enum SupplierSubcommands {
+    Add {
+        name: String,
+        #[clap(short, long)]
+        contact: Option<String>,
+    },
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+enum PurchaseOrderSubcommands {
+    Create {
+        supplier: u64,
+    },
+    AddLine {
+        po: u64,
+        #[command(flatten)]
+        product: ProductSelector,
+        quantity: Option<usize>,
+        #[clap(short, long)]
+        cost: String,
+        #[clap(short, long)]
+        expected: Option<NaiveDate>,
+    },
+    List {
+        #[clap(long)]
+        open: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ReturnSubcommands {
+    Create {
+        #[command(flatten)]
+        product: ProductSelector,
+        quantity: Option<usize>,
+        #[clap(short, long)]
+        expiry: Option<NaiveDate>,
+        #[clap(short, long)]
+        lot: Option<String>,
+        #[clap(short, long, num_args = 1..)]
+        reason: Vec<String>,
+    },
+    Inspect {
+        rma: u64,
+        #[clap(value_enum)]
+        outcome: InspectionOptions,
+        #[clap(short, long, default_value_t = 1)]
+        qty: usize,
+    },
+    List {
+        #[clap(long)]
+        open: bool,
+    },
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum AllocatorOptions {
+    Random,
+    Sequential,
+    Uuid,
+    Manual,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum ScanAction {
+    Restock,
+    Remove,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum CostMethodOptions {
+    Fifo,
+    Average,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+enum InspectionOptions {
+    Restockable,
+    Quarantine,
+    Scrap,
 }
 
 #[derive(Debug, Subcommand)]
 enum ListSubcommands {
     Products(ListProductsArgs),
     Items(ListItemsArgs),
+    Categories { category: Option<String> },
 }
 
 #[derive(Debug, Args)]
@@ -247,10 +692,26 @@ struct ListItemsArgs {
     name: Option<String>,
     #[clap(short, long)]
     id: Option<u64>,
+    #[clap(long, conflicts_with_all = ["name", "id", "barcode"])]
+    sku: Option<String>,
+    #[clap(long, conflicts_with_all = ["name", "id"])]
+    barcode: Option<String>,
     #[clap(short, long)]
     expiring: Option<u64>,
     #[clap(long)]
     expired: Option<bool>,
+    #[clap(short, long)]
+    lot: Option<String>,
+    #[clap(short, long)]
+    status: Option<String>,
+    #[clap(long, requires = "expiring")]
+    with_price: bool,
+    #[clap(long = "where", num_args = 1..)]
+    filter: Vec<String>,
+    #[clap(long, num_args = 1..=2)]
+    sort: Vec<String>,
+    #[clap(long)]
+    limit: Option<usize>,
 }
 
 #[derive(Debug, Args)]
@@ -258,27 +719,153 @@ struct ListProductsArgs {
     #[clap(short, long)]
     name: Option<String>,
     #[clap(long)]
-    max_price: Option<u64>,
+    max_price: Option<String>,
     #[clap(long)]
-    min_price: Option<u64>,
+    min_price: Option<String>,
     #[clap(short, long)]
     quality: Option<String>,
+    #[clap(long)]
+    category: Option<String>,
+    #[clap(long)]
+    tag: Option<String>,
+    #[clap(long = "where", num_args = 1..)]
+    filter: Vec<String>,
+    #[clap(long, num_args = 1..=2)]
+    sort: Vec<String>,
+    #[clap(long)]
+    limit: Option<usize>,
+}
+
+impl ProductSelector {
+    fn is_empty(&self) -> bool {
+        self.id.is_none() && self.name.is_none() && self.sku.is_none() && self.barcode.is_none()
+    }
+
+    /// Resolves the product. With a selector flag, clap reads the command's
+    /// next value as the ID, so that value is handed back for `Parsing::shifted`.
+    fn resolve(self, storage: &Storage) -> Result<(u64, Option<String>), Box<dyn Error>> {
+        let id = match (self.name, self.sku, self.barcode) {
+            (Some(name), _, _) => match storage.find_product_id(&name) {
+                Some(id) => id,
+                None => return Err(storage.product_not_found(&name)),
+            },
+            (None, Some(sku), _) => storage.find_product_by_sku(&sku)?,
+            (None, None, Some(barcode)) => storage.find_product_by_barcode(&barcode)?,
+            (None, None, None) => match self.id {
+                Some(product) => return Ok((Parsing::product_id(storage, &product)?, None)),
+                None => return Err(ReplError::base(InvalidIdOrName)),
+            },
+        };
+        Ok((id, self.id))
+    }
+
+    /// Resolves the product of a command that takes no value after it.
+    fn product(self, storage: &Storage) -> Result<u64, Box<dyn Error>> {
+        match self.resolve(storage)? {
+            (id, None) => Ok(id),
+            (_, Some(_)) => Err(ReplError::base(InvalidCommand)),
+        }
+    }
+
+    fn optional(self, storage: &Storage) -> Result<Option<u64>, Box<dyn Error>> {
+        match self.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(self.product(storage)?)),
+        }
+    }
 }
 
 #[allow(dead_code)]
 impl Parsing {
-    fn price(price: &str) -> Result<u64, Box<dyn Error>> {
-        let normalized_price = price.replace(",", ".");
+    fn product_id(storage: &Storage, product: &str) -> Result<u64, Box<dyn Error>> {
+        match product.parse::<u64>() {
+            Ok(id) => Ok(id),
+            Err(_) => storage
+                .find_product_id(product)
+                .ok_or_else(|| storage.product_not_found(product)),
+        }
+    }
 
-        match normalized_price.parse::<f64>() {
-            Ok(parsed_price) => {
-                let price_in_cents = (parsed_price * 100.0).round() as u64;
-                Ok(price_in_cents)
-            }
-            Err(_) => Err(ReplError::base(InvalidPrice)),
+    /// Takes the value `ProductSelector::resolve` handed back in place of the
+    /// command's own, as long as both were not given.
+    fn shifted<T: FromStr>(
+        shifted: Option<String>,
+        value: Option<T>,
+        error: ErrorMessage,
+    ) -> Result<Option<T>, Box<dyn Error>> {
+        match (shifted, value) {
+            (Some(_), Some(_)) => Err(ReplError::base(InvalidCommand)),
+            (Some(shifted), None) => match shifted.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => Err(ReplError::base(error)),
+            },
+            (None, value) => Ok(value),
         }
     }
 
+    /// Puts the word `ProductSelector::resolve` handed back in front of the
+    /// command's own words.
+    fn shifted_words(shifted: Option<String>, words: Vec<String>) -> Vec<String> {
+        shifted.into_iter().chain(words).collect()
+    }
+
+    fn listed_id(args: &ListItemsArgs, storage: &Storage) -> Result<Option<u64>, Box<dyn Error>> {
+        match (&args.sku, &args.barcode) {
+            (Some(sku), _) => Ok(Some(storage.find_product_by_sku(sku)?)),
+            (None, Some(barcode)) => Ok(Some(storage.find_product_by_barcode(barcode)?)),
+            (None, None) => Ok(args.id),
+        }
+    }
+
+    /// Builds a query from `--where`, `--sort` and `--limit`; the caller ANDs
+    /// its own filter flags onto the result.
+    fn query(
+        filter: &[String],
+        sort: &[String],
+        limit: Option<usize>,
+        format: &PriceFormat,
+    ) -> Result<Query, Box<dyn Error>> {
+        let mut query = Query::parse(&filter.join(" "), format)?;
+        if let [field, order @ ..] = sort {
+            query = query.sort_by(field, order.first().map(String::as_str))?;
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        Ok(query)
+    }
+
+    // Single filters keep their dedicated listings; anything that combines
+    // filters, or asks for an expression, sort or limit, goes through a query.
+    fn combined_item_filters(args: &ListItemsArgs) -> bool {
+        let filters = [
+            args.lot.is_some(),
+            args.status.is_some(),
+            args.expired.is_some() || args.expiring.is_some(),
+        ];
+        let selectors = [&args.name, &args.sku, &args.barcode].iter().filter(|arg| arg.is_some()).count()
+            + args.id.is_some() as usize;
+        !args.filter.is_empty()
+            || !args.sort.is_empty()
+            || args.limit.is_some()
+            || filters.iter().filter(|&&set| set).count() + selectors.min(1) > 1
+    }
+
+    fn placement(values: &[usize]) -> Result<(usize, usize, usize, usize), Box<dyn Error>> {
+        match values {
+            [row, shelf, level, zone] => Ok((*row, *shelf, *level, *zone)),
+            _ => Err(ReplError::base(InvalidCommand)),
+        }
+    }
+
+    fn currency(code: Option<&str>) -> Result<Option<Currency>, Box<dyn Error>> {
+        code.map(Currency::from_code).transpose()
+    }
+
+    fn price(price: &str, format: &PriceFormat, currency: Option<&Currency>) -> Result<u64, Box<dyn Error>> {
+        format.parse_in(price.trim(), currency)
+    }
+
     fn optional_date(date_str: &str) -> Option<NaiveDate> {
         let formats = [
             "%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d", "%Y %m %d", "%Y%m%d", "%d-%m-%Y", "%d/%m/%Y",
@@ -294,6 +881,17 @@ impl Parsing {
         None
     }
 
+    fn serials(file_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        match fs::read_to_string(file_path) {
+            Ok(contents) => Ok(contents
+                .lines()
+                .map(|line| line.trim().to_string())
+                .filter(|line| !line.is_empty())
+                .collect()),
+            Err(_) => Err(ReplError::base(InvalidFile)),
+        }
+    }
+
     fn handle_args(args: Vec<String>, expected_args: usize) -> Result<Vec<String>, &'static str> {
         if args.is_empty() {
             return Err("No arguments provided.");
@@ -356,11 +954,11 @@ impl Prompt {
         }
     }
 
-    fn price() -> Result<u64, Box<dyn Error>> {
+    fn price(format: &PriceFormat, currency: Option<&Currency>) -> Result<u64, Box<dyn Error>> {
         println!("Enter the price of the product:");
         let mut price = String::new();
         match stdin().read_line(&mut price) {
-            Ok(_) => match Parsing::price(price.trim()) {
+            Ok(_) => match Parsing::price(price.trim(), format, currency) {
                 Ok(price) => Ok(price),
                 Err(e) => Err(e),
             },
@@ -368,6 +966,46 @@ impl Prompt {
         }
     }
 
+    fn weight() -> Option<u64> {
+        println!("Enter the unit weight of the product in grams (optional):");
+        let mut weight = String::new();
+        match stdin().read_line(&mut weight) {
+            Ok(_) => weight.trim().parse::<u64>().ok(),
+            Err(_) => None,
+        }
+    }
+
+    fn lot() -> Option<String> {
+        println!("Enter the lot number of the delivery (optional):");
+        let mut lot = String::new();
+        match stdin().read_line(&mut lot) {
+            Ok(_) => Some(lot.trim().to_string()).filter(|lot| !lot.is_empty()),
+            Err(_) => None,
+        }
+    }
+
+    fn unit_cost(format: &PriceFormat) -> Option<u64> {
+        println!("Enter the unit cost of the delivery (optional):");
+        let mut cost = String::new();
+        match stdin().read_line(&mut cost) {
+            Ok(_) => Parsing::price(cost.trim(), format, None).ok(),
+            Err(_) => None,
+        }
+    }
+
+    fn serials() -> Vec<String> {
+        println!("Enter the serial numbers, one per line, ending with an empty line (optional):");
+        let mut serials = Vec::new();
+        loop {
+            let mut serial = String::new();
+            match stdin().read_line(&mut serial) {
+                Ok(_) if !serial.trim().is_empty() => serials.push(serial.trim().to_string()),
+                _ => break,
+            }
+        }
+        serials
+    }
+
     fn expiration_date() -> Option<NaiveDate> {
         println!("Enter the expiration date of the product (optional):");
         let mut expiration_date = String::new();
@@ -513,7 +1151,9 @@ impl Prompt {
         storage.name = name;
         match Prompt::warehouse_creation(warehouse) {
             Ok(warehouse) => {
-                storage.warehouse = warehouse;
+                storage
+                    .warehouses
+                    .insert(storage.active_warehouse.clone(), warehouse);
                 Ok(storage)
             }
             Err(e) => Err(e),
@@ -522,9 +1162,9 @@ impl Prompt {
 
     fn new_product(storage: &mut Storage) -> Result<(), Box<dyn Error>> {
         let name = Prompt::name();
-        match Prompt::price() {
+        match Prompt::price(&storage.price_format(), None) {
             Ok(price) => match Prompt::quality() {
-                Ok(quality) => match storage.new_product(name, price, quality) {
+                Ok(quality) => match storage.new_product(name, price, quality, Prompt::weight()) {
                     Ok(_) => Ok(()),
                     Err(e) => Err(e),
                 },
@@ -552,7 +1192,7 @@ impl Prompt {
 
     fn price_change(storage: &mut Storage) -> Result<(), Box<dyn Error>> {
         match Prompt::id() {
-            Ok(id) => match Prompt::price() {
+            Ok(id) => match Prompt::price(&storage.price_format(), storage.product_currency(id)) {
                 Ok(price) => match storage.change_price(id, price) {
                     Ok(_) => Ok(()),
                     Err(e) => Err(e),
@@ -563,39 +1203,34 @@ impl Prompt {
         }
     }
 
-    fn restock_product(storage: &mut Storage) -> Result<(), Box<dyn Error>> {
-        match Prompt::id_or_name() {
-            Ok(id_or_name) => match id_or_name.parse::<u64>() {
-                Ok(id) => match Prompt::quantity() {
-                    Ok(quantity) => match Prompt::expiration_date() {
-                        Some(expiry) => match storage.restock_product(id, quantity, Some(expiry)) {
-                            Ok(_) => Ok(()),
-                            Err(e) => Err(e),
-                        },
-                        None => match storage.restock_product(id, quantity, None) {
-                            Ok(_) => Ok(()),
-                            Err(e) => Err(e),
-                        },
-                    },
-                    Err(e) => Err(e),
-                },
-                Err(_) => match Prompt::quantity() {
-                    Ok(quantity) => match Prompt::expiration_date() {
-                        Some(expiry) => {
-                            match storage.restock_by_name(&id_or_name, quantity, Some(expiry)) {
-                                Ok(_) => Ok(()),
-                                Err(e) => Err(e),
-                            }
-                        }
-                        None => match storage.restock_by_name(&id_or_name, quantity, None) {
-                            Ok(_) => Ok(()),
-                            Err(e) => Err(e),
-                        },
-                    },
-                    Err(e) => Err(e),
+    fn scan(storage: &mut Storage, mut delivery: Option<&mut Delivery>) {
+        println!("Scan barcodes, one per line, ending with an empty line:");
+        loop {
+            let mut barcode = String::new();
+            match stdin().read_line(&mut barcode) {
+                Ok(0) | Err(_) => break,
+                Ok(_) if barcode.trim().is_empty() => break,
+                Ok(_) => match storage.scan(barcode.trim(), delivery.as_deref_mut()) {
+                    Ok(id) => {
+                        let product = storage.list.product(id).unwrap();
+                        println!("{}: {} in stock", product.name(), product.quantity);
+                    }
+                    Err(e) => eprintln!("{}", e),
                 },
-            },
-            Err(e) => Err(e),
+            }
+        }
+    }
+
+    fn restock_product(storage: &mut Storage) -> Result<(), Box<dyn Error>> {
+        let id_or_name = Prompt::id_or_name()?;
+        let quantity = Prompt::quantity()?;
+        let mut delivery = Delivery::new(Prompt::expiration_date())
+            .with_lot(Prompt::lot())
+            .with_serials(Prompt::serials())
+            .with_unit_cost(Prompt::unit_cost(&storage.price_format()));
+        match id_or_name.parse::<u64>() {
+            Ok(id) => storage.restock_with(id, quantity, &mut delivery),
+            Err(_) => storage.restock_with_by_name(&id_or_name, quantity, &mut delivery),
         }
     }
 
@@ -655,12 +1290,24 @@ fn readline() -> Result<String, Box<dyn Error>> {
 
 fn resolve_cmd(cmd: Commands, storage: &mut Storage) -> Result<bool, Box<dyn Error>> {
     use Commands::*;
+    storage.apply_scheduled_prices(chrono::Local::now().naive_local().date());
     match cmd {
         Add {
             name,
             price,
+            currency,
+            id,
+            sku,
+            barcode,
+            category,
+            tags,
+            weight,
+            levels,
+            shelf_life,
             quality,
         } => {
+            let currency = Parsing::currency(currency.as_deref())?;
+            let price = Parsing::price(&price, &storage.price_format(), currency.as_ref())?;
             use QualityOptions::*;
             let quality = match quality {
                 Normal => Quality::Normal,
@@ -670,55 +1317,398 @@ fn resolve_cmd(cmd: Commands, storage: &mut Storage) -> Result<bool, Box<dyn Err
                     Quality::OversizedAndFragile(zones, level)
                 }
             };
-            storage.new_product(name, price, quality)?;
+            let mut product = Product::new(&name, price, 0, quality);
+            product.set_weight(weight);
+            product.set_levels_required(levels.unwrap_or(1));
+            product.set_shelf_life(shelf_life);
+            product.currency = currency;
+            product.sku = sku;
+            product.barcode = barcode;
+            product.set_category(category.as_deref());
+            product.add_tags(&tags);
+            let id = match id {
+                Some(id) => {
+                    product.id = id;
+                    storage.add_product_with_id(product)?
+                }
+                None => storage.add_product(product)?,
+            };
+            println!("Product {} added with ID {}", name, id);
             Ok(true)
         }
-        Delete { id, name } => {
-            if let Some(name) = name {
-                storage.delete_product_by_name(&name)?;
-            } else if let Some(id) = id {
-                storage.delete_product_by_id(id)?;
-            } else {
+        Delete { product } => {
+            if product.is_empty() {
                 Prompt::delete_product(storage)?;
+            } else {
+                storage.delete_product_by_id(product.product(storage)?)?;
             }
             Ok(true)
         }
-        Remove { id, name, quantity } => {
-            if let Some(name) = name {
-                storage.remove_stock_by_name(&name, quantity)?;
-            } else if let Some(id) = id {
-                storage.remove_stock(id, quantity)?;
-            } else {
-                Prompt::remove_stock(storage)?;
-            }
+        Remove {
+            product,
+            quantity,
+            serial,
+        } => {
+            match serial {
+                Some(serial) => storage.remove_serial(&serial),
+                None if product.is_empty() => Prompt::remove_stock(storage),
+                None => {
+                    let (id, value) = product.resolve(storage)?;
+                    let quantity = match Parsing::shifted(value, quantity, InvalidQuantity)? {
+                        Some(quantity) => quantity,
+                        None => Prompt::quantity()?,
+                    };
+                    storage.remove_stock(id, quantity)
+                }
+            }?;
             Ok(true)
         }
         Restock {
-            id,
-            name,
+            product,
             quantity,
             expiration_date,
+            lot,
+            serials,
+            cost,
         } => {
-            match (id, name, expiration_date) {
-                (Some(id), None, _) => storage.restock_product(id, quantity, expiration_date),
-                (_, Some(name), _) => storage.restock_by_name(&name, quantity, expiration_date),
-                _ => Prompt::restock_product(storage),
+            let unit_cost = match cost {
+                Some(cost) => Some(Parsing::price(&cost, &storage.price_format(), None)?),
+                None => None,
+            };
+            let serials = match serials {
+                Some(file_path) => Parsing::serials(&file_path)?,
+                None => Vec::new(),
+            };
+            if product.is_empty() {
+                Prompt::restock_product(storage)?;
+                return Ok(true);
+            }
+            let (id, value) = product.resolve(storage)?;
+            let quantity = match Parsing::shifted(value, quantity, InvalidQuantity)? {
+                Some(quantity) => quantity,
+                None if !serials.is_empty() => serials.len(),
+                None => Prompt::quantity()?,
+            };
+            let mut delivery = Delivery::new(expiration_date)
+                .with_lot(lot)
+                .with_serials(serials)
+                .with_unit_cost(unit_cost);
+            storage.restock_with(id, quantity, &mut delivery)?;
+            Ok(true)
+        }
+        Price(price) => {
+            match price.cmd {
+                PriceSubcommands::History { product } => {
+                    storage.print_price_history(product.product(storage)?)
+                }
             }?;
             Ok(true)
         }
+        Find(find) => {
+            match find.cmd {
+                FindSubcommands::Serial { serial } => storage.print_serial(&serial),
+                FindSubcommands::Product { name, limit } => storage.print_search(&name.join(" "), limit),
+            }?;
+            Ok(true)
+        }
+        SetStatus {
+            row,
+            shelf,
+            level,
+            zone,
+            status,
+        } => {
+            let status = match status {
+                StatusOptions::Available => ItemStatus::Available,
+                StatusOptions::Quarantined => ItemStatus::Quarantined,
+                StatusOptions::Damaged => ItemStatus::Damaged,
+                StatusOptions::OnHold { reason } => ItemStatus::OnHold(reason.join(" ")),
+            };
+            storage.set_item_status((row, shelf, level, zone), status)?;
+            Ok(true)
+        }
+        Dispose {
+            before, dry_run, ..
+        } => {
+            let before = before.unwrap_or(chrono::Local::now().naive_local().date());
+            let write_offs = storage.dispose_expired(before, dry_run)?;
+            if dry_run {
+                println!("Items that would be disposed:");
+            }
+            let format = storage.price_format();
+            write_offs.iter().for_each(|write_off| println!("{}", write_off.priced(&format)));
+            Ok(true)
+        }
+        WriteOffs { month } => {
+            let month = match month {
+                Some(month) => match NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d") {
+                    Ok(date) => Some(date),
+                    Err(_) => return Err(ReplError::base(InvalidDate)),
+                },
+                None => None,
+            };
+            storage.write_off_report(month);
+            Ok(true)
+        }
+        Markdown(markdown) => {
+            match markdown.cmd {
+                MarkdownSubcommands::Add {
+                    product,
+                    quality,
+                    percent,
+                    days,
+                } => {
+                    let rule = MarkdownRule {
+                        days_before_expiry: days,
+                        percent_off: percent.min(100),
+                    };
+                    match quality {
+                        Some(quality) => storage.add_quality_markdown(&quality, rule),
+                        None => storage.add_markdown(product.product(storage)?, rule),
+                    }
+                }
+                MarkdownSubcommands::Clear { product, quality } => match quality {
+                    Some(quality) => {
+                        storage.clear_quality_markdowns(&quality);
+                        Ok(())
+                    }
+                    None => storage.clear_markdowns(product.product(storage)?),
+                },
+                MarkdownSubcommands::List => {
+                    storage.list_markdowns();
+                    Ok(())
+                }
+            }?;
+            Ok(true)
+        }
+        Supplier(supplier) => {
+            match supplier.cmd {
+                SupplierSubcommands::Add { name, contact } => {
+                    let id = storage.add_supplier(&name, contact);
+                    println!("Supplier {} added with ID {}", name, id);
+                }
+                SupplierSubcommands::List => storage.list_suppliers(),
+            }
+            Ok(true)
+        }
+        Po(po) => {
+            match po.cmd {
+                PurchaseOrderSubcommands::Create { supplier } => {
+                    let id = storage.create_purchase_order(supplier)?;
+                    println!("Purchase order {} created", id);
+                }
+                PurchaseOrderSubcommands::AddLine {
+                    po,
+                    product,
+                    quantity,
+                    cost,
+                    expected,
+                } => {
+                    let (product_id, value) = product.resolve(storage)?;
+                    let quantity = Parsing::shifted(value, quantity, InvalidQuantity)?
+                        .ok_or(ReplError::base(InvalidQuantity))?;
+                    let unit_cost = Parsing::price(&cost, &storage.price_format(), None)?;
+                    let line = PurchaseOrderLine::new(product_id, quantity, unit_cost, expected);
+                    let number = storage.add_purchase_order_line(po, line)?;
+                    println!("Line {} added to purchase order {}", number, po);
+                }
+                PurchaseOrderSubcommands::List { open } => storage.list_purchase_orders(open),
+            }
+            Ok(true)
+        }
+        Rma(rma) => {
+            match rma.cmd {
+                ReturnSubcommands::Create {
+                    product,
+                    quantity,
+                    expiry,
+                    lot,
+                    reason,
+                } => {
+                    let (product_id, value) = product.resolve(storage)?;
+                    let quantity = Parsing::shifted(value, quantity, InvalidQuantity)?
+                        .ok_or(ReplError::base(InvalidQuantity))?;
+                    let reason = Some(reason.join(" ")).filter(|reason| !reason.is_empty());
+                    let id = storage.create_return(product_id, quantity, expiry, lot, reason)?;
+                    println!("Return {} registered", id);
+                }
+                ReturnSubcommands::Inspect { rma, outcome, qty } => {
+                    let outcome = match outcome {
+                        InspectionOptions::Restockable => InspectionOutcome::Restockable,
+                        InspectionOptions::Quarantine => InspectionOutcome::Quarantine,
+                        InspectionOptions::Scrap => InspectionOutcome::Scrap,
+                    };
+                    storage.inspect_return(rma, outcome, qty)?;
+                }
+                ReturnSubcommands::List { open } => storage.list_returns(open),
+            }
+            Ok(true)
+        }
+        Journal { product } => {
+            storage.print_journal(product.optional(storage)?);
+            Ok(true)
+        }
+        Allocator { allocator, next } => {
+            if let Some(allocator) = allocator {
+                storage.set_id_allocator(match allocator {
+                    AllocatorOptions::Random => IdAllocator::Random,
+                    AllocatorOptions::Sequential => IdAllocator::Sequential {
+                        next: next.unwrap_or(1),
+                    },
+                    AllocatorOptions::Uuid => IdAllocator::Uuid,
+                    AllocatorOptions::Manual => IdAllocator::Manual,
+                });
+            }
+            println!("Product ids are allocated by {}", storage.list.id_allocator);
+            Ok(true)
+        }
+        Scan {
+            action,
+            expiration_date,
+            lot,
+        } => {
+            let mut delivery = match action {
+                ScanAction::Restock => Some(Delivery::new(expiration_date).with_lot(lot)),
+                ScanAction::Remove => None,
+            };
+            Prompt::scan(storage, delivery.as_mut());
+            Ok(true)
+        }
+        Currency { code } => {
+            if let Some(code) = code {
+                storage.set_currency(&code)?;
+            }
+            storage.print_currency();
+            Ok(true)
+        }
+        Locale { tag } => {
+            if let Some(tag) = tag {
+                storage.set_locale(&tag)?;
+            }
+            storage.print_currency();
+            Ok(true)
+        }
+        Valuation { product, method } => {
+            let product_id = product.optional(storage)?;
+            let method = match method {
+                CostMethodOptions::Fifo => CostMethod::Fifo,
+                CostMethodOptions::Average => CostMethod::WeightedAverage,
+            };
+            storage.print_valuation(method, product_id)?;
+            Ok(true)
+        }
+        Receive {
+            po,
+            line,
+            qty,
+            expiry,
+            lot,
+        } => {
+            let received = storage.receive(po, line, qty, expiry, lot)?;
+            println!("Received {} units against purchase order {}", received, po);
+            Ok(true)
+        }
+        Replenish => {
+            storage.print_replenishment();
+            Ok(true)
+        }
+        Alerts {
+            horizons,
+            low_stock,
+            format,
+            notify,
+        } => {
+            let date = chrono::Local::now().naive_local().date();
+            let digest = AlertDigest::scan(storage, date, &horizons, low_stock);
+            match format {
+                AlertFormat::Text => print!("{}", digest),
+                AlertFormat::Json => println!("{}", digest.to_json()?),
+                AlertFormat::Email => print!("{}", digest.to_email()),
+            }
+            if let Some(command) = notify {
+                CommandNotifier::new(&command).notify(&digest)?;
+            }
+            Ok(true)
+        }
+        Recall { lot, remove } => {
+            let recalled = storage.recall_lot(&lot, remove)?;
+            let action = if remove { "removed" } else { "quarantined" };
+            println!("{} items of lot {} {}", recalled, lot, action);
+            Ok(true)
+        }
         List(list) => match list.cmd {
+            ListSubcommands::Categories { category } => {
+                storage.print_category_rollups(category.as_deref());
+                Ok(true)
+            }
             ListSubcommands::Products(args) => {
-                match (args.name, args.max_price, args.min_price, args.quality) {
-                    (Some(name), _, _, _) => storage.search_product_name(&name),
-                    (_, Some(max_price), _, _) => storage.list_with_max_price(max_price),
-                    (_, _, Some(min_price), _) => storage.list_with_min_price(min_price),
-                    (_, _, _, Some(quality)) => storage.list_with_quality(quality.to_lowercase()),
-                    _ => storage.list_products(),
+                let format = storage.price_format();
+                let mut query = Parsing::query(&args.filter, &args.sort, args.limit, &format)?;
+                let flags = [
+                    (Field::Name, Op::Contains, args.name),
+                    (Field::Price, Op::Le, args.max_price),
+                    (Field::Price, Op::Ge, args.min_price),
+                    (Field::Quality, Op::Eq, args.quality),
+                    (Field::Category, Op::Eq, args.category.clone()),
+                    (Field::Tag, Op::Eq, args.tag.clone()),
+                ];
+                for (field, op, value) in flags {
+                    if let Some(value) = value {
+                        query = query.and(Expr::compare(field, op, &value, &format)?);
+                    }
+                }
+                if query.sort.is_none() && (args.category.is_some() || args.tag.is_some()) {
+                    query = query.sort(Field::Category, false);
+                }
+                storage.print_product_query(&query)?;
+                Ok(true)
+            }
+            ListSubcommands::Items(args) if !args.with_price && Parsing::combined_item_filters(&args) => {
+                let today = chrono::Local::now().naive_local().date();
+                let format = storage.price_format();
+                let mut query = Parsing::query(&args.filter, &args.sort, args.limit, &format)?;
+                if let Some(id) = Parsing::listed_id(&args, storage)? {
+                    query = query.and(Expr::compare(Field::Id, Op::Eq, &id.to_string(), &format)?);
+                }
+                if let Some(name) = args.name {
+                    query = query.and(Expr::compare(Field::Name, Op::Eq, &name, &format)?);
+                }
+                if let Some(expired) = args.expired {
+                    let expr = Expr::compare(Field::Expiry, Op::Lt, &today.to_string(), &format)?;
+                    query = query.and(if expired { expr } else { expr.negate() });
+                }
+                if let Some(days) = args.expiring {
+                    let date = today + chrono::Duration::days(days as i64);
+                    query = query.and(Expr::compare(Field::Expiry, Op::Le, &date.to_string(), &format)?);
+                }
+                if let Some(lot) = args.lot {
+                    query = query.and(Expr::compare(Field::Lot, Op::Eq, &lot, &format)?);
+                }
+                if let Some(status) = args.status {
+                    query = query.and(Expr::compare(Field::Status, Op::Eq, &status, &format)?);
                 }
+                storage.print_item_query(&query);
                 Ok(true)
             }
             ListSubcommands::Items(args) => {
-                match (args.id, args.name, args.expired, args.expiring) {
+                let id = Parsing::listed_id(&args, storage)?;
+                if let Some(lot) = args.lot {
+                    storage.print_lot(&lot)?;
+                    return Ok(true);
+                }
+                if let Some(status) = args.status {
+                    storage.list_items_with_status(&status);
+                    return Ok(true);
+                }
+                if let (true, Some(days)) = (args.with_price, args.expiring) {
+                    let id = match (id, args.name) {
+                        (_, Some(name)) => Some(storage.find_product_id(&name).ok_or(ReplError::base(InvalidIdOrName))?),
+                        (id, None) => id,
+                    };
+                    storage.list_expiring_with_price(days, id);
+                    return Ok(true);
+                }
+                match (id, args.name, args.expired, args.expiring) {
                     (Some(id), None, None, None) => storage.list_items_with_id(id),
                     (Some(id), None, Some(true), None) => storage.list_expired_with_id(id),
                     (Some(id), None, None, Some(days)) => storage.list_expiring_with_id(id, days),
@@ -735,6 +1725,146 @@ fn resolve_cmd(cmd: Commands, storage: &mut Storage) -> Result<bool, Box<dyn Err
                 Ok(true)
             }
         },
+        Change(change) => {
+            match change.cmd {
+                ChangeSubcommands::Price(PriceArgs {
+                    product,
+                    price,
+                    effective,
+                    reason,
+                }) => match product.is_empty() {
+                    true => Prompt::price_change(storage),
+                    false => {
+                        let (id, value) = product.resolve(storage)?;
+                        let format = storage.price_format();
+                        let currency = storage.product_currency(id);
+                        let price = match Parsing::shifted(value, price, InvalidPrice)? {
+                            Some(price) => Parsing::price(&price, &format, currency)?,
+                            None => Prompt::price(&format, currency)?,
+                        };
+                        let reason = Some(reason.join(" ")).filter(|reason| !reason.is_empty());
+                        match effective {
+                            Some(effective) => storage.schedule_price(id, effective, price, reason),
+                            None => storage.change_price_with(id, price, reason),
+                        }
+                    }
+                },
+                ChangeSubcommands::Name(NameArgs { product, new_name }) => {
+                    let (id, value) = product.resolve(storage)?;
+                    match Parsing::shifted_words(value, new_name) {
+                        name if name.is_empty() => Err(ReplError::base(InvalidCommand)),
+                        name => storage.change_name(id, &name.join(" ")),
+                    }
+                }
+                ChangeSubcommands::Sku(SkuArgs { product, new_sku }) => {
+                    let (id, value) = product.resolve(storage)?;
+                    let sku = Parsing::shifted(value, new_sku, InvalidCommand)?;
+                    storage.change_sku(id, sku)
+                }
+                ChangeSubcommands::Barcode(BarcodeArgs { product, new_barcode }) => {
+                    let (id, value) = product.resolve(storage)?;
+                    let barcode = Parsing::shifted(value, new_barcode, InvalidCommand)?;
+                    storage.change_barcode(id, barcode)
+                }
+                ChangeSubcommands::Category(CategoryArgs { product, category }) => {
+                    let (id, value) = product.resolve(storage)?;
+                    let category = Parsing::shifted_words(value, category);
+                    storage.change_category(id, Some(&category.join(" ")))
+                }
+                ChangeSubcommands::Tags(TagsArgs {
+                    product,
+                    add,
+                    remove,
+                }) => {
+                    let id = product.product(storage)?;
+                    storage.change_tags(id, &add, &remove)
+                }
+                ChangeSubcommands::Currency(CurrencyArgs { product, code }) => {
+                    let (id, value) = product.resolve(storage)?;
+                    let code = Parsing::shifted(value, code, InvalidCommand)?;
+                    storage.change_currency(id, code.as_deref())
+                }
+                ChangeSubcommands::Weight(WeightArgs { product, weight }) => {
+                    let (id, value) = product.resolve(storage)?;
+                    let weight = Parsing::shifted(value, weight, InvalidNumber)?;
+                    storage.change_weight(id, weight)
+                }
+                ChangeSubcommands::ShelfLife(ShelfLifeArgs { product, days }) => {
+                    let (id, value) = product.resolve(storage)?;
+                    let days = Parsing::shifted(value, days, InvalidNumber)?;
+                    storage.change_shelf_life(id, days)
+                }
+                ChangeSubcommands::Reorder(ReorderArgs {
+                    product,
+                    point,
+                    quantity,
+                    max,
+                }) => {
+                    let id = product.product(storage)?;
+                    storage.set_reorder_policy(id, point, quantity, max)
+                }
+                ChangeSubcommands::LoadRating(LoadRatingArgs {
+                    row,
+                    shelf,
+                    level,
+                    load_rating,
+                }) => storage.set_load_rating(row, shelf, level, load_rating),
+                _ => Err(ReplError::base(InvalidCommand)),
+            }?;
+            Ok(true)
+        }
+        Capacity => {
+            storage.capacity_report();
+            Ok(true)
+        }
+        Warehouse(warehouse) => {
+            match warehouse.cmd {
+                WarehouseSubcommands::Add(WarehouseArgs {
+                    name,
+                    rows,
+                    shelves,
+                    levels,
+                    zones,
+                }) => {
+                    let warehouse = match (rows, shelves, levels, zones) {
+                        (Some(rows), Some(shelves), Some(levels), Some(zones)) => {
+                            let mut warehouse = warehouse::Warehouse::new();
+                            warehouse.initialize_rows(rows, shelves, levels, zones);
+                            warehouse
+                        }
+                        _ => Prompt::warehouse_creation(warehouse::Warehouse::new())?,
+                    };
+                    storage.add_warehouse(&name, warehouse)
+                }
+                WarehouseSubcommands::Remove { name } => storage.remove_warehouse(&name),
+                WarehouseSubcommands::Use { name } => storage.use_warehouse(&name),
+                WarehouseSubcommands::List => {
+                    storage.list_warehouses();
+                    Ok(())
+                }
+                WarehouseSubcommands::Stock { product } => {
+                    storage.print_product_quantities(product.product(storage)?)
+                }
+            }?;
+            Ok(true)
+        }
+        Transfer {
+            product,
+            quantity,
+            from,
+            to,
+        } => {
+            let (id, value) = product.resolve(storage)?;
+            let quantity = Parsing::shifted(value, quantity, InvalidQuantity)?
+                .ok_or(ReplError::base(InvalidQuantity))?;
+            let from = from.unwrap_or(storage.active_warehouse.clone());
+            storage.transfer_stock(id, quantity, &from, &to)?;
+            Ok(true)
+        }
+        Move { from, to } => {
+            storage.move_item(Parsing::placement(&from)?, Parsing::placement(&to)?)?;
+            Ok(true)
+        }
         Load { file_path } => {
             match Storage::load(&file_path, storage) {
                 Ok(_) => Ok(true),
@@ -753,7 +1883,7 @@ fn resolve_cmd(cmd: Commands, storage: &mut Storage) -> Result<bool, Box<dyn Err
             if let Some(file_path) = file_path {
                 match storage.save_as(&file_path) {
                     Ok(_) => Ok(true),
-                    Err(e) => Err(Box::new(e)),
+                    Err(e) => Err(e),
                 }
             } else {
                 match storage.save() {
@@ -772,7 +1902,6 @@ fn resolve_cmd(cmd: Commands, storage: &mut Storage) -> Result<bool, Box<dyn Err
         }
 
         ForceExit => Ok(false),
-        _ => Ok(false),
     }
 }
 
@@ -0,0 +1,132 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+use ErrorMessage::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InspectionOutcome {
+    Restockable,
+    Quarantine,
+    Scrap,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReturnAuthorization {
+    pub id: u64,
+    pub product_id: u64,
+    pub quantity: usize,
+    pub created: NaiveDate,
+    pub reason: Option<String>,
+    pub expiry_date: Option<NaiveDate>,
+    pub lot: Option<String>,
+    pub outcomes: Vec<InspectionOutcome>,
+}
+
+#[derive(Debug)]
+pub enum ErrorMessage {
+    ReturnNotFound,
+    AllUnitsInspected,
+}
+
+#[derive(Debug)]
+pub(crate) struct ReturnError {
+    message: String,
+}
+
+impl ErrorMessage {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ReturnNotFound => "Return authorization not found",
+            AllUnitsInspected => "Not enough uninspected units left on return",
+        }
+    }
+}
+
+impl Display for ErrorMessage {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Display for ReturnError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Return Error: {}", self.message)
+    }
+}
+
+impl Error for ReturnError {}
+
+impl ReturnError {
+    pub fn boxed(message: String) -> Box<dyn Error> {
+        Box::new(ReturnError { message })
+    }
+
+    pub fn with_id(message: ErrorMessage, id: u64) -> Box<dyn Error> {
+        ReturnError::boxed(format!("{} - {}", message, id))
+    }
+}
+
+impl Display for InspectionOutcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InspectionOutcome::Restockable => write!(f, "restockable"),
+            InspectionOutcome::Quarantine => write!(f, "quarantine"),
+            InspectionOutcome::Scrap => write!(f, "scrap"),
+        }
+    }
+}
+
+impl Display for ReturnAuthorization {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let count = |outcome| self.outcomes.iter().filter(|o| **o == outcome).count();
+        write!(
+            f,
+            "RMA {}: {} units of {}, created {}, {} pending, {} restocked, {} quarantined, {} scrapped",
+            self.id,
+            self.quantity,
+            self.product_id,
+            self.created,
+            self.pending(),
+            count(InspectionOutcome::Restockable),
+            count(InspectionOutcome::Quarantine),
+            count(InspectionOutcome::Scrap)
+        )?;
+        if let Some(reason) = &self.reason {
+            write!(f, " - {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl ReturnAuthorization {
+    pub fn new(id: u64, product_id: u64, quantity: usize, created: NaiveDate) -> Self {
+        ReturnAuthorization {
+            id,
+            product_id,
+            quantity,
+            created,
+            reason: None,
+            expiry_date: None,
+            lot: None,
+            outcomes: Vec::new(),
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.quantity.saturating_sub(self.outcomes.len())
+    }
+
+    pub fn check_pending(&self, quantity: usize) -> Result<(), Box<dyn Error>> {
+        if quantity == 0 || quantity > self.pending() {
+            return Err(ReturnError::with_id(AllUnitsInspected, self.id));
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, outcome: InspectionOutcome, quantity: usize) {
+        self.outcomes.extend(std::iter::repeat_n(outcome, quantity));
+    }
+}
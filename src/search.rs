@@ -0,0 +1,66 @@
+const EXACT: u32 = 100;
+const PREFIX: u32 = 80;
+const CONTAINS: u32 = 60;
+const FUZZY: u32 = 50;
+const TYPO_PENALTY: u32 = 15;
+const FULL_NAME_BONUS: u32 = 50;
+
+/// Scores how well `query` matches `name`, or `None` if some query word has no
+/// counterpart in the name. Every query word is matched against its best name
+/// word: exact words beat prefixes, prefixes beat substrings, and substrings
+/// beat words within a few typos.
+pub fn score(query: &str, name: &str) -> Option<u32> {
+    let (query, name) = (query.to_lowercase(), name.to_lowercase());
+    let words: Vec<&str> = name.split_whitespace().collect();
+    let mut total = 0;
+    for term in query.split_whitespace() {
+        total += words.iter().filter_map(|word| word_score(term, word)).max()?;
+    }
+    if total > 0 && query.split_whitespace().eq(name.split_whitespace()) {
+        total += FULL_NAME_BONUS;
+    }
+    Some(total).filter(|&total| total > 0)
+}
+
+fn word_score(term: &str, word: &str) -> Option<u32> {
+    if term == word {
+        return Some(EXACT);
+    }
+    if word.starts_with(term) {
+        return Some(PREFIX);
+    }
+    if word.contains(term) {
+        return Some(CONTAINS);
+    }
+    let allowed = max_typos(term);
+    let prefix: String = word.chars().take(term.chars().count()).collect();
+    let distance = distance(term, word).min(distance(term, &prefix));
+    match distance <= allowed && allowed > 0 {
+        true => Some(FUZZY - TYPO_PENALTY * distance as u32),
+        false => None,
+    }
+}
+
+/// Short words have to be spelled right; longer ones tolerate one or two typos.
+fn max_typos(term: &str) -> usize {
+    match term.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -1,5 +1,16 @@
 #[cfg(test)]
-use crate::{ warehouse::{Warehouse, PlacementStrategy::*}, product::ProductList};
+use crate::{ alerts::{AlertDigest, CommandNotifier, Notifier}, backend, bitmap::ZoneBitmap, currency::{Currency, Locale}, inventory::Storage, warehouse::{Warehouse, PlacementStrategy::*}, purchasing::{PurchaseOrderLine, PurchaseOrderStatus}, query::{Expr, Field, Op, Query}, returns::InspectionOutcome, valuation::CostMethod, journal::MovementKind, product::{validate_barcode, Delivery, IdAllocator, ItemStatus, ProductItem, MarkdownRule, Product, ProductList, Quality::*}};
+
+#[cfg(test)]
+impl Storage {
+    /// Storage with the sample products and a full-size warehouse `name` in use.
+    fn with_warehouse(name: &str) -> Self {
+        let mut storage = Storage { list: ProductList::default(), ..Storage::default() };
+        storage.warehouses.insert(name.to_string(), Warehouse::default());
+        storage.active_warehouse = name.to_string();
+        storage
+    }
+}
 
 #[test]
 fn contiguous_restock() {
@@ -16,7 +27,7 @@ fn contiguous_restock() {
 
 #[test]
 fn round_robin_restock() {
-    let mut warehouse = Warehouse { strategy: RoundRobin, ..Warehouse::default() };
+    let mut warehouse = Warehouse::default().with_strategy(RoundRobin);
     let mut product_list = ProductList::default();
     let product_id = product_list.id_from_name("Apple").unwrap();
     match warehouse.independent_restock(product_id, 100, &mut product_list, None) {
@@ -29,7 +40,7 @@ fn round_robin_restock() {
 
 #[test]
 fn closest_to_start_restock() {
-    let mut warehouse = Warehouse { strategy: ClosestToStart, ..Warehouse::default() };
+    let mut warehouse = Warehouse::default().with_strategy(ClosestToStart);
     let mut product_list = ProductList::default();
     let product_id = product_list.id_from_name("Apple").unwrap();
     match warehouse.independent_restock(product_id, 100, &mut product_list, None) {
@@ -59,7 +70,7 @@ fn contiguous_oversized_restock() {
 
 #[test]
 fn round_robin_oversized_restock() {
-    let mut warehouse = Warehouse { strategy: RoundRobin, ..Warehouse::default() };
+    let mut warehouse = Warehouse::default().with_strategy(RoundRobin);
     let mut product_list = ProductList::default();
     let product_id = product_list.id_from_name("Watermelon").unwrap();
     match warehouse.independent_restock(product_id, 100, &mut product_list, None) {
@@ -75,7 +86,7 @@ fn round_robin_oversized_restock() {
 
 #[test]
 fn closest_to_start_oversized_restock() {
-    let mut warehouse = Warehouse { strategy: ClosestToStart, ..Warehouse::default() };
+    let mut warehouse = Warehouse::default().with_strategy(ClosestToStart);
     let mut product_list = ProductList::default();
     let product_id = product_list.id_from_name("Watermelon").unwrap();
     match warehouse.independent_restock(product_id, 100, &mut product_list, None) {
@@ -105,6 +116,894 @@ fn removal() {
         Ok(_) => warehouse.print_items_and_names(&product_list),
         Err(e) => panic!("{}", e),
     }
+    assert!(warehouse.items().iter().all(|item| item.expiry_date == expiry_date));
     // println!("Product list: {:#?}", product_list);
     // println!("{:#?}", warehouse);
 }
+
+#[test]
+fn placement_boundaries() {
+    let mut product_list = ProductList::default();
+    let apple = product_list.id_from_name("Apple").unwrap();
+    let banana = product_list.id_from_name("Banana").unwrap();
+    let watermelon = product_list.id_from_name("Watermelon").unwrap();
+
+    // A run starting on the last zone of a level used to map to zone 0 of the next level.
+    let mut warehouse = Warehouse::default();
+    warehouse.independent_restock(apple, 9, &mut product_list, None).unwrap();
+    warehouse.independent_restock(apple, 2, &mut product_list, None).unwrap();
+    let placements: Vec<_> = warehouse.items().iter().map(|item| item.placement).collect();
+    assert_eq!(placements[9..], [(1, 1, 1, 10), (1, 1, 2, 1)]);
+
+    // The last window of the flat map was never searched, so a full restock found no space.
+    let mut warehouse = Warehouse::default();
+    let capacity = warehouse.check_capacity();
+    warehouse.independent_restock(apple, capacity, &mut product_list, None).unwrap();
+    assert_eq!(warehouse.items().len(), capacity);
+
+    // Running past the last row indexed out of bounds instead of returning EndOfRows.
+    let mut warehouse = Warehouse::default();
+    let expiry_date = Some("2030-01-01".parse().unwrap());
+    assert!(warehouse.independent_restock(banana, 400, &mut product_list, expiry_date).is_err());
+
+    // Oversized slots were not aligned, so a second restock overlapped the first.
+    let mut warehouse = Warehouse::default();
+    warehouse.independent_restock(watermelon, 4, &mut product_list, None).unwrap();
+    warehouse.independent_restock(watermelon, 4, &mut product_list, None).unwrap();
+    let zones: Vec<_> = warehouse.items().iter().map(|item| item.placement.3).collect();
+    assert_eq!(zones, [1, 4, 7, 1, 4, 7, 1, 4]);
+}
+
+#[test]
+fn load_limit() {
+    let mut warehouse = Warehouse::default();
+    let mut product_list = ProductList::default();
+    let product_id = product_list.id_from_name("Apple").unwrap();
+    product_list.product_mut(product_id).unwrap().set_weight(Some(40_000));
+    warehouse.set_level_load_rating(1, 1, 1, Some(100_000)).unwrap();
+    match warehouse.independent_restock(product_id, 2, &mut product_list, None) {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e),
+    }
+    if warehouse.independent_restock(product_id, 1, &mut product_list, None).is_ok() {
+        panic!("Level load rating was exceeded");
+    }
+    assert_eq!(warehouse.rows()[0].shelves[0].levels[0].load(), 80_000);
+    assert_eq!(product_list.product(product_id).unwrap().quantity, 2);
+    warehouse.print_capacity_report();
+
+    // The running load follows moves and is rebuilt when read back.
+    warehouse.move_item(&product_list, (1, 1, 1, 1), (1, 1, 2, 1)).unwrap();
+    assert_eq!(warehouse.rows()[0].shelves[0].levels[0].load(), 40_000);
+    assert_eq!(warehouse.rows()[0].shelves[0].load(), 80_000);
+    let loaded: Warehouse = serde_json::from_str(&serde_json::to_string(&warehouse).unwrap()).unwrap();
+    assert_eq!(loaded.rows()[0].shelves[0].levels[1].load(), 40_000);
+    warehouse.remove_item(1, 1, 2, 1).unwrap();
+    assert_eq!(warehouse.load(), 40_000);
+}
+
+#[test]
+fn vertical_oversized_restock() {
+    let mut product_list = ProductList::default();
+    let mut carpet = Product::new("Carpet", 2000, 0, Oversized(2));
+    carpet.set_levels_required(2);
+    product_list.add(carpet).unwrap();
+    let product_id = product_list.id_from_name("Carpet").unwrap();
+    for strategy in [Contiguous, RoundRobin, ClosestToStart] {
+        let mut warehouse = Warehouse::default().with_strategy(strategy);
+        match warehouse.independent_restock(product_id, 30, &mut product_list, None) {
+            Ok(_) => warehouse.print_items_and_names(&product_list),
+            Err(e) => panic!("{}", e),
+        }
+        let (row, shelf, level, zone) = warehouse.items_with_id(product_id)[0].placement;
+        match warehouse.item(row, shelf, level + 1, zone + 1) {
+            Some(item) => assert_eq!(item.placement, (row, shelf, level, zone)),
+            None => panic!("Upper level of item not found"),
+        }
+        assert_eq!(warehouse.occupied_zones(), 30 * 4);
+        match warehouse.remove_stock(product_id, 30) {
+            Ok(_) => assert!(warehouse.is_empty()),
+            Err(e) => panic!("{}", e),
+        }
+    }
+    let mut warehouse = Warehouse::default();
+    let apple = product_list.id_from_name("Apple").unwrap();
+    let item = ProductItem::new(apple, &product_list, (1, 1, 2, 2), None).unwrap();
+    warehouse.add_item(1, 1, 2, 2, item).unwrap();
+    let item = ProductItem::new(product_id, &product_list, (1, 1, 1, 1), None).unwrap();
+    assert!(warehouse.add_oversized_item(1, 1, 1, 1, item).is_err());
+    assert!(warehouse.item(1, 1, 1, 1).is_none());
+    assert_eq!(warehouse.occupied_zones(), 1);
+
+    // Stacks stand on every other level, so a full first level moves the
+    // carpet up to the third rather than onto the second.
+    for zone in 1..=10 {
+        let item = ProductItem::new(apple, &product_list, (1, 1, 1, zone), None).unwrap();
+        warehouse.add_item(1, 1, 1, zone, item).unwrap();
+    }
+    assert_eq!(warehouse.rows()[0].shelves[0].find_oversized_vacant_zone(2, 2), Some((3, 1)));
+}
+
+#[test]
+fn transfer_between_warehouses() {
+    let mut storage = Storage::with_warehouse("main");
+    storage.add_warehouse("north", Warehouse::default().with_strategy(RoundRobin)).unwrap();
+    let product_id = storage.find_product_id("Banana").unwrap();
+    storage.list.product_mut(product_id).unwrap().set_weight(Some(10));
+    let expiry_date = Some("2030-01-31".parse().unwrap());
+    match storage.restock_product(product_id, 20, expiry_date) {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e),
+    }
+    let timestamps: Vec<_> = storage.warehouse().items().iter().map(|item| item.timestamp).collect();
+    match storage.transfer_stock(product_id, 15, "main", "north") {
+        Ok(_) => storage.warehouses["north"].print_items_and_names(&storage.list),
+        Err(e) => panic!("{}", e),
+    }
+    let quantities = storage.product_quantities(product_id);
+    assert_eq!(quantities["main"], 5);
+    assert_eq!(quantities["north"], 15);
+    assert_eq!(storage.list.product(product_id).unwrap().quantity, 20);
+    for item in storage.warehouses["north"].items() {
+        assert_eq!(item.expiry_date, expiry_date);
+        assert!(timestamps.contains(&item.timestamp));
+    }
+    if storage.transfer_stock(product_id, 10, "main", "north").is_ok() {
+        panic!("Transferred more stock than available");
+    }
+
+    let placements: Vec<_> = storage.warehouse().items().iter().map(|item| item.placement).collect();
+    let north = storage.warehouses.get_mut("north").unwrap();
+    let loads: Vec<_> = north
+        .rows()
+        .iter()
+        .flat_map(|row| row.shelves.iter().map(|shelf| (row.number, shelf.number, shelf.load())))
+        .collect();
+    for (row, shelf, load) in loads {
+        north.set_shelf_load_rating(row, shelf, Some(load)).unwrap();
+    }
+    north.set_shelf_load_rating(1, 1, Some(170)).unwrap();
+    assert!(storage.transfer_stock(product_id, 5, "main", "north").is_err());
+    storage.add_warehouse("empty", Warehouse::new()).unwrap();
+    assert!(storage.transfer_stock(product_id, 5, "main", "empty").is_err());
+    let quantities = storage.product_quantities(product_id);
+    assert_eq!((quantities["main"], quantities["north"], quantities["empty"]), (5, 15, 0));
+    let unmoved: Vec<_> = storage.warehouse().items().iter().map(|item| item.placement).collect();
+    assert_eq!(placements, unmoved);
+}
+
+#[test]
+fn lot_recall() {
+    let mut storage = Storage::with_warehouse("main");
+    storage.add_warehouse("north", Warehouse::default()).unwrap();
+    let product_id = storage.find_product_id("Apple").unwrap();
+    let mut delivery = Delivery::new(None).with_lot(Some("A-17".to_string()));
+    match storage.restock_with(product_id, 4, &mut delivery) {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e),
+    }
+    match storage.transfer_stock(product_id, 4, "main", "north") {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e),
+    }
+    let mut delivery = Delivery::new(None).with_lot(Some("B-02".to_string()));
+    match storage.restock_with(product_id, 3, &mut delivery) {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e),
+    }
+    let locations = storage.lot_locations("A-17");
+    assert_eq!(locations.len(), 4);
+    assert!(locations.iter().all(|(warehouse, _)| *warehouse == "north"));
+
+    assert_eq!(storage.recall_lot("A-17", false).unwrap(), 4);
+    assert!(storage.warehouses["north"].items().iter().all(|item| item.status == ItemStatus::Quarantined));
+    if storage.transfer_stock(product_id, 1, "north", "main").is_ok() {
+        panic!("Transferred quarantined stock");
+    }
+
+    assert_eq!(storage.recall_lot("B-02", true).unwrap(), 3);
+    assert!(storage.warehouse().is_empty());
+    assert_eq!(storage.list.product(product_id).unwrap().quantity, 4);
+    let write_off = &storage.write_offs[0];
+    assert_eq!((write_off.lot.as_deref(), write_off.quantity, write_off.reason.as_str()), (Some("B-02"), 3, "Recall"));
+    if storage.recall_lot("C-99", false).is_ok() {
+        panic!("Recalled an unknown lot");
+    }
+
+    // Nothing is removed when the list cannot cover the recall.
+    storage.list.step_qty(product_id, -1).unwrap();
+    assert!(storage.recall_lot("A-17", true).is_err());
+    assert_eq!(storage.warehouses["north"].items().len(), 4);
+    assert_eq!(storage.write_offs.len(), 1);
+}
+
+#[test]
+fn serial_tracking() {
+    let mut storage = Storage::with_warehouse("main");
+    storage.add_warehouse("north", Warehouse::default()).unwrap();
+    let product_id = storage.find_product_id("Apple").unwrap();
+    let serials: Vec<String> = (1..=3).map(|n| format!("SN-{}", n)).collect();
+    let mut delivery = Delivery::new(None).with_serials(serials.clone());
+    match storage.restock_with(product_id, 3, &mut delivery) {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e),
+    }
+    storage.transfer_stock(product_id, 1, "main", "north").unwrap();
+    for serial in &serials {
+        assert!(storage.find_serial(serial).is_some());
+    }
+
+    let mut duplicate = Delivery::new(None).with_serials(vec!["SN-4".to_string(), "SN-1".to_string()]);
+    if storage.restock_with(product_id, 2, &mut duplicate).is_ok() {
+        panic!("Accepted a duplicate serial");
+    }
+    let mut mismatch = Delivery::new(None).with_serials(vec!["SN-5".to_string()]);
+    if storage.restock_with(product_id, 2, &mut mismatch).is_ok() {
+        panic!("Accepted fewer serials than units");
+    }
+
+    let (_, item) = storage.find_serial("SN-2").unwrap();
+    let placement = item.placement;
+    match storage.remove_serial("SN-2") {
+        Ok(_) => assert!(storage.warehouse().item(placement.0, placement.1, placement.2, placement.3).is_none()),
+        Err(e) => panic!("{}", e),
+    }
+    assert!(storage.find_serial("SN-2").is_none());
+    assert_eq!(storage.list.product(product_id).unwrap().quantity, 2);
+
+    let (warehouse, _) = storage.find_serial("SN-3").unwrap();
+    assert_eq!(warehouse, "north");
+    storage.remove_serial("SN-3").unwrap();
+    assert!(storage.warehouses["north"].items().is_empty());
+    assert_eq!(storage.list.product(product_id).unwrap().quantity, 1);
+
+    // Serials follow their item when it moves, and repeats within one delivery
+    // are refused.
+    let from = storage.find_serial("SN-1").unwrap().1.placement;
+    storage.move_item(from, (2, 1, 1, 1)).unwrap();
+    assert_eq!(storage.find_serial("SN-1").unwrap().1.placement, (2, 1, 1, 1));
+    let mut repeated = Delivery::new(None).with_serials(vec!["SN-6".to_string(), "SN-6".to_string()]);
+    if storage.restock_with(product_id, 2, &mut repeated).is_ok() {
+        panic!("Accepted a serial twice");
+    }
+}
+
+#[test]
+fn held_items_are_not_picked() {
+    let mut storage = Storage::with_warehouse("main");
+    let product_id = storage.find_product_id("Apple").unwrap();
+    match storage.restock_product(product_id, 4, None) {
+        Ok(_) => {},
+        Err(e) => panic!("{}", e),
+    }
+    storage.set_item_status((1, 1, 1, 1), ItemStatus::Damaged).unwrap();
+    storage.set_item_status((1, 1, 1, 2), ItemStatus::OnHold("customer claim".to_string())).unwrap();
+    let product = storage.list.product(product_id).unwrap();
+    assert_eq!((product.quantity, product.available_quantity(), product.held), (4, 2, 2));
+    assert_eq!(storage.warehouse().items_with_status("on-hold").len(), 1);
+    assert_eq!(storage.warehouse().items_with_status("On Hold").len(), 1);
+    assert_eq!(storage.warehouse().items_with_status(" DAMAGED ")[0].placement, (1, 1, 1, 1));
+
+    if storage.remove_stock(product_id, 3).is_ok() {
+        panic!("Picked held items");
+    }
+    match storage.remove_stock(product_id, 2) {
+        Ok(_) => assert!(storage.warehouse().items().iter().all(|item| !item.is_available())),
+        Err(e) => panic!("{}", e),
+    }
+    storage.set_item_status((1, 1, 1, 1), ItemStatus::Available).unwrap();
+    assert_eq!(storage.list.product(product_id).unwrap().available_quantity(), 1);
+}
+
+#[test]
+fn dispose_expired_stock() {
+    let mut storage = Storage::with_warehouse("main");
+    let product_id = storage.find_product_id("Apple").unwrap();
+    let price = storage.list.product(product_id).unwrap().price;
+    let mut expired = Delivery::new(Some("2020-01-31".parse().unwrap())).with_lot(Some("OLD".to_string()));
+    let mut fresh = Delivery::new(Some("2099-01-31".parse().unwrap()));
+    storage.restock_with(product_id, 3, &mut expired).unwrap();
+    storage.restock_with(product_id, 2, &mut fresh).unwrap();
+    storage.restock_product(product_id, 1, None).unwrap();
+    let today = "2024-06-01".parse().unwrap();
+
+    let preview = storage.dispose_expired(today, true).unwrap();
+    assert_eq!(preview.len(), 1);
+    assert_eq!(storage.warehouse().items().len(), 6);
+    assert!(storage.write_offs.is_empty());
+
+    let write_offs = storage.dispose_expired(today, false).unwrap();
+    assert_eq!(write_offs, preview);
+    assert_eq!(write_offs[0].quantity, 3);
+    assert_eq!(write_offs[0].value, price * 3);
+    assert_eq!(write_offs[0].lot.as_deref(), Some("OLD"));
+    assert_eq!(storage.warehouse().items().len(), 3);
+    assert_eq!(storage.list.product(product_id).unwrap().quantity, 3);
+    assert_eq!(storage.write_offs, write_offs);
+
+    // Nothing leaves any warehouse when the list cannot cover the disposal.
+    storage.warehouses.insert("north".to_string(), Warehouse::default());
+    storage.use_warehouse("north").unwrap();
+    storage.restock_with(product_id, 2, &mut expired).unwrap();
+    storage.use_warehouse("main").unwrap();
+    storage.restock_with(product_id, 1, &mut expired).unwrap();
+    storage.list.step_qty(product_id, -4).unwrap();
+    assert!(storage.dispose_expired(today, false).is_err());
+    assert_eq!(storage.warehouse().items().len(), 4);
+    assert_eq!(storage.warehouses["north"].items().len(), 2);
+    assert_eq!(storage.write_offs.len(), 1);
+
+    // A take that fails part-way puts back what it already took.
+    let mut items = storage.warehouse().items();
+    let mut missing = items[0].clone();
+    missing.placement = (9, 9, 9, 9);
+    items.insert(0, missing);
+    assert!(storage.warehouse_mut().take_stock(items.len(), items).is_err());
+    assert_eq!(storage.warehouse().items().len(), 4);
+}
+
+#[test]
+fn shelf_life_expiry() {
+    let mut warehouse = Warehouse::default();
+    let mut product_list = ProductList::default();
+    let product_id = product_list.id_from_name("Banana").unwrap();
+    if warehouse.independent_restock(product_id, 1, &mut product_list, None).is_ok() {
+        panic!("Restocked fragile product without expiry date");
+    }
+    product_list.product_mut(product_id).unwrap().set_shelf_life(Some(7));
+    let expected = chrono::Local::now().naive_local().date() + chrono::Days::new(7);
+    match warehouse.independent_restock(product_id, 2, &mut product_list, None) {
+        Ok(_) => assert!(warehouse.items().iter().all(|item| item.expiry_date == Some(expected))),
+        Err(e) => panic!("{}", e),
+    }
+    let explicit = "2030-01-31".parse().unwrap();
+    match warehouse.independent_restock(product_id, 1, &mut product_list, Some(explicit)) {
+        Ok(_) => assert_eq!(warehouse.items_with_id(product_id).iter().filter(|item| item.expiry_date == Some(explicit)).count(), 1),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn markdown_pricing() {
+    let mut warehouse = Warehouse::default();
+    let mut product_list = ProductList::default();
+    let apple = product_list.id_from_name("Apple").unwrap();
+    let banana = product_list.id_from_name("Banana").unwrap();
+    let today = chrono::Local::now().naive_local().date();
+    for (id, days) in [(apple, 2), (apple, 5), (apple, 30), (banana, 1)] {
+        let expiry_date = Some(today + chrono::Days::new(days));
+        warehouse.independent_restock(id, 1, &mut product_list, expiry_date).unwrap();
+    }
+    let rule = |days_before_expiry, percent_off| MarkdownRule { days_before_expiry, percent_off };
+    product_list.product_mut(apple).unwrap().markdowns = vec![rule(3, 30), rule(7, 10)];
+    product_list.quality_markdowns.insert("fragile".to_string(), vec![rule(2, 50)]);
+
+    let prices: Vec<_> = Warehouse::sort_by_expiry_date(warehouse.items_with_id(apple))
+        .iter()
+        .map(|item| product_list.effective_price(item, today).unwrap())
+        .collect();
+    assert_eq!(prices, vec![70, 90, 100]);
+    let banana_item = &warehouse.items_with_id(banana)[0];
+    assert_eq!(product_list.effective_price(banana_item, today), Some(25));
+}
+
+#[test]
+fn alert_digest() {
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    let banana = storage.find_product_id("Banana").unwrap();
+    let today = chrono::Local::now().naive_local().date();
+    for days in [-2, 0, 5, 20, 90] {
+        let expiry_date = Some(today + chrono::Duration::days(days));
+        storage.restock_product(apple, 1, expiry_date).unwrap();
+    }
+    storage.restock_product(banana, 1, Some(today + chrono::Duration::days(3))).unwrap();
+
+    let digest = AlertDigest::scan(&storage, today, &[30, 1, 7], 2);
+    assert_eq!(digest.expired.len(), 1);
+    let expiring: Vec<usize> = digest.expiring.values().map(|alerts| alerts.len()).collect();
+    assert_eq!(expiring, vec![1, 2, 1]);
+    assert_eq!(digest.low_stock.len(), 1);
+    assert_eq!(digest.low_stock[0].product_id, banana);
+    assert_eq!(digest.out_of_stock.len(), 1);
+
+    let path = std::env::temp_dir().join(format!("alert-digest-{}.json", std::process::id()));
+    let notifier = CommandNotifier::new(&format!("cat > {}", path.display()));
+    match notifier.notify(&digest) {
+        Ok(_) => assert_eq!(std::fs::read_to_string(&path).unwrap(), digest.to_json().unwrap()),
+        Err(e) => panic!("{}", e),
+    }
+    let _ = std::fs::remove_file(path);
+    assert!(CommandNotifier::new("exit 1").notify(&digest).is_err());
+}
+
+#[test]
+fn replenishment_suggestions() {
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    let watermelon = storage.find_product_id("Watermelon").unwrap();
+    storage.restock_product(apple, 2, None).unwrap();
+    storage.set_reorder_policy(apple, Some(5), None, Some(8)).unwrap();
+    storage.set_reorder_policy(watermelon, Some(10), Some(10_000), None).unwrap();
+
+    let on_order = std::collections::BTreeMap::from([(apple, 1)]);
+    let report = storage.replenishment(&on_order);
+    assert_eq!(report.len(), 2);
+    assert_eq!((report[0].product_id, report[0].on_hand, report[0].on_order, report[0].suggested), (apple, 2, 1, 2));
+    let capacity = storage.warehouse().check_oversized_capacity(3, 1);
+    assert_eq!((report[1].product_id, report[1].suggested), (watermelon, capacity - 1));
+
+    storage.restock_product(apple, 3, None).unwrap();
+    assert_eq!(storage.replenishment(&on_order).len(), 1);
+
+    let mut storage = Storage::with_warehouse("main");
+    let banana = storage.find_product_id("Banana").unwrap();
+    let expiry_date = Some("2030-01-01".parse().unwrap());
+    storage.restock_product(banana, 300, expiry_date).unwrap();
+    storage.set_reorder_policy(banana, Some(1_000), Some(1_000), None).unwrap();
+    assert_eq!(storage.replenishment(&on_order)[0].suggested, 2 * 6 * 3 * 10 - 300);
+    storage.set_reorder_policy(watermelon, Some(10), Some(10_000), None).unwrap();
+    assert_eq!(storage.replenishment(&on_order)[1].suggested, 2 * 6 * 4 * 3 - 10 * 3 * 3);
+
+    storage.warehouses.insert("north".to_string(), Warehouse::default());
+    assert_eq!(storage.replenishment(&on_order)[0].capacity, 2 * (2 * 6 * 3 * 10) - 300);
+}
+
+#[test]
+fn receive_purchase_order() {
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    let banana = storage.find_product_id("Banana").unwrap();
+    let supplier = storage.add_supplier("Fruitco", None);
+    let po = storage.create_purchase_order(supplier).unwrap();
+    storage.add_purchase_order_line(po, PurchaseOrderLine::new(apple, 5, 40, None)).unwrap();
+    storage.add_purchase_order_line(po, PurchaseOrderLine::new(banana, 3, 20, None)).unwrap();
+    assert_eq!(storage.on_order()[&apple], 5);
+
+    storage.receive(po, Some(1), Some(3), None, None).unwrap();
+    assert_eq!(storage.purchase_orders[&po].status, PurchaseOrderStatus::PartiallyReceived);
+    assert_eq!(storage.on_order()[&apple], 2);
+    let expiry_date = Some("2030-01-31".parse().unwrap());
+    storage.receive(po, Some(2), None, expiry_date, Some("B7".to_string())).unwrap();
+    assert_eq!(storage.lot_locations("B7").len(), 3);
+    storage.receive(po, Some(1), Some(4), None, None).unwrap();
+
+    let order = &storage.purchase_orders[&po];
+    assert_eq!(order.status, PurchaseOrderStatus::Closed);
+    assert_eq!(order.lines[0].variance(), 2);
+    assert_eq!(storage.list.product(apple).unwrap().quantity, 7);
+    assert!(storage.on_order().values().all(|quantity| *quantity == 0));
+    if storage.receive(po, None, None, None, None).is_ok() {
+        panic!("Received against a closed purchase order");
+    }
+
+    // A line that does not fit leaves the lines before it unreceived.
+    let po = storage.create_purchase_order(supplier).unwrap();
+    storage.add_purchase_order_line(po, PurchaseOrderLine::new(apple, 5, 40, None)).unwrap();
+    storage.add_purchase_order_line(po, PurchaseOrderLine::new(banana, 400, 20, None)).unwrap();
+    let ledger = storage.cost_ledger[&apple].len();
+    assert!(storage.receive(po, None, None, None, None).is_err());
+    assert_eq!(storage.list.product(apple).unwrap().quantity, 7);
+    assert_eq!(storage.warehouse().find_all_item_occurences(apple).len(), 7);
+    assert_eq!(storage.cost_ledger[&apple].len(), ledger);
+    assert_eq!(storage.on_order()[&apple], 5);
+}
+
+#[test]
+fn return_inspection() {
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    let expiry_date = Some("2030-01-31".parse().unwrap());
+    let rma = storage.create_return(apple, 4, expiry_date, Some("R1".to_string()), None).unwrap();
+
+    storage.inspect_return(rma, InspectionOutcome::Restockable, 2).unwrap();
+    storage.inspect_return(rma, InspectionOutcome::Quarantine, 1).unwrap();
+    storage.inspect_return(rma, InspectionOutcome::Scrap, 1).unwrap();
+    if storage.inspect_return(rma, InspectionOutcome::Scrap, 1).is_ok() {
+        panic!("Inspected more units than returned");
+    }
+
+    let items = storage.lot_locations("R1");
+    assert_eq!(items.len(), 3);
+    assert!(items.iter().all(|(_, item)| item.expiry_date == expiry_date));
+    let product = storage.list.product(apple).unwrap();
+    assert_eq!((product.quantity, product.held), (3, 1));
+    assert_eq!(storage.write_offs.len(), 1);
+    let kinds: Vec<_> = storage.journal.iter().map(|movement| movement.kind).collect();
+    assert_eq!(kinds, vec![MovementKind::ReturnRestocked, MovementKind::ReturnQuarantined, MovementKind::ReturnScrapped]);
+    assert_eq!(storage.returns[&rma].pending(), 0);
+}
+
+#[test]
+fn inventory_valuation() {
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    storage.restock_with(apple, 4, &mut Delivery::new(None).with_unit_cost(Some(60))).unwrap();
+    storage.restock_with(apple, 4, &mut Delivery::new(None).with_unit_cost(Some(90))).unwrap();
+    storage.remove_stock(apple, 6).unwrap();
+    assert!(storage.warehouse().items_with_id(apple).iter().all(|item| item.unit_cost.is_some()));
+
+    let fifo = storage.valuation(CostMethod::Fifo, Some(apple)).unwrap();
+    let product = &fifo.products[0];
+    assert_eq!((product.on_hand, product.sold, product.revenue), (2, 6, 600));
+    assert_eq!((product.stock_value, product.cost_of_goods), (180, 420));
+    assert_eq!(product.margin(), 180);
+
+    let average = storage.valuation(CostMethod::WeightedAverage, None).unwrap();
+    assert_eq!((average.stock_value(), average.cost_of_goods()), (150, 450));
+    assert_eq!(average.margin(), 150);
+
+    // Sales keep the price they were made at; emptying the rest is no sale.
+    storage.change_price(apple, 200).unwrap();
+    storage.empty_stock(apple).unwrap();
+    let product = &storage.valuation(CostMethod::Fifo, Some(apple)).unwrap().products[0];
+    assert_eq!((product.sold, product.revenue, product.cost_of_goods), (6, 600, 420));
+    assert_eq!((product.written_off, product.write_off_cost), (2, 180));
+    assert_eq!(product.margin(), 180);
+
+    // Costs are in the storage currency, so a product priced in another one is refused.
+    storage.change_currency(apple, Some("JPY")).unwrap();
+    assert!(storage.valuation(CostMethod::Fifo, None).is_err());
+
+    // A restock that runs out of space part-way leaves no stock without a receipt.
+    let banana = storage.find_product_id("Banana").unwrap();
+    let mut delivery = Delivery::new(Some("2030-01-01".parse().unwrap()));
+    assert!(storage.restock_with(banana, 400, &mut delivery).is_err());
+    assert_eq!(storage.list.product(banana).unwrap().quantity, 0);
+    assert!(storage.warehouse().items_with_id(banana).is_empty());
+    assert!(!storage.cost_ledger.contains_key(&banana));
+}
+
+#[test]
+fn scheduled_price_changes() {
+    let mut storage = Storage { list: ProductList::default(), ..Storage::default() };
+    let apple = storage.find_product_id("Apple").unwrap();
+    storage.change_price_with(apple, 120, Some("Supplier increase".to_string())).unwrap();
+    storage.schedule_price(apple, "2030-01-01".parse().unwrap(), 150, None).unwrap();
+    storage.schedule_price(apple, "2030-06-01".parse().unwrap(), 90, None).unwrap();
+    assert_eq!(storage.list.product(apple).unwrap().price, 120);
+
+    assert_eq!(storage.apply_scheduled_prices("2030-03-01".parse().unwrap()), 1);
+    let product = storage.list.product(apple).unwrap();
+    assert_eq!(product.price, 150);
+    assert_eq!(product.scheduled_prices.len(), 1);
+    let history: Vec<_> = product.price_history.iter().map(|c| (c.old_price, c.new_price)).collect();
+    assert_eq!(history, vec![(100, 120), (120, 150)]);
+    assert_eq!(product.price_history[0].reason.as_deref(), Some("Supplier increase"));
+}
+
+#[test]
+fn currency_formatting() {
+    let mut storage = Storage::default();
+    let usd = Currency::default();
+    assert_eq!(usd.format(123456, Locale::EnUs), "$1,234.56");
+    assert_eq!(usd.parse("1,234.56", Locale::EnUs).unwrap(), 123456);
+    assert_eq!(usd.parse("2,5", Locale::EnUs).unwrap(), 250);
+
+    let yen = Currency::from_code("jpy").unwrap();
+    assert_eq!(yen.format(1500, Locale::EnUs), "¥1,500");
+    assert!(yen.parse("15.5", Locale::EnUs).is_err());
+    let dinar = Currency::from_code("KWD").unwrap();
+    assert_eq!(dinar.parse("1,5", Locale::DeDe).unwrap(), 1500);
+    assert_eq!(dinar.format(1234567, Locale::DeDe), "1.234,567 KD");
+
+    storage.set_currency("EUR").unwrap();
+    storage.set_locale("pt-BR").unwrap();
+    let format = storage.price_format();
+    assert_eq!(format.format(129990), "€ 1.299,90");
+    assert_eq!(format.parse("1.299,90").unwrap(), 129990);
+    assert_eq!(format.format_in(1500, Some(&yen)), "¥ 1.500");
+    let totals = [(usd.clone(), 250), (yen.clone(), 1500)].into_iter().collect();
+    assert_eq!(format.format_totals(&totals), "¥ 1.500 + $ 2,50");
+
+    // Codes are stored as text and checked when the storage is read back.
+    assert_eq!(serde_json::to_string(&yen).unwrap(), "\"JPY\"");
+    assert!(serde_json::from_str::<Currency>("\"XYZ\"").is_err());
+}
+
+#[test]
+fn sku_and_barcode_identifiers() {
+    assert!(validate_barcode("4006381333931").is_ok());
+    assert!(validate_barcode("036000291452").is_ok());
+    assert!(validate_barcode("96385074").is_ok());
+    assert!(validate_barcode("4006381333932").is_err());
+    assert!(validate_barcode("40063813339").is_err());
+
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    storage.change_sku(apple, Some("APL-01".to_string())).unwrap();
+    storage.change_barcode(apple, Some("036000291452".to_string())).unwrap();
+
+    let mut rice = Product::new("Rice", 250, 0, Normal);
+    rice.sku = Some("apl-01".to_string());
+    assert!(storage.add_product(rice.clone()).is_err());
+    rice.sku = None;
+    rice.barcode = Some("0036000291452".to_string());
+    assert!(storage.add_product(rice.clone()).is_err());
+    rice.barcode = Some("4006381333931".to_string());
+    rice.sku = Some(" RCE-01 ".to_string());
+    let rice = storage.add_product(rice).unwrap();
+    assert_eq!(storage.list.product(rice).unwrap().sku.as_deref(), Some("RCE-01"));
+
+    assert_eq!(storage.find_product_by_sku("APL-01").unwrap(), apple);
+    assert_eq!(storage.find_product_id("APL-01"), None);
+    let mut delivery = Delivery::new(None).with_serials(vec!["AP-1".to_string(), "AP-2".to_string()]);
+    assert_eq!(storage.scan("036000291452", Some(&mut delivery)).unwrap(), apple);
+    storage.scan("036000291452", Some(&mut delivery)).unwrap();
+    storage.scan("036000291452", None).unwrap();
+    assert_eq!(storage.list.product(apple).unwrap().quantity, 1);
+    assert!(delivery.serials.is_empty());
+    assert!(storage.find_serial("AP-1").is_some() != storage.find_serial("AP-2").is_some());
+    assert!(storage.scan("96385074", None).is_err());
+}
+
+#[test]
+fn product_id_allocation() {
+    let list = ProductList::default();
+    let ids: Vec<u64> = ["Apple", "Banana", "Watermelon"].iter().map(|name| list.id_from_name(name).unwrap()).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+
+    let json = serde_json::to_string(&list).unwrap();
+    let mut list: ProductList = serde_json::from_str(&json).unwrap();
+    assert_eq!(list.add(Product::new("Rice", 250, 0, Normal)).unwrap(), 4);
+
+    let legacy = r#"{"products": {"924843": {"id": 924843, "name": "Apple", "price": 100, "quantity": 0, "quality": "Normal"}}}"#;
+    let mut legacy: ProductList = serde_json::from_str(legacy).unwrap();
+    assert!(legacy.product(924843).is_some());
+    assert_eq!(legacy.add(Product::new("Rice", 250, 0, Normal)).unwrap(), 1);
+
+    legacy.set_id_allocator(IdAllocator::Manual);
+    assert!(legacy.add(Product::new("Tea", 100, 0, Normal)).is_err());
+    let mut tea = Product::new("Tea", 100, 0, Normal);
+    tea.id = 924843;
+    assert!(legacy.add(tea.clone()).is_err());
+    tea.id = 77;
+    assert_eq!(legacy.add(tea).unwrap(), 77);
+
+    legacy.set_id_allocator(IdAllocator::Uuid);
+    for name in ["Coffee", "Cocoa", "Mate", "Chai", "Matcha", "Rooibos", "Sencha", "Oolong"] {
+        let id = legacy.add(Product::new(name, 300, 0, Normal)).unwrap();
+        assert!(id > 0 && id <= i64::MAX as u64 && legacy.product(id).is_some());
+    }
+}
+
+#[test]
+fn category_rollups() {
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    let watermelon = storage.find_product_id("Watermelon").unwrap();
+    storage.change_category(apple, Some(" Produce / Fruit ")).unwrap();
+    storage.change_category(watermelon, Some("Produce/Fruit/Melons")).unwrap();
+    storage.change_tags(apple, &["Organic/EU".to_string()], &[]).unwrap();
+    storage.restock_product(apple, 4, None).unwrap();
+    storage.restock_product(watermelon, 2, None).unwrap();
+
+    assert_eq!(storage.list.filter_by_category("produce").len(), 2);
+    assert_eq!(storage.list.filter_by_category("Produce/Fruit/Melons").len(), 1);
+    assert!(storage.list.filter_by_category("Produce/Fr").is_empty());
+    assert_eq!(storage.list.filter_by_tag("organic").len(), 1);
+
+    let rollups = storage.category_rollups();
+    let produce = rollups.iter().find(|rollup| rollup.category == "Produce").unwrap();
+    assert_eq!((produce.products, produce.quantity), (2, 6));
+    assert_eq!(produce.value.get(&Currency::default()), Some(&(4 * 100 + 2 * 75)));
+    assert_eq!(produce.zones, 4 + 2 * 3);
+    let uncategorized = rollups.iter().find(|rollup| rollup.category == "Uncategorized").unwrap();
+    assert_eq!((uncategorized.products, uncategorized.quantity), (1, 0));
+}
+
+#[test]
+fn product_query() {
+    let mut storage = Storage::with_warehouse("main");
+    let apple = storage.find_product_id("Apple").unwrap();
+    let banana = storage.find_product_id("Banana").unwrap();
+    storage.restock_product(apple, 3, None).unwrap();
+    let expiry = chrono::Local::now().naive_local().date() + chrono::Duration::days(5);
+    storage.restock_product(banana, 2, Some(expiry)).unwrap();
+    let names = |products: Vec<&Product>| products.iter().map(|p| p.name().to_string()).collect::<Vec<_>>();
+    let format = storage.price_format();
+
+    let query = Query::parse("price<=0.80 and qty>0", &format).unwrap();
+    assert_eq!(names(storage.query_products(&query).unwrap()), ["Banana"]);
+    let query = Query::parse("(quality=fragile or name~melon) and not qty>0", &format).unwrap();
+    assert_eq!(names(storage.query_products(&query).unwrap()), ["Watermelon"]);
+    let query = Query::parse("price>=0.5", &format).unwrap().sort_by("price", Some("desc")).unwrap().limit(2);
+    assert_eq!(names(storage.query_products(&query).unwrap()), ["Apple", "Watermelon"]);
+
+    let query = Query::new()
+        .and(Expr::compare(Field::Name, Op::Contains, "a", &format).unwrap())
+        .and(Expr::compare(Field::Price, Op::Le, "0.80", &format).unwrap());
+    assert_eq!(names(storage.query_products(&query).unwrap()), ["Banana", "Watermelon"]);
+
+    let items = storage.query_items(&Query::parse("name=apple and zone>=0", &format).unwrap());
+    assert_eq!(items.len(), 3);
+    let items = storage.query_items(&Query::parse("expiry<=today+7 and quality=fragile", &format).unwrap());
+    assert_eq!(items.len(), 2);
+    assert!(storage.query_products(&Query::parse("lot=A1", &format).unwrap()).is_err());
+    assert!(Query::parse("price<=abc", &format).is_err());
+    assert!(Query::parse("(qty>0", &format).is_err());
+}
+
+#[test]
+fn fuzzy_product_search() {
+    let mut product_list = ProductList::default();
+    product_list.add(Product::new("Pineapple", 300, 0, Normal)).unwrap();
+    product_list.add(Product::new("Apple Juice", 250, 0, Normal)).unwrap();
+    let names = |query: &str| product_list.search_by_name(query).iter().map(|p| p.name().to_string()).collect::<Vec<_>>();
+
+    assert_eq!(names("apple"), ["Apple", "Apple Juice", "Pineapple"]);
+    assert_eq!(names("pine"), ["Pineapple"]);
+    assert_eq!(names("aple"), ["Apple", "Apple Juice"]);
+    assert_eq!(names("watremelon"), ["Watermelon"]);
+    assert!(names("kiwi").is_empty());
+    assert_eq!(product_list.id_from_name_ignore_case("apple  juice"), product_list.id_from_name("Apple Juice"));
+
+    let mut storage = Storage { list: product_list, ..Storage::with_warehouse("main") };
+    let error = storage.restock_by_name("Banan", 1, None).unwrap_err();
+    assert!(error.to_string().ends_with("Did you mean \"Banana\"?"));
+}
+
+#[test]
+fn product_name_index() {
+    let mut product_list = ProductList::default();
+    let apple = product_list.id_from_name("Apple").unwrap();
+    assert_eq!(product_list.id_from_name(" APPLE "), None);
+    assert_eq!(product_list.id_from_name_ignore_case(" APPLE "), Some(apple));
+
+    product_list.rename(apple, "Green Apple").unwrap();
+    assert_eq!(product_list.id_from_name("Apple"), None);
+    assert_eq!(product_list.id_from_name_ignore_case("green apple"), Some(apple));
+    assert!(product_list.rename(apple, "Banana").is_err());
+    assert!(product_list.add(Product::new("Green Apple", 90, 0, Normal)).is_err());
+    let red_apple = product_list.add(Product::new("GREEN APPLE ", 90, 0, Normal)).unwrap();
+    assert_eq!(product_list.ids_from_name_ignore_case("green apple"), [apple, red_apple]);
+    assert_eq!(product_list.id_from_name_ignore_case("green apple"), None);
+
+    product_list.remove_by_name("Green Apple").unwrap();
+    assert_eq!(product_list.id_from_name_ignore_case("green apple"), Some(red_apple));
+
+    let json = serde_json::to_string(&product_list).unwrap();
+    assert!(!json.contains("names"));
+    let loaded: ProductList = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.id_from_name("Banana"), product_list.id_from_name("Banana"));
+    assert_eq!(loaded.id_from_name_ignore_case("green apple"), Some(red_apple));
+}
+
+#[test]
+fn zone_bitmap_placement() {
+    let mut bitmap = ZoneBitmap::from_occupancy((0..130).map(|zone| zone % 40 < 10));
+    assert_eq!((bitmap.occupied(), bitmap.leading_free(), bitmap.trailing_free()), (40, 0, 0));
+    assert_eq!(bitmap.longest_free(), 30);
+    assert_eq!(bitmap.find_free_run(0, 30), Some(10));
+    assert_eq!(bitmap.find_free_run(11, 30), Some(50));
+    assert_eq!(bitmap.find_free_run(0, 31), None);
+    bitmap.set(40, false);
+    bitmap.summarize();
+    assert_eq!((bitmap.longest_free(), bitmap.find_free_run(0, 31)), (31, Some(10)));
+    assert_eq!(bitmap.free_runs().next(), Some((10, 31)));
+
+    let mut product_list = ProductList::default();
+    let apple = product_list.id_from_name("Apple").unwrap();
+    let mut warehouse = Warehouse::default();
+    let zones_per_level = warehouse.rows()[0].shelves[0].levels[0].zones.len();
+    warehouse.independent_restock(apple, zones_per_level + 2, &mut product_list, None).unwrap();
+    warehouse.remove_item(1, 1, 1, 2).unwrap();
+    assert_eq!(warehouse.find_first_contiguous_space(1), Some((1, 1, 1, 2)));
+    assert_eq!(warehouse.find_first_contiguous_space(2), Some((1, 1, 2, 3)));
+    assert_eq!(warehouse.find_round_robin_continuation(), Some((1, 1, 2, 3)));
+
+    let loaded: Warehouse = serde_json::from_str(&serde_json::to_string(&warehouse).unwrap()).unwrap();
+    assert_eq!(loaded.occupied_zones(), zones_per_level + 1);
+    assert_eq!(loaded.find_first_contiguous_space(2), Some((1, 1, 2, 3)));
+}
+
+#[test]
+fn product_location_index() {
+    let mut product_list = ProductList::default();
+    let apple = product_list.id_from_name("Apple").unwrap();
+    let watermelon = product_list.id_from_name("Watermelon").unwrap();
+    let scan = |warehouse: &Warehouse, id: u64| {
+        let mut placements: Vec<_> = warehouse.items().iter().filter(|item| item.id == id).map(|item| item.placement).collect();
+        placements.sort();
+        placements
+    };
+    for strategy in [Contiguous, RoundRobin, ClosestToStart] {
+        let mut warehouse = Warehouse::default().with_strategy(strategy);
+        warehouse.independent_restock(apple, 250, &mut product_list, None).unwrap();
+        warehouse.independent_restock(watermelon, 20, &mut product_list, None).unwrap();
+        warehouse.remove_stock(apple, 30).unwrap();
+        warehouse.remove_stock(watermelon, 5).unwrap();
+        for id in [apple, watermelon] {
+            assert_eq!(warehouse.find_all_item_occurences(id), scan(&warehouse, id));
+        }
+        assert_eq!(warehouse.items_with_id(apple).len(), 220);
+        assert_eq!(warehouse.find_first_item_occurrence(watermelon), scan(&warehouse, watermelon).first().copied());
+        assert_eq!(warehouse.find_last_item_occurrence(apple), scan(&warehouse, apple).last().copied());
+
+        let loaded: Warehouse = serde_json::from_str(&serde_json::to_string(&warehouse).unwrap()).unwrap();
+        assert_eq!(loaded.find_all_item_occurences(watermelon), scan(&warehouse, watermelon));
+        let free = scan(&warehouse, watermelon)[0];
+        warehouse.remove_all_stock(watermelon).unwrap();
+        assert!(!warehouse.contains_product(watermelon) && warehouse.contains_product(apple));
+        let first = warehouse.find_first_item_occurrence(apple).unwrap();
+        let last = warehouse.find_last_item_occurrence(apple).unwrap();
+        warehouse.move_item(&product_list, first, free).unwrap();
+        assert!(warehouse.move_item(&product_list, free, last).is_err());
+        assert!(warehouse.item(free.0, free.1, free.2, free.3).is_some());
+        assert_eq!(warehouse.find_all_item_occurences(apple), scan(&warehouse, apple));
+        warehouse.remove_row(2).unwrap();
+        assert_eq!(warehouse.find_all_item_occurences(apple), scan(&warehouse, apple));
+    }
+}
+
+#[test]
+fn sqlite_backend_round_trip() {
+    let path = std::env::temp_dir().join(format!("storage-backend-{}.db", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let _ = std::fs::remove_file(&path);
+    let mut storage = Storage { file_path: path.clone(), ..Storage::with_warehouse("main") };
+    storage.add_warehouse("north", Warehouse::default().with_strategy(RoundRobin)).unwrap();
+    let mut carpet = Product::new("Carpet", 2000, 0, Oversized(2));
+    carpet.set_levels_required(2);
+    let carpet = storage.add_product(carpet).unwrap();
+    let apple = storage.find_product_id("Apple").unwrap();
+    let banana = storage.find_product_id("Banana").unwrap();
+    storage.restock_product(apple, 40, None).unwrap();
+    storage.restock_product(banana, 10, Some("2030-01-31".parse().unwrap())).unwrap();
+    storage.restock_product(carpet, 3, None).unwrap();
+    storage.set_item_status(storage.warehouse().items_with_id(apple)[0].placement, ItemStatus::Damaged).unwrap();
+    storage.warehouse_mut().set_shelf_load_rating(1, 2, Some(500_000)).unwrap();
+    storage.save().unwrap();
+
+    let load = |path: &str| {
+        let mut loaded = Storage::default();
+        Storage::load(path, &mut loaded).unwrap();
+        loaded
+    };
+    let mut loaded = load(&path);
+    assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&storage).unwrap());
+    assert_eq!(loaded.warehouse().find_all_item_occurences(carpet), storage.warehouse().find_all_item_occurences(carpet));
+
+    loaded.remove_stock(apple, 15).unwrap();
+    loaded.remove_warehouse("north").unwrap();
+    let pear = loaded.add_product(Product::new("Pear", 150, 0, Normal)).unwrap();
+    loaded.delete_product_by_id(pear).unwrap();
+    loaded.save().unwrap();
+    let reloaded = load(&path);
+    assert_eq!(serde_json::to_value(&reloaded).unwrap(), serde_json::to_value(&loaded).unwrap());
+    assert!(!reloaded.warehouses.contains_key("north"));
+
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    let count = |sql: &str| connection.query_row(sql, [], |row| row.get::<_, usize>(0)).unwrap();
+    assert_eq!(count("SELECT COUNT(*) FROM items"), loaded.warehouse().items().len());
+    assert_eq!(count("SELECT COUNT(*) FROM products"), loaded.list.products().count());
+    assert_eq!(count(&format!("SELECT COUNT(*) FROM items WHERE product_id = {}", apple)), 25);
+    assert!(format!("{:?}", backend::for_path("storage.json")).starts_with("JsonBackend"));
+    assert!(format!("{:?}", backend::for_path(&path)).starts_with("SqliteBackend"));
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn save_after_loading_a_copy() {
+    let temp = |name: &str| {
+        let path = std::env::temp_dir().join(format!("storage-copy-{}.{}", std::process::id(), name));
+        path.to_str().unwrap().to_string()
+    };
+    let (json, db) = (temp("json"), temp("db"));
+    let _ = std::fs::remove_file(&db);
+    let mut storage = Storage { file_path: json.clone(), ..Storage::with_warehouse("main") };
+    let apple = storage.find_product_id("Apple").unwrap();
+    storage.restock_product(apple, 10, None).unwrap();
+    storage.save_as(&db).unwrap();
+
+    // The copy saves back to the file it was loaded from, not the original.
+    let mut loaded = Storage::default();
+    Storage::load(&db, &mut loaded).unwrap();
+    loaded.remove_stock(apple, 4).unwrap();
+    loaded.save().unwrap();
+    let mut reloaded = Storage::default();
+    Storage::load(&db, &mut reloaded).unwrap();
+    assert_eq!(reloaded.list.product(apple).unwrap().quantity, 6);
+    assert!(!std::path::Path::new(&json).exists());
+    let _ = std::fs::remove_file(db);
+}
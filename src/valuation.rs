@@ -0,0 +1,290 @@
+use crate::{
+    currency::{PriceFormat, Priced},
+    product::Product,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display, Formatter},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostMethod {
+    Fifo,
+    WeightedAverage,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CostEntry {
+    Receipt {
+        date: NaiveDate,
+        quantity: usize,
+        unit_cost: Option<u64>,
+    },
+    Issue {
+        date: NaiveDate,
+        quantity: usize,
+        /// Unit price of a sale; write-offs leave it empty.
+        #[serde(default)]
+        unit_price: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductValuation {
+    pub product_id: u64,
+    pub product_name: String,
+    pub on_hand: usize,
+    pub stock_value: u64,
+    pub sold: usize,
+    pub revenue: u64,
+    pub cost_of_goods: u64,
+    pub written_off: usize,
+    pub write_off_cost: u64,
+}
+
+// Issued units, split between sales and write-offs.
+#[derive(Debug, Default)]
+struct Issued {
+    sold: usize,
+    revenue: u64,
+    cost_of_goods: u64,
+    written_off: usize,
+    write_off_cost: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValuationReport {
+    pub method: CostMethod,
+    pub products: Vec<ProductValuation>,
+}
+
+impl Display for CostMethod {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CostMethod::Fifo => write!(f, "FIFO"),
+            CostMethod::WeightedAverage => write!(f, "weighted average"),
+        }
+    }
+}
+
+impl Priced for ProductValuation {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        write!(
+            f,
+            "{} (ID {}): {} on hand valued at {}, {} sold for {} at cost {}, {} written off at cost {}, margin {}",
+            self.product_name,
+            self.product_id,
+            self.on_hand,
+            format.format(self.stock_value),
+            self.sold,
+            format.format(self.revenue),
+            format.format(self.cost_of_goods),
+            self.written_off,
+            format.format(self.write_off_cost),
+            format_margin(self.margin(), format)
+        )
+    }
+}
+
+impl Priced for ValuationReport {
+    fn fmt_priced(&self, f: &mut Formatter, format: &PriceFormat) -> fmt::Result {
+        writeln!(f, "Inventory valuation ({})", self.method)?;
+        for product in &self.products {
+            writeln!(f, "  {}", product.priced(format))?;
+        }
+        write!(
+            f,
+            "Total: {} on hand valued at {}, revenue {}, cost of goods {}, write-offs {}, margin {}",
+            self.products.iter().map(|p| p.on_hand).sum::<usize>(),
+            format.format(self.stock_value()),
+            format.format(self.revenue()),
+            format.format(self.cost_of_goods()),
+            format.format(self.write_off_cost()),
+            format_margin(self.margin(), format)
+        )
+    }
+}
+
+impl CostEntry {
+    pub fn receipt(date: NaiveDate, quantity: usize, unit_cost: Option<u64>) -> Self {
+        CostEntry::Receipt {
+            date,
+            quantity,
+            unit_cost,
+        }
+    }
+
+    pub fn sale(date: NaiveDate, quantity: usize, unit_price: u64) -> Self {
+        CostEntry::Issue {
+            date,
+            quantity,
+            unit_price: Some(unit_price),
+        }
+    }
+
+    pub fn write_off(date: NaiveDate, quantity: usize) -> Self {
+        CostEntry::Issue {
+            date,
+            quantity,
+            unit_price: None,
+        }
+    }
+}
+
+impl Issued {
+    fn add(&mut self, quantity: usize, unit_price: Option<u64>, cost: u64) {
+        match unit_price {
+            Some(unit_price) => {
+                self.sold += quantity;
+                self.revenue += unit_price * quantity as u64;
+                self.cost_of_goods += cost;
+            }
+            None => {
+                self.written_off += quantity;
+                self.write_off_cost += cost;
+            }
+        }
+    }
+}
+
+impl ProductValuation {
+    pub fn new(product: &Product, on_hand: usize, entries: &[CostEntry], method: CostMethod) -> Self {
+        let (stock_value, issued) = match method {
+            CostMethod::Fifo => fifo(entries),
+            CostMethod::WeightedAverage => weighted_average(entries),
+        };
+        ProductValuation {
+            product_id: product.id,
+            product_name: product.name().to_string(),
+            on_hand,
+            stock_value,
+            sold: issued.sold,
+            revenue: issued.revenue,
+            cost_of_goods: issued.cost_of_goods,
+            written_off: issued.written_off,
+            write_off_cost: issued.write_off_cost,
+        }
+    }
+
+    pub fn margin(&self) -> i64 {
+        self.revenue as i64 - self.cost_of_goods as i64
+    }
+}
+
+impl ValuationReport {
+    pub fn stock_value(&self) -> u64 {
+        self.products.iter().map(|p| p.stock_value).sum()
+    }
+
+    pub fn revenue(&self) -> u64 {
+        self.products.iter().map(|p| p.revenue).sum()
+    }
+
+    pub fn cost_of_goods(&self) -> u64 {
+        self.products.iter().map(|p| p.cost_of_goods).sum()
+    }
+
+    pub fn write_off_cost(&self) -> u64 {
+        self.products.iter().map(|p| p.write_off_cost).sum()
+    }
+
+    pub fn margin(&self) -> i64 {
+        self.products.iter().map(|p| p.margin()).sum()
+    }
+}
+
+fn format_margin(margin: i64, format: &PriceFormat) -> String {
+    match margin {
+        margin if margin < 0 => format!("-{}", format.format(margin.unsigned_abs())),
+        margin => format.format(margin as u64),
+    }
+}
+
+// Receipts without a known cost come in at the running average, so returns
+// and legacy stock do not drag the valuation to zero.
+fn fifo(entries: &[CostEntry]) -> (u64, Issued) {
+    let mut layers: VecDeque<(usize, u64)> = VecDeque::new();
+    let mut issued = Issued::default();
+    for entry in entries {
+        match entry {
+            CostEntry::Receipt {
+                quantity,
+                unit_cost,
+                ..
+            } => {
+                let unit_cost = unit_cost.unwrap_or_else(|| average(&layers));
+                layers.push_back((*quantity, unit_cost));
+            }
+            CostEntry::Issue {
+                quantity,
+                unit_price,
+                ..
+            } => {
+                let (mut remaining, mut cost) = (*quantity, 0);
+                while remaining > 0 {
+                    let Some(layer) = layers.front_mut() else {
+                        break;
+                    };
+                    let taken = remaining.min(layer.0);
+                    cost += taken as u64 * layer.1;
+                    layer.0 -= taken;
+                    remaining -= taken;
+                    if layer.0 == 0 {
+                        layers.pop_front();
+                    }
+                }
+                issued.add(*quantity, *unit_price, cost);
+            }
+        }
+    }
+    let stock_value = layers.iter().map(|(quantity, cost)| *quantity as u64 * cost).sum();
+    (stock_value, issued)
+}
+
+fn weighted_average(entries: &[CostEntry]) -> (u64, Issued) {
+    let (mut quantity_on_hand, mut stock_value) = (0usize, 0u64);
+    let mut issued = Issued::default();
+    for entry in entries {
+        match entry {
+            CostEntry::Receipt {
+                quantity,
+                unit_cost,
+                ..
+            } => {
+                stock_value += match unit_cost {
+                    Some(unit_cost) => *quantity as u64 * unit_cost,
+                    None if quantity_on_hand > 0 => {
+                        stock_value * *quantity as u64 / quantity_on_hand as u64
+                    }
+                    None => 0,
+                };
+                quantity_on_hand += quantity;
+            }
+            CostEntry::Issue {
+                quantity,
+                unit_price,
+                ..
+            } => {
+                let taken = (*quantity).min(quantity_on_hand);
+                let cost = match quantity_on_hand {
+                    0 => 0,
+                    _ => stock_value * taken as u64 / quantity_on_hand as u64,
+                };
+                stock_value -= cost;
+                quantity_on_hand -= taken;
+                issued.add(*quantity, *unit_price, cost);
+            }
+        }
+    }
+    (stock_value, issued)
+}
+
+fn average(layers: &VecDeque<(usize, u64)>) -> u64 {
+    let quantity: usize = layers.iter().map(|(quantity, _)| quantity).sum();
+    match quantity {
+        0 => 0,
+        _ => layers.iter().map(|(q, cost)| *q as u64 * cost).sum::<u64>() / quantity as u64,
+    }
+}
@@ -1,11 +1,15 @@
-use crate::product::{ProductItem, ProductList, Quality::*};
+use crate::{
+    bitmap::ZoneBitmap,
+    product::{format_weight, Delivery, ItemStatus, Product, ProductItem, ProductList},
+};
 use chrono::NaiveDate;
 use log::{info, Level as LogLevel, LevelFilter, Metadata, Record, SetLoggerError};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet},
     error::Error,
     fmt::{self, Debug, Display, Formatter},
+    sync::atomic::{AtomicU64, Ordering},
 };
 use ErrorMessage::*;
 use InfoMessage::*;
@@ -18,9 +22,10 @@ pub enum ItemPart {
     ProductStart(ProductItem, usize),
     ProductPart(usize, usize),
     ProductEnd(usize),
+    ProductAbove(usize, usize),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
 pub enum PlacementStrategy {
     Contiguous,
     RoundRobin,
@@ -34,10 +39,28 @@ pub struct Zone {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "LevelData")]
 pub struct Level {
     pub number: usize,
     pub available_space: usize,
     pub zones: Vec<Zone>,
+    #[serde(default)]
+    pub load_rating: Option<u64>,
+    #[serde(skip)]
+    occupancy: ZoneBitmap,
+    #[serde(skip)]
+    revision: u64,
+    #[serde(skip)]
+    load: u64,
+}
+
+#[derive(Deserialize)]
+struct LevelData {
+    number: usize,
+    available_space: usize,
+    zones: Vec<Zone>,
+    #[serde(default)]
+    load_rating: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +68,8 @@ pub struct Shelf {
     pub number: usize,
     pub available_space: usize,
     pub levels: Vec<Level>,
+    #[serde(default)]
+    pub load_rating: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,14 +80,196 @@ pub struct Row {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "WarehouseData")]
 pub struct Warehouse {
     pub available_space: usize,
-    pub rows: Vec<Row>,
+    rows: Vec<Row>,
     pub strategy: PlacementStrategy,
+    #[serde(skip)]
+    pub(crate) locations: LocationIndex,
+}
+
+#[derive(Deserialize)]
+struct WarehouseData {
+    available_space: usize,
+    rows: Vec<Row>,
+    strategy: PlacementStrategy,
+}
+
+/// Placements of each product's items, keyed by the zone holding the item (the
+/// start zone for oversized items) and kept in placement order. A second set
+/// keeps the same placements in picking order, by expiry date, so picking a few
+/// units does not sort every unit of the product. Serial numbers map to the
+/// placement of their item.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LocationIndex {
+    placements: HashMap<u64, BTreeSet<(usize, usize, usize, usize)>>,
+    picking: HashMap<u64, BTreeSet<PickKey>>,
+    serials: HashMap<String, (usize, usize, usize, usize)>,
+}
+
+/// Items without an expiry date sort after every dated item.
+type PickKey = (bool, Option<NaiveDate>, (usize, usize, usize, usize));
+
+/// A run of free zones or slots that may carry over from one level to the next,
+/// as placement treats the whole warehouse as one sequence.
+#[derive(Default)]
+struct FreeRun {
+    start: Option<(usize, usize, usize, usize)>,
+    len: usize,
 }
 
 struct WarehouseLogger;
 
+/// Source of `Level` revisions, shared by all levels so that a level replaced by
+/// a new one never carries the old one's revision.
+static REVISIONS: AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+impl From<LevelData> for Level {
+    fn from(data: LevelData) -> Self {
+        let mut level = Level {
+            number: data.number,
+            available_space: data.available_space,
+            zones: data.zones,
+            load_rating: data.load_rating,
+            occupancy: ZoneBitmap::default(),
+            revision: next_revision(),
+            load: 0,
+        };
+        level.reindex();
+        level
+    }
+}
+
+impl From<WarehouseData> for Warehouse {
+    fn from(data: WarehouseData) -> Self {
+        Warehouse {
+            locations: LocationIndex::build(&data.rows),
+            available_space: data.available_space,
+            rows: data.rows,
+            strategy: data.strategy,
+        }
+    }
+}
+
+impl LocationIndex {
+    fn build(rows: &[Row]) -> Self {
+        let mut index = LocationIndex::default();
+        rows.iter().for_each(|row| index.add_row(row));
+        index
+    }
+
+    fn add_row(&mut self, row: &Row) {
+        for shelf in &row.shelves {
+            for level in &shelf.levels {
+                for zone in &level.zones {
+                    if let Some(WholeProduct(item)) | Some(ProductStart(item, _)) = &zone.item {
+                        let placement = (row.number, shelf.number, level.number, zone.number);
+                        self.insert(item.id, item.expiry_date, item.serial.as_deref(), placement);
+                    }
+                }
+            }
+        }
+    }
+
+    fn remove_row(&mut self, row_number: usize) {
+        self.placements.retain(|_, placements| {
+            placements.retain(|placement| placement.0 != row_number);
+            !placements.is_empty()
+        });
+        self.picking.retain(|_, keys| {
+            keys.retain(|(_, _, placement)| placement.0 != row_number);
+            !keys.is_empty()
+        });
+        self.serials.retain(|_, placement| placement.0 != row_number);
+    }
+
+    fn insert(
+        &mut self,
+        product_id: u64,
+        expiry: Option<NaiveDate>,
+        serial: Option<&str>,
+        placement: (usize, usize, usize, usize),
+    ) {
+        self.placements.entry(product_id).or_default().insert(placement);
+        self.picking.entry(product_id).or_default().insert((expiry.is_none(), expiry, placement));
+        if let Some(serial) = serial {
+            self.serials.insert(serial.to_string(), placement);
+        }
+    }
+
+    fn remove(
+        &mut self,
+        product_id: u64,
+        expiry: Option<NaiveDate>,
+        serial: Option<&str>,
+        placement: (usize, usize, usize, usize),
+    ) {
+        if let Some(serial) = serial {
+            self.serials.remove(serial);
+        }
+        if let Some(placements) = self.placements.get_mut(&product_id) {
+            placements.remove(&placement);
+            if placements.is_empty() {
+                self.placements.remove(&product_id);
+            }
+        }
+        if let Some(keys) = self.picking.get_mut(&product_id) {
+            keys.remove(&(expiry.is_none(), expiry, placement));
+            if keys.is_empty() {
+                self.picking.remove(&product_id);
+            }
+        }
+    }
+
+    fn contains(&self, product_id: u64) -> bool {
+        self.placements.contains_key(&product_id)
+    }
+
+    fn serial(&self, serial: &str) -> Option<(usize, usize, usize, usize)> {
+        self.serials.get(serial).copied()
+    }
+
+    fn get(
+        &self,
+        product_id: u64,
+    ) -> impl DoubleEndedIterator<Item = (usize, usize, usize, usize)> + '_ {
+        self.placements.get(&product_id).into_iter().flatten().copied()
+    }
+
+    /// Placements in the order units are picked: earliest expiry first, or the
+    /// most recently placed first when no unit has an expiry date.
+    fn picking_order(&self, product_id: u64) -> Box<dyn Iterator<Item = (usize, usize, usize, usize)> + '_> {
+        let keys = self.picking.get(&product_id).into_iter().flatten();
+        let dated = self.picking.get(&product_id).and_then(|keys| keys.first()).is_some_and(|key| !key.0);
+        match dated {
+            true => Box::new(keys.map(|(_, _, placement)| *placement)),
+            false => Box::new(keys.rev().map(|(_, _, placement)| *placement)),
+        }
+    }
+}
+
+impl FreeRun {
+    fn extend(&mut self, start: (usize, usize, usize, usize), len: usize) {
+        if len == 0 {
+            return;
+        }
+        if self.len == 0 {
+            self.start = Some(start);
+        }
+        self.len += len;
+    }
+
+    fn restart(&mut self, start: (usize, usize, usize, usize), len: usize) {
+        self.start = Some(start).filter(|_| len > 0);
+        self.len = len;
+    }
+}
+
 impl log::Log for WarehouseLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
         metadata.level() <= LogLevel::Info
@@ -111,6 +318,10 @@ pub enum ErrorMessage {
     ProductNotListed,
     EndOfRows,
     EndOfWarehouse,
+    LoadLimitExceeded,
+    DuplicateSerial,
+    SerialCountMismatch,
+    SerialNotFound,
 }
 
 impl Display for ErrorMessage {
@@ -138,6 +349,10 @@ impl ErrorMessage {
             ProductNotListed => "Product not listed",
             EndOfRows => "End of last row reached",
             EndOfWarehouse => "End of warehouse reached",
+            LoadLimitExceeded => "Load rating exceeded",
+            DuplicateSerial => "Serial number already in use",
+            SerialCountMismatch => "Number of serials does not match quantity",
+            SerialNotFound => "No item found with serial",
         }
     }
 
@@ -299,6 +514,14 @@ impl Zone {
     pub fn is_empty(&self) -> bool {
         self.item.is_none()
     }
+
+    /// Weight of the item held here, counted on the zone where it starts.
+    fn weight(&self) -> u64 {
+        match &self.item {
+            Some(WholeProduct(item)) | Some(ProductStart(item, _)) => item.weight.unwrap_or(0),
+            _ => 0,
+        }
+    }
 }
 
 impl Level {
@@ -307,14 +530,63 @@ impl Level {
             number,
             zones: Vec::new(),
             available_space: 0,
+            load_rating: None,
+            occupancy: ZoneBitmap::default(),
+            revision: next_revision(),
+            load: 0,
         }
     }
 
     pub fn add_zone(&mut self, zone: Zone) {
+        self.push_zone(zone);
+        self.occupancy.summarize();
+    }
+
+    fn push_zone(&mut self, zone: Zone) {
+        self.touch();
+        self.occupancy.push(!zone.is_empty());
+        self.load += zone.weight();
         self.zones.push(zone);
         self.available_space += 1;
     }
 
+    /// Rebuilds the occupancy bitmap and the load from the zones.
+    pub fn reindex(&mut self) {
+        self.touch();
+        self.occupancy = ZoneBitmap::from_occupancy(self.zones.iter().map(|zone| !zone.is_empty()));
+        self.load = self.zones.iter().map(Zone::weight).sum();
+    }
+
+    /// Changes whenever the zones of the level may have changed.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn touch(&mut self) {
+        self.revision = next_revision();
+    }
+
+    pub fn occupancy(&self) -> &ZoneBitmap {
+        &self.occupancy
+    }
+
+    fn position(&self, zone_number: usize) -> Option<usize> {
+        match zone_number.checked_sub(1).and_then(|index| self.zones.get(index)) {
+            Some(zone) if zone.number == zone_number => Some(zone_number - 1),
+            _ => self.zones.iter().position(|zone| zone.number == zone_number),
+        }
+    }
+
+    fn refresh(&mut self, first_zone: usize, last_zone: usize) {
+        if let Some(start) = self.position(first_zone) {
+            let end = (start + last_zone - first_zone).min(self.zones.len() - 1);
+            for index in start..=end {
+                self.occupancy.set(index, !self.zones[index].is_empty());
+            }
+            self.occupancy.summarize();
+        }
+    }
+
     pub fn remove_zone(
         &mut self,
         row_number: usize,
@@ -328,6 +600,7 @@ impl Level {
         {
             self.zones.remove(zone);
             self.available_space -= 1;
+            self.reindex();
             Ok(())
         } else {
             let message = ZoneNotFound.at((row_number, shelf_number, self.number, zone_number));
@@ -336,72 +609,57 @@ impl Level {
     }
 
     pub fn zone(&self, zone_number: usize) -> Option<&Zone> {
-        self.zones.iter().find(|zone| zone.number == zone_number)
+        self.position(zone_number).map(|index| &self.zones[index])
     }
 
-    pub fn zone_mut(&mut self, zone_number: usize) -> Option<&mut Zone> {
-        self.zones
-            .iter_mut()
-            .find(|zone| zone.number == zone_number)
+    fn zone_mut(&mut self, zone_number: usize) -> Option<&mut Zone> {
+        self.touch();
+        self.position(zone_number).map(|index| &mut self.zones[index])
     }
 
-    pub fn flat_map(&self) -> String {
-        self.zones
-            .iter()
-            .map(|zone| if zone.is_empty() { "0" } else { "1" })
-            .collect()
+    pub fn is_full(&self) -> bool {
+        self.available_space == 0
     }
 
-    pub fn oversized_flat_map(&self, zones_required: usize) -> String {
-        let binding = self.flat_map();
-        let map = binding.as_str();
-        let mut oversized_map = String::new();
-
-        let mut index = 0;
-        while index + zones_required < map.len() {
-            if map[index..index + zones_required] == "0".repeat(zones_required) {
-                oversized_map.push('1');
-                oversized_map.push_str("0".repeat(zones_required - 1).as_str());
-                index += zones_required;
-            } else {
-                oversized_map.push(map.chars().nth(index).unwrap());
-                index += 1;
-            }
-        }
-        oversized_map
+    pub fn is_empty(&self) -> bool {
+        self.occupancy.is_all_free()
     }
 
-    pub fn flat_map_position_to_zone(&self, position: usize) -> Option<usize> {
-        if position < self.zones.len() {
-            return Some(position)
-        } 
-        None
+    pub fn set_load_rating(&mut self, load_rating: Option<u64>) {
+        self.load_rating = load_rating;
     }
 
-    pub fn oversized_flat_map_position_to_zone(
-        &self,
-        position: usize,
-        zones_required: usize,
-    ) -> Option<usize> {
-        if position < self.zones.len() - zones_required {
-            return Some(position)
-        }
-        None
+    pub fn load(&self) -> u64 {
+        self.load
     }
 
-    pub fn is_full(&self) -> bool {
-        self.available_space == 0
+    pub fn occupied_zones(&self) -> usize {
+        self.occupancy.occupied()
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.flat_map().chars().all(|c| c == '0')
+    fn check_load(
+        &self,
+        row_number: usize,
+        shelf_number: usize,
+        zone_number: usize,
+        weight: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let (Some(load_rating), Some(weight)) = (self.load_rating, weight) {
+            if self.load() + weight > load_rating {
+                let message =
+                    LoadLimitExceeded.at((row_number, shelf_number, self.number, zone_number));
+                return Err(WarehouseError::addition(message));
+            }
+        }
+        Ok(())
     }
 
     pub fn initialize_zones(&mut self, zone_count: usize) {
         for i in 1..=zone_count {
             let zone = Zone::new(i, None);
-            self.add_zone(zone);
+            self.push_zone(zone);
         }
+        self.occupancy.summarize();
     }
 
     pub fn add_item(
@@ -412,10 +670,14 @@ impl Level {
         item: ProductItem,
     ) -> Result<(), Box<dyn Error>> {
         let level_number = self.number;
+        let weight = item.weight;
+        self.check_load(row_number, shelf_number, zone_number, weight)?;
         if let Some(zone) = self.zone_mut(zone_number) {
             match zone.add(row_number, shelf_number, level_number, item) {
                 Ok(_) => {
                     self.available_space -= 1;
+                    self.load += weight.unwrap_or(0);
+                    self.refresh(zone_number, zone_number);
                     Ok(())
                 }
                 Err(e) => Err(e),
@@ -434,9 +696,12 @@ impl Level {
         zone_number: usize,
     ) -> Result<(), Box<dyn Error>> {
         if let Some(zone) = self.zone_mut(zone_number) {
+            let weight = zone.weight();
             match zone.remove(row_number, shelf_number, level_number, zone_number) {
                 Ok(_) => {
                     self.available_space += 1;
+                    self.load -= weight;
+                    self.refresh(zone_number, zone_number);
                     Ok(())
                 }
                 Err(e) => Err(e),
@@ -454,23 +719,27 @@ impl Level {
         zone_number: usize,
         zones_required: usize,
     ) -> Result<(), Box<dyn Error>> {
-        let map = self.flat_map();
+        let zone_count = self.occupancy.len();
         let last_zone = zone_number + zones_required - 1;
-        if zone_number > map.len() {
+        if zone_number == 0 || zone_number > zone_count {
             let message = ZoneNotFound.at((row_number, shelf_number, self.number, zone_number));
             return Err(WarehouseError::addition(message));
-        } else if last_zone > map.len() {
+        } else if last_zone > zone_count {
             let message =
                 InsufficientSpace.at((row_number, shelf_number, self.number, zone_number));
             return Err(WarehouseError::addition(message));
         }
-        for i in zone_number..=last_zone {
-            if map.chars().nth(i).unwrap() == '1' {
-                let message = ZoneOccupied.at((row_number, shelf_number, self.number, i));
-                return Err(WarehouseError::addition(message));
+        match self.occupancy.next_occupied(zone_number - 1) {
+            Some(index) if index < last_zone => {
+                let message = ZoneOccupied.at((row_number, shelf_number, self.number, index + 1));
+                Err(WarehouseError::addition(message))
             }
+            _ => Ok(()),
         }
-        Ok(())
+    }
+
+    pub fn is_range_free(&self, zone_number: usize, zones_required: usize) -> bool {
+        zone_number > 0 && self.occupancy.is_range_free(zone_number - 1, zones_required)
     }
 
     pub fn add_oversized_item(
@@ -482,18 +751,24 @@ impl Level {
     ) -> Result<(), Box<dyn Error>> {
         let zones_required = item.zones_required;
         self.check_if_fits(row_number, shelf_number, zone_number, zones_required)?;
+        self.check_load(row_number, shelf_number, zone_number, item.weight)?;
         if let Some(zone) = self.zone_mut(zone_number) {
             let last_zone = zone_number + item.zones_required - 1;
+            let weight = item.weight.unwrap_or(0);
             zone.item = Some(ProductStart(item, last_zone));
+            self.load += weight;
             for i in zone_number + 1..last_zone {
                 if let Some(z) = self.zone_mut(i) {
                     z.item = Some(ProductPart(zone_number, last_zone));
                 }
             }
-            if let Some(z) = self.zone_mut(last_zone) {
-                z.item = Some(ProductEnd(zone_number));
+            if last_zone > zone_number {
+                if let Some(z) = self.zone_mut(last_zone) {
+                    z.item = Some(ProductEnd(zone_number));
+                }
             }
             self.available_space -= zones_required;
+            self.refresh(zone_number, last_zone);
             Ok(())
         } else {
             let message = ZoneNotFound.at((row_number, shelf_number, self.number, zone_number));
@@ -501,6 +776,47 @@ impl Level {
         }
     }
 
+    pub fn add_vertical_part(
+        &mut self,
+        row_number: usize,
+        shelf_number: usize,
+        zone_number: usize,
+        zones_required: usize,
+        base_level: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_if_fits(row_number, shelf_number, zone_number, zones_required)?;
+        for i in zone_number..zone_number + zones_required {
+            if let Some(zone) = self.zone_mut(i) {
+                zone.item = Some(ProductAbove(base_level, zone_number));
+            }
+        }
+        self.available_space -= zones_required;
+        self.refresh(zone_number, zone_number + zones_required - 1);
+        Ok(())
+    }
+
+    pub fn remove_vertical_part(
+        &mut self,
+        row_number: usize,
+        shelf_number: usize,
+        zone_number: usize,
+        zones_required: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        for i in zone_number..zone_number + zones_required {
+            match self.zone_mut(i) {
+                Some(zone) if matches!(zone.item, Some(ProductAbove(_, _))) => zone.item = None,
+                _ => {
+                    let message = NoProductFound.at((row_number, shelf_number, self.number, i));
+                    self.refresh(zone_number, i);
+                    return Err(WarehouseError::remotion(message));
+                }
+            }
+        }
+        self.available_space += zones_required;
+        self.refresh(zone_number, zone_number + zones_required - 1);
+        Ok(())
+    }
+
     fn get_oversized_range(&self, zone_number: usize) -> Option<(usize, usize)> {
         if let Some(zone) = self.zone(zone_number) {
             match &zone.item {
@@ -520,12 +836,14 @@ impl Level {
     ) -> Result<(), Box<dyn Error>> {
         let range = self.get_oversized_range(zone_number);
         if let Some((start, end)) = range {
+            self.load -= self.zone(start).map_or(0, Zone::weight);
             for i in start..=end {
                 if let Some(zone) = self.zone_mut(i) {
                     zone.item = None;
                 }
             }
             self.available_space += end - start + 1;
+            self.refresh(start, end);
             Ok(())
         } else {
             let message = NoProductFound.at((row_number, shelf_number, self.number, zone_number));
@@ -534,19 +852,11 @@ impl Level {
     }
 
     fn find_vacant_zone(&self) -> Option<usize> {
-        self.zones.iter().position(|zone| zone.is_empty())
+        self.occupancy.next_free(0)
     }
 
-    fn find_oversized_vacant_zone(&self, zones_required: usize) -> Option<usize> {
-        let map = self.flat_map();
-        let mut index = 0;
-        while index + zones_required <= map.len() {
-            if map[index..index + zones_required] == "0".repeat(zones_required) {
-                return Some(index);
-            }
-            index += 1;
-        }
-        None
+    fn is_slot_free(&self, slot: usize, zones_required: usize) -> bool {
+        self.occupancy.is_range_free(slot * zones_required, zones_required)
     }
 
     pub fn item(&self, zone_number: usize) -> Option<&ProductItem> {
@@ -565,19 +875,19 @@ impl Level {
                         return None;
                     }
                 }
-                None => return None,
+                Some(ProductAbove(_, _)) | None => return None,
             }
         }
         None
     }
 
-    pub fn item_mut(&mut self, zone_number: usize) -> Option<&mut ProductItem> {
+    fn item_mut(&mut self, zone_number: usize) -> Option<&mut ProductItem> {
         let item_zone_number = match &self.zone(zone_number) {
             Some(zone) => match &zone.item {
                 Some(WholeProduct(_)) => zone_number,
                 Some(ProductStart(_, _)) => zone_number,
                 Some(ProductPart(start, _)) | Some(ProductEnd(start)) => *start,
-                None => return None,
+                Some(ProductAbove(_, _)) | None => return None,
             },
             None => return None,
         };
@@ -670,20 +980,15 @@ impl Level {
     }
 
     pub fn items(&self) -> Vec<ProductItem> {
-        self.zones
-            .iter()
-            .filter_map(|zone| {
-                if let Some(item) = &zone.item {
-                    match item {
-                        WholeProduct(item) => Some(item.clone()),
-                        ProductStart(item, _) => Some(item.clone()),
-                        _ => None,
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect()
+        self.stored_items().cloned().collect()
+    }
+
+    /// Items starting on this level, borrowed in zone order.
+    fn stored_items(&self) -> impl Iterator<Item = &ProductItem> {
+        self.zones.iter().filter_map(|zone| match &zone.item {
+            Some(WholeProduct(item)) | Some(ProductStart(item, _)) => Some(item),
+            _ => None,
+        })
     }
 }
 
@@ -693,6 +998,7 @@ impl Shelf {
             number,
             available_space: 0,
             levels: Vec::new(),
+            load_rating: None,
         }
     }
 
@@ -727,79 +1033,23 @@ impl Shelf {
         None
     }
 
-    pub fn zone_mut(&mut self, level_number: usize, zone_number: usize) -> Option<&mut Zone> {
-        if let Some(level) = self.level_mut(level_number) {
-            return level.zone_mut(zone_number);
-        }
-        None
-    }
-
     pub fn level(&self, level_number: usize) -> Option<&Level> {
         self.levels.iter().find(|lvl| lvl.number == level_number)
     }
 
-    pub fn level_mut(&mut self, level_number: usize) -> Option<&mut Level> {
+    fn level_mut(&mut self, level_number: usize) -> Option<&mut Level> {
         self.levels
             .iter_mut()
             .find(|lvl| lvl.number == level_number)
     }
 
-    pub fn flat_map(&self) -> String {
+    fn base_levels(&self, levels_required: usize) -> impl Iterator<Item = (usize, &Level)> {
+        let level_count = self.levels.len();
         self.levels
             .iter()
-            .map(|lvl| lvl.flat_map())
-            .collect::<Vec<String>>()
-            .join("")
-    }
-
-    pub fn oversized_flat_map(&self, zones_required: usize) -> String {
-        self.levels
-            .iter()
-            .map(|lvl| lvl.oversized_flat_map(zones_required))
-            .collect::<Vec<String>>()
-            .join("")
-    }
-
-    pub fn flat_map_position_to_zone(&self, position: usize) -> Option<(usize, usize)> {
-        let mut cumulative_capacity = 0;
-        for (level_index, level) in self.levels.iter().enumerate() {
-            let level_capacity = level.check_capacity();
-            if position < cumulative_capacity + level_capacity {
-                return level
-                    .flat_map_position_to_zone(position - cumulative_capacity)
-                    .map(|zone_index| (level_index + 1, zone_index));
-            }
-            cumulative_capacity += level_capacity;
-        }
-        None
-    }
-
-    pub fn oversized_flat_map_position_to_zone(
-        &self,
-        position: usize,
-        zones_required: usize,
-    ) -> Option<(usize, usize)> {
-        let mut cumulative_capacity = 0;
-        for (level_index, level) in self.levels.iter().enumerate() {
-            let level_capacity = level.check_capacity();
-            println!("{} {}", position, cumulative_capacity);
-            if position < cumulative_capacity + level_capacity - zones_required {
-                println!("{} {}", position, cumulative_capacity);
-                return level
-                    .oversized_flat_map_position_to_zone(
-                        position - cumulative_capacity,
-                        zones_required,
-                    )
-                    .map(|zone_index| (level_index + 1, zone_index));
-            } else if position < cumulative_capacity + level_capacity {
-                return level.oversized_flat_map_position_to_zone(
-                    1,
-                    zones_required,
-                ).map(|zone_index| (level_index + 1, zone_index));
-            }
-            cumulative_capacity += level_capacity;
-        }
-        None
+            .enumerate()
+            .step_by(levels_required)
+            .filter(move |(level_index, _)| level_index + levels_required <= level_count)
     }
 
     pub fn find_vacant_zone(&self) -> Option<(usize, usize)> {
@@ -811,10 +1061,21 @@ impl Shelf {
         None
     }
 
-    pub fn find_oversized_vacant_zone(&self, zones_required: usize) -> Option<(usize, usize)> {
-        for (level_index, level) in self.levels.iter().enumerate() {
-            if let Some(zone_index) = level.find_oversized_vacant_zone(zones_required) {
-                return Some((level_index + 1, zone_index + 1));
+    /// First free run of `zones_required` zones on a base level that is also
+    /// free on every level of the stack above it.
+    pub fn find_oversized_vacant_zone(
+        &self,
+        zones_required: usize,
+        levels_required: usize,
+    ) -> Option<(usize, usize)> {
+        for (level_index, level) in self.base_levels(levels_required) {
+            let upper = &self.levels[level_index + 1..level_index + levels_required];
+            let mut from = 0;
+            while let Some(start) = level.occupancy().find_free_run(from, zones_required) {
+                if upper.iter().all(|lvl| lvl.is_range_free(start + 1, zones_required)) {
+                    return Some((level_index + 1, start + 1));
+                }
+                from = start + 1;
             }
         }
         None
@@ -830,10 +1091,9 @@ impl Shelf {
         self.levels.iter().map(|lvl| lvl.check_capacity()).sum()
     }
 
-    pub fn check_oversized_capacity(&self, zones_required: usize) -> usize {
-        self.levels
-            .iter()
-            .map(|lvl| lvl.check_oversized_capacity(zones_required))
+    pub fn check_oversized_capacity(&self, zones_required: usize, levels_required: usize) -> usize {
+        self.base_levels(levels_required)
+            .map(|(_, lvl)| lvl.check_oversized_capacity(zones_required))
             .sum()
     }
 
@@ -845,6 +1105,35 @@ impl Shelf {
         self.levels.iter().all(|lvl| lvl.is_empty())
     }
 
+    pub fn set_load_rating(&mut self, load_rating: Option<u64>) {
+        self.load_rating = load_rating;
+    }
+
+    pub fn load(&self) -> u64 {
+        self.levels.iter().map(|lvl| lvl.load()).sum()
+    }
+
+    pub fn occupied_zones(&self) -> usize {
+        self.levels.iter().map(|lvl| lvl.occupied_zones()).sum()
+    }
+
+    fn check_load(
+        &self,
+        row_number: usize,
+        level_number: usize,
+        zone_number: usize,
+        weight: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let (Some(load_rating), Some(weight)) = (self.load_rating, weight) {
+            if self.load() + weight > load_rating {
+                let message =
+                    LoadLimitExceeded.at((row_number, self.number, level_number, zone_number));
+                return Err(WarehouseError::addition(message));
+            }
+        }
+        Ok(())
+    }
+
     pub fn initialize_columns(&mut self, level_count: usize, zone_per_level: usize) {
         for i in 1..=level_count {
             let mut column = Level::new(i);
@@ -861,6 +1150,7 @@ impl Shelf {
         item: ProductItem,
     ) -> Result<(), Box<dyn Error>> {
         let shelf_number = self.number;
+        self.check_load(row_number, level_number, zone_number, item.weight)?;
         if let Some(level) = self.level_mut(level_number) {
             match level.add_item(row_number, shelf_number, zone_number, item) {
                 Ok(_) => {
@@ -905,61 +1195,136 @@ impl Shelf {
     ) -> Result<(), Box<dyn Error>> {
         let shelf_number = self.number;
         let zones_required = item.zones_required;
-        if let Some(level) = self.level_mut(level_number) {
-            match level.add_oversized_item(row_number, shelf_number, zone_number, item) {
-                Ok(_) => {
-                    self.available_space -= zones_required;
-                    Ok(())
+        let levels_required = item.levels_required;
+        let zones_occupied = item.zones_occupied();
+        self.check_load(row_number, level_number, zone_number, item.weight)?;
+        for upper_level in level_number + 1..level_number + levels_required {
+            match self.level(upper_level) {
+                Some(level) => {
+                    level.check_if_fits(row_number, shelf_number, zone_number, zones_required)?
+                }
+                None => {
+                    let message = LevelNotFound.at((row_number, shelf_number, upper_level));
+                    return Err(WarehouseError::addition(message));
                 }
-                Err(e) => Err(e),
             }
+        }
+        if let Some(level) = self.level_mut(level_number) {
+            level.add_oversized_item(row_number, shelf_number, zone_number, item)?;
         } else {
             let message = LevelNotFound.at((row_number, self.number, level_number));
-            Err(WarehouseError::addition(message))
+            return Err(WarehouseError::addition(message));
+        }
+        for upper_level in level_number + 1..level_number + levels_required {
+            let added = match self.level_mut(upper_level) {
+                Some(level) => level.add_vertical_part(
+                    row_number,
+                    shelf_number,
+                    zone_number,
+                    zones_required,
+                    level_number,
+                ),
+                None => Ok(()),
+            };
+            if let Err(e) = added {
+                self.undo_oversized_item(
+                    row_number,
+                    level_number,
+                    upper_level,
+                    zone_number,
+                    zones_required,
+                );
+                return Err(e);
+            }
         }
+        self.available_space -= zones_occupied;
+        Ok(())
     }
 
-    pub fn remove_oversized_item(
+    /// Clears a partly placed oversized item: the base level and the upper levels
+    /// below `failed_level`.
+    fn undo_oversized_item(
         &mut self,
         row_number: usize,
         level_number: usize,
+        failed_level: usize,
         zone_number: usize,
-    ) -> Result<(), Box<dyn Error>> {
+        zones_required: usize,
+    ) {
         let shelf_number = self.number;
-        let zones_required = match self.item(level_number, zone_number) {
-            Some(item) => item.zones_required,
-            None => {
-                let message =
-                    NoProductFound.at((row_number, shelf_number, level_number, zone_number));
-                return Err(WarehouseError::remotion(message));
+        for upper_level in level_number + 1..failed_level {
+            if let Some(level) = self.level_mut(upper_level) {
+                let _ = level.remove_vertical_part(
+                    row_number,
+                    shelf_number,
+                    zone_number,
+                    zones_required,
+                );
             }
-        };
+        }
         if let Some(level) = self.level_mut(level_number) {
-            match level.remove_oversized_item(row_number, shelf_number, zone_number) {
-                Ok(_) => {
-                    self.available_space += zones_required;
-                    Ok(())
+            let _ = level.remove_oversized_item(row_number, shelf_number, zone_number);
+        }
+    }
+
+    pub fn remove_oversized_item(
+        &mut self,
+        row_number: usize,
+        level_number: usize,
+        zone_number: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let shelf_number = self.number;
+        let (base_level, start_zone, zones_required, levels_required) =
+            match self.item(level_number, zone_number) {
+                Some(item) => (
+                    item.placement.2,
+                    item.placement.3,
+                    item.zones_required,
+                    item.levels_required,
+                ),
+                None => {
+                    let message =
+                        NoProductFound.at((row_number, shelf_number, level_number, zone_number));
+                    return Err(WarehouseError::remotion(message));
                 }
-                Err(e) => Err(e),
-            }
+            };
+        if let Some(level) = self.level_mut(base_level) {
+            level.remove_oversized_item(row_number, shelf_number, start_zone)?;
         } else {
-            let message = LevelNotFound.at((row_number, shelf_number, level_number));
-            Err(WarehouseError::remotion(message))
+            let message = LevelNotFound.at((row_number, shelf_number, base_level));
+            return Err(WarehouseError::remotion(message));
         }
+        for upper_level in base_level + 1..base_level + levels_required {
+            if let Some(level) = self.level_mut(upper_level) {
+                level.remove_vertical_part(row_number, shelf_number, start_zone, zones_required)?;
+            }
+        }
+        self.available_space += zones_required * levels_required;
+        Ok(())
     }
 
     pub fn item(&self, level_number: usize, zone_number: usize) -> Option<&ProductItem> {
+        if let Some(ProductAbove(base_level, start_zone)) =
+            self.zone(level_number, zone_number).and_then(|zone| zone.item.as_ref())
+        {
+            return self.level(*base_level)?.item(*start_zone);
+        }
         if let Some(level) = self.level(level_number) {
             return level.item(zone_number);
         }
         None
     }
 
-    pub fn item_mut(
+    fn item_mut(
         &mut self,
         level_number: usize,
         zone_number: usize,
     ) -> Option<&mut ProductItem> {
+        if let Some(ProductAbove(base_level, start_zone)) =
+            self.zone(level_number, zone_number).and_then(|zone| zone.item.clone())
+        {
+            return self.level_mut(base_level)?.item_mut(start_zone);
+        }
         if let Some(level) = self.level_mut(level_number) {
             return level.item_mut(zone_number);
         }
@@ -1051,83 +1416,14 @@ impl Row {
         None
     }
 
-    pub fn zone_mut(
-        &mut self,
-        shelf_number: usize,
-        level_number: usize,
-        zone_number: usize,
-    ) -> Option<&mut Zone> {
-        if let Some(shelf) = self.shelf_mut(shelf_number) {
-            return shelf.zone_mut(level_number, zone_number);
-        }
-        None
-    }
-
     pub fn shelf(&self, shelf_number: usize) -> Option<&Shelf> {
         self.shelves.iter().find(|sh| sh.number == shelf_number)
     }
 
-    pub fn shelf_mut(&mut self, shelf_number: usize) -> Option<&mut Shelf> {
+    fn shelf_mut(&mut self, shelf_number: usize) -> Option<&mut Shelf> {
         self.shelves.iter_mut().find(|sh| sh.number == shelf_number)
     }
 
-    pub fn flat_map(&self) -> String {
-        self.shelves
-            .iter()
-            .map(|sh| sh.flat_map())
-            .collect::<Vec<String>>()
-            .join("")
-    }
-
-    pub fn oversized_flat_map(&self, zones_required: usize) -> String {
-        self.shelves
-            .iter()
-            .map(|sh| sh.oversized_flat_map(zones_required))
-            .collect::<Vec<String>>()
-            .join("")
-    }
-
-    pub fn flat_map_position_to_zone(&self, position: usize) -> Option<(usize, usize, usize)> {
-        let mut cumulative_capacity = 0;
-        for (shelf_index, shelf) in self.shelves.iter().enumerate() {
-            let shelf_capacity = shelf.check_capacity();
-            if position < cumulative_capacity + shelf_capacity {
-                return shelf
-                    .flat_map_position_to_zone(position - cumulative_capacity)
-                    .map(|(level_index, zone_index)| (shelf_index + 1, level_index, zone_index));
-            }
-            cumulative_capacity += shelf_capacity;
-        }
-        None
-    }
-
-    pub fn oversized_flat_map_position_to_zone(
-        &self,
-        position: usize,
-        zones_required: usize,
-    ) -> Option<(usize, usize, usize)> {
-        let mut cumulative_capacity = 0;
-        for (shelf_index, shelf) in self.shelves.iter().enumerate() {
-            let shelf_capacity = shelf.check_capacity();
-            if position < cumulative_capacity + shelf_capacity - zones_required {
-                println!("{} {}", position, cumulative_capacity);
-                return shelf
-                    .oversized_flat_map_position_to_zone(
-                        position - cumulative_capacity,
-                        zones_required,
-                    )
-                    .map(|(level_index, zone_index)| (shelf_index + 1, level_index, zone_index));
-            } else if position < cumulative_capacity + shelf_capacity {
-                return shelf.oversized_flat_map_position_to_zone(
-                    1,
-                    zones_required,
-                ).map(|(level_index, zone_index)| (shelf_index + 1, level_index, zone_index));
-            }
-            cumulative_capacity += shelf_capacity;
-        }
-        None
-    }
-
     pub fn contains_product(&self, product_id: u64) -> bool {
         self.shelves
             .iter()
@@ -1138,10 +1434,10 @@ impl Row {
         self.shelves.iter().map(|sh| sh.check_capacity()).sum()
     }
 
-    pub fn check_oversized_capacity(&self, zones_required: usize) -> usize {
+    pub fn check_oversized_capacity(&self, zones_required: usize, levels_required: usize) -> usize {
         self.shelves
             .iter()
-            .map(|sh| sh.check_oversized_capacity(zones_required))
+            .map(|sh| sh.check_oversized_capacity(zones_required, levels_required))
             .sum()
     }
 
@@ -1153,6 +1449,14 @@ impl Row {
         self.shelves.iter().all(|sh| sh.is_empty())
     }
 
+    pub fn load(&self) -> u64 {
+        self.shelves.iter().map(|sh| sh.load()).sum()
+    }
+
+    pub fn occupied_zones(&self) -> usize {
+        self.shelves.iter().map(|sh| sh.occupied_zones()).sum()
+    }
+
     pub fn initialize_shelves(
         &mut self,
         shelf_count: usize,
@@ -1196,11 +1500,11 @@ impl Row {
         item: ProductItem,
     ) -> Result<(), Box<dyn Error>> {
         let row_number = self.number;
-        let zones_required = item.zones_required;
+        let zones_occupied = item.zones_occupied();
         if let Some(shelf) = self.shelf_mut(shelf_number) {
             match shelf.add_oversized_item(row_number, level_number, zone_number, item) {
                 Ok(_) => {
-                    self.available_space -= zones_required;
+                    self.available_space -= zones_occupied;
                     Ok(())
                 }
                 Err(e) => Err(e),
@@ -1239,8 +1543,8 @@ impl Row {
         zone_number: usize,
     ) -> Result<(), Box<dyn Error>> {
         let row_number = self.number;
-        let zones_required = match self.item(shelf_number, level_number, zone_number) {
-            Some(item) => item.zones_required,
+        let zones_occupied = match self.item(shelf_number, level_number, zone_number) {
+            Some(item) => item.zones_occupied(),
             None => {
                 let message =
                     NoProductFound.at((row_number, shelf_number, level_number, zone_number));
@@ -1250,7 +1554,7 @@ impl Row {
         if let Some(shelf) = self.shelf_mut(shelf_number) {
             match shelf.remove_oversized_item(row_number, level_number, zone_number) {
                 Ok(_) => {
-                    self.available_space += zones_required;
+                    self.available_space += zones_occupied;
                     Ok(())
                 }
                 Err(e) => Err(e),
@@ -1261,13 +1565,15 @@ impl Row {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_qty(
         &mut self,
         id: u64,
         list: &mut ProductList,
         qty: &mut usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
         start: (usize, usize, usize),
+        placed: &mut Vec<(usize, usize, usize, usize)>,
     ) -> Result<(), Box<dyn Error>> {
         let product = match list.product(id) {
             Some(product) => product,
@@ -1291,13 +1597,13 @@ impl Row {
         }
         while *qty > 0 {
             let placement = (row, shelf, level, zone);
-            let item = ProductItem::new(id, list, placement, expiry_date)?;
-            match self.add_item(shelf, level, zone, item) {
+            match delivery.place(id, list, placement, |item| self.add_item(shelf, level, zone, item)) {
                 Ok(_) => {
                     info!(
                         "{}",
                         Added(format!("{} at {:?}", id, (row, shelf, level, zone)))
                     );
+                    placed.push(placement);
                     *qty -= 1;
                     zone += 1;
                     if zone > self.shelves[shelf - 1].levels[level - 1].zones.len() {
@@ -1318,14 +1624,17 @@ impl Row {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_oversized_qty(
         &mut self,
         id: u64,
         list: &mut ProductList,
         qty: &mut usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
         zones_required: usize,
+        levels_required: usize,
         start: (usize, usize, usize),
+        placed: &mut Vec<(usize, usize, usize, usize)>,
     ) -> Result<(), Box<dyn Error>> {
         let product = match list.product(id) {
             Some(product) => product,
@@ -1349,20 +1658,24 @@ impl Row {
         }
         while *qty > 0 {
             let placement = (row, shelf, level, zone);
-            let item = ProductItem::new(id, list, placement, expiry_date)?;
-            match self.add_oversized_item(shelf, level, zone, item) {
+            match delivery.place(id, list, placement, |item| {
+                self.add_oversized_item(shelf, level, zone, item)
+            }) {
                 Ok(_) => {
                     info!(
                         "{}",
                         Added(format!("{} at {:?}", id, (row, shelf, level, zone)))
                     );
+                    placed.push(placement);
                     *qty -= 1;
                     zone += zones_required;
-                    if zone > self.shelves[shelf - 1].levels[level - 1].zones.len() - zones_required
+                    if zone + zones_required - 1 > self.shelves[shelf - 1].levels[level - 1].zones.len()
                     {
                         zone = 1;
-                        level += 1;
-                        if level > max_level.unwrap_or(self.shelves[shelf - 1].levels.len()) {
+                        level += levels_required;
+                        let levels = self.shelves[shelf - 1].levels.len();
+                        if level > max_level.unwrap_or(levels) || level + levels_required - 1 > levels
+                        {
                             level = 1;
                             shelf += 1;
                             if shelf > self.shelves.len() {
@@ -1389,7 +1702,7 @@ impl Row {
         None
     }
 
-    pub fn item_mut(
+    fn item_mut(
         &mut self,
         shelf_number: usize,
         level_number: usize,
@@ -1443,11 +1756,18 @@ impl Warehouse {
             available_space: 0,
             rows: Vec::new(),
             strategy: Contiguous,
+            locations: LocationIndex::default(),
         }
     }
 
+    pub fn with_strategy(mut self, strategy: PlacementStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
     pub fn add_row(&mut self, row: Row) {
         self.available_space += row.available_space;
+        self.locations.add_row(&row);
         self.rows.push(row);
     }
 
@@ -1456,6 +1776,7 @@ impl Warehouse {
             let row = &self.rows[row_index];
             self.available_space -= row.available_space;
             self.rows.remove(row_index);
+            self.locations.remove_row(row_number);
             Ok(())
         } else {
             let message = WarehouseError::message(RowNotFound, None);
@@ -1476,24 +1797,17 @@ impl Warehouse {
         None
     }
 
-    pub fn zone_mut(
-        &mut self,
-        row_number: usize,
-        shelf_number: usize,
-        level_number: usize,
-        zone_number: usize,
-    ) -> Option<&mut Zone> {
-        if let Some(row) = self.row_mut(row_number) {
-            return row.zone_mut(shelf_number, level_number, zone_number);
-        }
-        None
-    }
-
+    /// Rows are only changed through the warehouse, which keeps the location
+    /// index and the occupancy bitmaps in step.
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
     pub fn row(&self, row_number: usize) -> Option<&Row> {
         self.rows.iter().find(|r| r.number == row_number)
     }
 
-    pub fn row_mut(&mut self, row_number: usize) -> Option<&mut Row> {
+    fn row_mut(&mut self, row_number: usize) -> Option<&mut Row> {
         self.rows.iter_mut().find(|r| r.number == row_number)
     }
 
@@ -1501,8 +1815,33 @@ impl Warehouse {
         self.rows.iter().map(|r| r.check_capacity()).sum()
     }
 
-    pub fn check_oversized_capacity(&self, zones_required: usize) -> usize {
-        self.rows.iter().map(|r| r.check_oversized_capacity(zones_required)).sum()
+    pub fn check_oversized_capacity(&self, zones_required: usize, levels_required: usize) -> usize {
+        self.rows
+            .iter()
+            .map(|r| r.check_oversized_capacity(zones_required, levels_required))
+            .sum()
+    }
+
+    /// Items of `product` that still fit: the free zones, or free slots for
+    /// oversized products, on the levels the product may be placed on.
+    pub fn capacity_for(&self, product: &Product) -> usize {
+        let max_level = product.max_level().unwrap_or(usize::MAX);
+        if product.is_oversized() {
+            let (zones_required, levels_required) = product.footprint();
+            self.level_stacks(levels_required)
+                .filter(|((_, _, level), _)| *level <= max_level)
+                .map(|(_, stack)| {
+                    (0..Warehouse::stack_slots(stack, zones_required))
+                        .filter(|&slot| Warehouse::is_stack_slot_free(stack, slot, zones_required))
+                        .count()
+                })
+                .sum()
+        } else {
+            self.levels()
+                .filter(|((_, _, level), _)| *level <= max_level)
+                .map(|(_, level)| level.occupancy().free())
+                .sum()
+        }
     }
 
     pub fn is_full(&self) -> bool {
@@ -1513,70 +1852,53 @@ impl Warehouse {
         self.rows.iter().all(|r| r.is_empty())
     }
 
-    pub fn flat_map(&self) -> String {
-        self.rows
-            .iter()
-            .map(|row| row.flat_map())
-            .collect::<Vec<String>>()
-            .join("")
+    pub fn load(&self) -> u64 {
+        self.rows.iter().map(|r| r.load()).sum()
     }
 
-    pub fn oversized_flat_map(&self, zones_required: usize) -> String {
-        self.rows
-            .iter()
-            .map(|row| row.oversized_flat_map(zones_required))
-            .collect::<Vec<String>>()
-            .join("")
+    pub fn occupied_zones(&self) -> usize {
+        self.rows.iter().map(|r| r.occupied_zones()).sum()
     }
 
-    pub fn flat_map_position_to_zone(
-        &self,
-        position: usize,
-    ) -> Option<(usize, usize, usize, usize)> {
-        let mut cumulative_capacity = 0;
-        for (row_index, row) in self.rows.iter().enumerate() {
-            let row_capacity = row.check_capacity();
-            if position < cumulative_capacity + row_capacity {
-                return row
-                    .flat_map_position_to_zone(position - cumulative_capacity)
-                    .map(|(shelf_index, level_index, zone_index)| {
-                        (row_index + 1, shelf_index, level_index, zone_index)
-                    });
-            }
-            cumulative_capacity += row_capacity;
+    pub fn set_shelf_load_rating(
+        &mut self,
+        row_number: usize,
+        shelf_number: usize,
+        load_rating: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self.row_mut(row_number).and_then(|row| row.shelf_mut(shelf_number)) {
+            Some(shelf) => {
+                shelf.set_load_rating(load_rating);
+                Ok(())
+            }
+            None => {
+                let message = ShelfNotFound.at((row_number, shelf_number));
+                Err(WarehouseError::placement(message))
+            }
         }
-        None
     }
 
-    pub fn oversized_flat_map_position_to_zone(
-        &self,
-        position: usize,
-        zones_required: usize,
-    ) -> Option<(usize, usize, usize, usize)> {
-        let mut cumulative_capacity = 0;
-        for (row_index, row) in self.rows.iter().enumerate() {
-            let row_capacity = row.check_capacity();
-            if position < cumulative_capacity + row_capacity - zones_required {
-                println!("{} {}", position, cumulative_capacity);
-                return row
-                    .oversized_flat_map_position_to_zone(
-                        position - cumulative_capacity,
-                        zones_required,
-                    )
-                    .map(|(shelf_index, level_index, zone_index)| {
-                        (row_index + 1, shelf_index, level_index, zone_index)
-                    });
-            } else if position < cumulative_capacity + row_capacity {
-                return row.oversized_flat_map_position_to_zone(
-                    1,
-                    zones_required,
-                ).map(|(shelf_index, level_index, zone_index)| {
-                    (row_index + 1, shelf_index, level_index, zone_index)
-                });
+    pub fn set_level_load_rating(
+        &mut self,
+        row_number: usize,
+        shelf_number: usize,
+        level_number: usize,
+        load_rating: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        match self
+            .row_mut(row_number)
+            .and_then(|row| row.shelf_mut(shelf_number))
+            .and_then(|shelf| shelf.level_mut(level_number))
+        {
+            Some(level) => {
+                level.set_load_rating(load_rating);
+                Ok(())
+            }
+            None => {
+                let message = LevelNotFound.at((row_number, shelf_number, level_number));
+                Err(WarehouseError::placement(message))
             }
-            cumulative_capacity += row_capacity;
         }
-        None
     }
 
     pub fn initialize_rows(
@@ -1594,7 +1916,7 @@ impl Warehouse {
     }
 
     pub fn contains_product(&self, product_id: u64) -> bool {
-        self.rows.iter().any(|row| row.contains_product(product_id))
+        self.locations.contains(product_id)
     }
 
     pub fn add_item(
@@ -1605,10 +1927,13 @@ impl Warehouse {
         zone_number: usize,
         item: ProductItem,
     ) -> Result<(), Box<dyn Error>> {
+        let (product_id, expiry, serial) = (item.id, item.expiry_date, item.serial.clone());
         if let Some(row) = self.row_mut(row_number) {
             match row.add_item(shelf_number, level_number, zone_number, item) {
                 Ok(_) => {
                     self.available_space -= 1;
+                    let placement = (row_number, shelf_number, level_number, zone_number);
+                    self.locations.insert(product_id, expiry, serial.as_deref(), placement);
                     Ok(())
                 }
                 Err(e) => Err(e),
@@ -1628,11 +1953,14 @@ impl Warehouse {
         zone_number: usize,
         item: ProductItem,
     ) -> Result<(), Box<dyn Error>> {
-        let zones_required = item.zones_required;
+        let (product_id, expiry, zones_occupied) = (item.id, item.expiry_date, item.zones_occupied());
+        let serial = item.serial.clone();
         if let Some(row) = self.row_mut(row_number) {
             match row.add_oversized_item(shelf_number, level_number, zone_number, item) {
                 Ok(_) => {
-                    self.available_space -= zones_required;
+                    self.available_space -= zones_occupied;
+                    let placement = (row_number, shelf_number, level_number, zone_number);
+                    self.locations.insert(product_id, expiry, serial.as_deref(), placement);
                     Ok(())
                 }
                 Err(e) => Err(e),
@@ -1651,20 +1979,21 @@ impl Warehouse {
         level_number: usize,
         zone_number: usize,
     ) -> Result<(), Box<dyn Error>> {
-        let zones_required = match self.item(row_number, shelf_number, level_number, zone_number) {
-            Some(item) => item.zones_required,
+        let placement = (row_number, shelf_number, level_number, zone_number);
+        let (product_id, expiry, serial, oversized, zones_occupied) = match self.item(row_number, shelf_number, level_number, zone_number) {
+            Some(item) => (item.id, item.expiry_date, item.serial.clone(), item.is_oversized(), item.zones_occupied()),
             None => {
-                let message =
-                    NoProductFound.at((row_number, shelf_number, level_number, zone_number));
+                let message = NoProductFound.at(placement);
                 return Err(WarehouseError::remotion(message));
             }
         };
 
         if let Some(row) = self.row_mut(row_number) {
-            if zones_required > 1 {
+            if oversized {
                 match row.remove_oversized_item(shelf_number, level_number, zone_number) {
                     Ok(_) => {
-                        self.available_space += zones_required;
+                        self.available_space += zones_occupied;
+                        self.locations.remove(product_id, expiry, serial.as_deref(), placement);
                         Ok(())
                     }
                     Err(e) => Err(e),
@@ -1673,6 +2002,7 @@ impl Warehouse {
                 match row.remove_item(row_number, shelf_number, level_number, zone_number) {
                     Ok(_) => {
                         self.available_space += 1;
+                        self.locations.remove(product_id, expiry, serial.as_deref(), placement);
                         Ok(())
                     }
                     Err(e) => Err(e),
@@ -1685,6 +2015,39 @@ impl Warehouse {
         }
     }
 
+    /// Moves the item at `from` to `to`, keeping the location index in step.
+    /// The item stays at `from` when it cannot be placed at `to`.
+    pub fn move_item(
+        &mut self,
+        list: &ProductList,
+        from: (usize, usize, usize, usize),
+        to: (usize, usize, usize, usize),
+    ) -> Result<(), Box<dyn Error>> {
+        let (row, shelf, level, zone) = from;
+        let mut item = match self.item(row, shelf, level, zone) {
+            Some(item) => item.clone(),
+            None => return Err(WarehouseError::remotion(NoProductFound.at(from))),
+        };
+        item.relocate(list, to)?;
+        self.remove_item(row, shelf, level, zone)?;
+        let (row, shelf, level, zone) = to;
+        let result = match item.is_oversized() {
+            true => self.add_oversized_item(row, shelf, level, zone, item.clone()),
+            false => self.add_item(row, shelf, level, zone, item.clone()),
+        };
+        match result {
+            Ok(_) => {
+                info!("{}", Moved(format!("{} from {:?} to {:?}", item.id, from, to)));
+                Ok(())
+            }
+            Err(e) => {
+                item.placement = from;
+                self.put_back(vec![item])?;
+                Err(e)
+            }
+        }
+    }
+
     pub fn item(
         &self,
         row_number: usize,
@@ -1698,7 +2061,7 @@ impl Warehouse {
         None
     }
 
-    pub fn item_mut(
+    fn item_mut(
         &mut self,
         row_number: usize,
         shelf_number: usize,
@@ -1715,43 +2078,18 @@ impl Warehouse {
         &self,
         product_id: u64,
     ) -> Option<(usize, usize, usize, usize)> {
-        for row in &self.rows {
-            let row_number = row.number;
-            if let Some((shelf_number, level_number, zone_number)) =
-                row.find_first_item_occurrence(product_id)
-            {
-                return Some((row_number, shelf_number, level_number, zone_number));
-            }
-        }
-        None
+        self.locations.get(product_id).next()
     }
 
     pub fn find_last_item_occurrence(
         &self,
         product_id: u64,
     ) -> Option<(usize, usize, usize, usize)> {
-        for row in &self.rows {
-            let row_number = row.number;
-            if let Some((shelf_number, level_number, zone_number)) =
-                row.find_last_item_occurrence(product_id)
-            {
-                return Some((row_number, shelf_number, level_number, zone_number));
-            }
-        }
-        None
+        self.locations.get(product_id).next_back()
     }
 
     pub fn find_all_item_occurences(&self, product_id: u64) -> Vec<(usize, usize, usize, usize)> {
-        let mut items = Vec::new();
-        for row in &self.rows {
-            let row_number = row.number;
-            items.extend(row.find_all_item_occurences(product_id).iter().map(
-                |(shelf_number, level_number, zone_number)| {
-                    (row_number, *shelf_number, *level_number, *zone_number)
-                },
-            ));
-        }
-        items
+        self.locations.get(product_id).collect()
     }
 
     pub fn items(&self) -> Vec<ProductItem> {
@@ -1767,16 +2105,15 @@ impl Warehouse {
             .iter()
             .map(|item| {
                 let product = product_list.product(item.id).unwrap();
-                (product.name.as_str(), item)
+                (product.name(), item)
             })
             .collect()
     }
 
     pub fn items_with_id(&self, product_id: u64) -> Vec<ProductItem> {
-        self.rows
-            .iter()
-            .flat_map(|row| row.items())
-            .filter(|item| item.id == product_id)
+        self.locations
+            .get(product_id)
+            .filter_map(|(row, shelf, level, zone)| self.item(row, shelf, level, zone).cloned())
             .collect()
     }
 
@@ -1785,26 +2122,118 @@ impl Warehouse {
         product_name: &str,
         product_list: &ProductList,
     ) -> Vec<ProductItem> {
+        match product_list.id_from_name(product_name) {
+            Some(id) => self.items_with_id(id),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn items_with_lot(&self, lot: &str) -> Vec<ProductItem> {
         self.rows
             .iter()
             .flat_map(|row| row.items())
-            .filter(|item| {
-                let product = product_list.product(item.id).unwrap();
-                product.name == product_name
-            })
+            .filter(|item| item.lot.as_deref() == Some(lot))
+            .collect()
+    }
+
+    pub fn available_items_with_id(&self, product_id: u64) -> Vec<ProductItem> {
+        self.items_with_id(product_id)
+            .into_iter()
+            .filter(|item| item.is_available())
+            .collect()
+    }
+
+    pub fn items_with_status(&self, status: &str) -> Vec<ProductItem> {
+        let status = ItemStatus::normalize(status);
+        self.levels()
+            .flat_map(|(_, level)| level.stored_items())
+            .filter(|item| item.status.name() == status)
+            .cloned()
             .collect()
     }
 
+    pub fn held_quantity(&self, product_id: u64) -> usize {
+        self.items_with_id(product_id)
+            .iter()
+            .filter(|item| !item.is_available())
+            .count()
+    }
+
+    pub fn set_item_status(
+        &mut self,
+        placement: (usize, usize, usize, usize),
+        status: ItemStatus,
+    ) -> Result<u64, Box<dyn Error>> {
+        let (row, shelf, level, zone) = placement;
+        match self.item_mut(row, shelf, level, zone) {
+            Some(item) => {
+                info!("Item {} at {:?} marked as {}", item.id, item.placement, status);
+                item.status = status;
+                Ok(item.id)
+            }
+            None => Err(WarehouseError::placement(NoProductFound.at(placement))),
+        }
+    }
+
+    pub fn quarantine_lot(&mut self, lot: &str) -> usize {
+        let items = self.items_with_lot(lot);
+        for item in &items {
+            let (row, shelf, level, zone) = item.placement;
+            if let Some(item) = self.item_mut(row, shelf, level, zone) {
+                item.status = ItemStatus::Quarantined;
+            }
+        }
+        info!("Quarantined {} items of lot {}", items.len(), lot);
+        items.len()
+    }
+
+    pub fn item_with_serial(&self, serial: &str) -> Option<&ProductItem> {
+        let (row, shelf, level, zone) = self.locations.serial(serial)?;
+        self.item(row, shelf, level, zone)
+    }
+
+    pub fn check_serials(&self, serials: &[String]) -> Result<(), Box<dyn Error>> {
+        let mut seen = HashSet::new();
+        for serial in serials {
+            if !seen.insert(serial.as_str()) || self.locations.serial(serial).is_some() {
+                let message = WarehouseError::message(DuplicateSerial, Some(format!("- {}", serial)));
+                return Err(WarehouseError::placement(message));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn remove_serial(&mut self, serial: &str) -> Result<ProductItem, Box<dyn Error>> {
+        match self.item_with_serial(serial).cloned() {
+            Some(item) => {
+                let (row, shelf, level, zone) = item.placement;
+                self.remove_item(row, shelf, level, zone)?;
+                info!("{}", Removed(format!("item {} with serial {}", item.id, serial)));
+                Ok(item)
+            }
+            None => {
+                let message = WarehouseError::message(SerialNotFound, Some(format!("- {}", serial)));
+                Err(WarehouseError::remotion(message))
+            }
+        }
+    }
+
     pub fn print_item_list(item_list: &[ProductItem]) {
         item_list.iter().for_each(|item| {
+            let mut line = format!("ID: {}, Placement: {:?}", item.id, item.placement);
             if let Some(expiry_date) = item.expiry_date {
-                println!(
-                    "ID: {}, Placement: {:?}, Expiry Date: {}",
-                    item.id, item.placement, expiry_date
-                );
-            } else {
-                println!("ID: {}, Placement: {:?}", item.id, item.placement);
+                line.push_str(&format!(", Expiry Date: {}", expiry_date));
             }
+            if let Some(lot) = &item.lot {
+                line.push_str(&format!(", Lot: {}", lot));
+            }
+            if let Some(serial) = &item.serial {
+                line.push_str(&format!(", Serial: {}", serial));
+            }
+            if !item.is_available() {
+                line.push_str(&format!(", Status: {}", item.status));
+            }
+            println!("{}", line);
         });
         println!();
     }
@@ -1966,14 +2395,102 @@ impl Warehouse {
         println!();
     }
 
+    pub fn print_capacity_report(&self) {
+        println!("Capacity report");
+        println!(
+            "Warehouse: {}, Load: {}",
+            zone_utilization(self.occupied_zones(), self.check_capacity()),
+            format_weight(self.load())
+        );
+        for row in &self.rows {
+            println!(
+                "Row {}: {}, Load: {}",
+                row.number,
+                zone_utilization(row.occupied_zones(), row.check_capacity()),
+                format_weight(row.load())
+            );
+            for shelf in &row.shelves {
+                println!(
+                    "  Shelf {}: {}, Load: {}",
+                    shelf.number,
+                    zone_utilization(shelf.occupied_zones(), shelf.check_capacity()),
+                    load_utilization(shelf.load(), shelf.load_rating)
+                );
+                for level in &shelf.levels {
+                    println!(
+                        "    Level {}: {}, Load: {}",
+                        level.number,
+                        zone_utilization(level.occupied_zones(), level.check_capacity()),
+                        load_utilization(level.load(), level.load_rating)
+                    );
+                }
+            }
+        }
+        println!();
+    }
+
+    /// Every level in placement order, with its (row, shelf, level) position.
+    fn levels(&self) -> impl Iterator<Item = ((usize, usize, usize), &Level)> {
+        self.rows.iter().enumerate().flat_map(|(row_index, row)| {
+            row.shelves.iter().enumerate().flat_map(move |(shelf_index, shelf)| {
+                shelf.levels.iter().enumerate().map(move |(level_index, level)| {
+                    ((row_index + 1, shelf_index + 1, level_index + 1), level)
+                })
+            })
+        })
+    }
+
+    /// Every stack of `levels_required` levels an oversized item can stand on,
+    /// in placement order, with the position of its base level.
+    fn level_stacks(
+        &self,
+        levels_required: usize,
+    ) -> impl Iterator<Item = ((usize, usize, usize), &[Level])> {
+        self.rows.iter().enumerate().flat_map(move |(row_index, row)| {
+            row.shelves.iter().enumerate().flat_map(move |(shelf_index, shelf)| {
+                shelf.base_levels(levels_required).map(move |(level_index, _)| {
+                    let stack = &shelf.levels[level_index..level_index + levels_required];
+                    ((row_index + 1, shelf_index + 1, level_index + 1), stack)
+                })
+            })
+        })
+    }
+
+    fn stack_slots(stack: &[Level], zones_required: usize) -> usize {
+        stack[0].check_oversized_capacity(zones_required)
+    }
+
+    fn is_stack_free(stack: &[Level], slots: usize, zones_required: usize) -> bool {
+        stack.iter().all(|level| {
+            level.is_empty() && level.check_oversized_capacity(zones_required) >= slots
+        })
+    }
+
+    fn is_stack_slot_free(stack: &[Level], slot: usize, zones_required: usize) -> bool {
+        stack.iter().all(|level| level.is_slot_free(slot, zones_required))
+    }
+
+    /// First run of `qty` free zones, which may continue across levels, shelves
+    /// and rows. Full levels are skipped using their occupancy summaries.
     pub fn find_first_contiguous_space(&self, qty: usize) -> Option<(usize, usize, usize, usize)> {
-        let flat_map = self.flat_map();
-        let mut index = 0;
-        while index + qty < flat_map.len() {
-            if flat_map[index..index + qty] == "0".repeat(qty) {
-                return self.flat_map_position_to_zone(index + 1);
+        if qty == 0 {
+            let mut levels = self.levels().filter(|(_, level)| !level.zones.is_empty());
+            return levels.next().map(|((row, shelf, level), _)| (row, shelf, level, 1));
+        }
+        let mut run = FreeRun::default();
+        for ((row, shelf, level_number), level) in self.levels() {
+            let occupancy = level.occupancy();
+            if run.len > 0 && run.len + occupancy.leading_free() >= qty {
+                return run.start;
+            }
+            if let Some(index) = occupancy.find_free_run(0, qty) {
+                return Some((row, shelf, level_number, index + 1));
+            }
+            let start = (row, shelf, level_number, occupancy.len() - occupancy.trailing_free() + 1);
+            match occupancy.is_all_free() {
+                true => run.extend((row, shelf, level_number, 1), occupancy.len()),
+                false => run.restart(start, occupancy.trailing_free()),
             }
-            index += 1;
         }
         None
     }
@@ -1982,40 +2499,74 @@ impl Warehouse {
         &self,
         qty: usize,
         zones_required: usize,
+        levels_required: usize,
     ) -> Option<(usize, usize, usize, usize)> {
-        let flat_map = self.oversized_flat_map(zones_required);
-        println!("{}", flat_map);
-        let mut index = 0;
-        while index + (qty * zones_required) < flat_map.len() {
-            if flat_map[index..index + (qty * zones_required)]
-                == format!("1{}", "0".repeat(zones_required - 1)).repeat(qty)
-            {
-                println!("{}", index);
-                return self.oversized_flat_map_position_to_zone(index + 1, zones_required);
+        if qty == 0 {
+            let mut stacks = self
+                .level_stacks(levels_required)
+                .filter(|(_, stack)| Warehouse::stack_slots(stack, zones_required) > 0);
+            return stacks.next().map(|((row, shelf, level), _)| (row, shelf, level, 1));
+        }
+        let mut run = FreeRun::default();
+        for ((row, shelf, level), stack) in self.level_stacks(levels_required) {
+            let slots = Warehouse::stack_slots(stack, zones_required);
+            if slots == 0 {
+                continue;
+            }
+            if Warehouse::is_stack_free(stack, slots, zones_required) {
+                run.extend((row, shelf, level, 1), slots);
+            } else if stack.iter().any(|lvl| lvl.occupancy().longest_free() < zones_required) {
+                run = FreeRun::default();
+            } else {
+                for slot in 0..slots {
+                    if !Warehouse::is_stack_slot_free(stack, slot, zones_required) {
+                        run = FreeRun::default();
+                        continue;
+                    }
+                    run.extend((row, shelf, level, slot * zones_required + 1), 1);
+                    if run.len >= qty {
+                        return run.start;
+                    }
+                }
+            }
+            if run.len >= qty {
+                return run.start;
             }
-            index += 1;
         }
         None
     }
 
+    fn index_placed(&mut self, id: u64, placed: Vec<(usize, usize, usize, usize)>) {
+        for (row, shelf, level, zone) in placed {
+            let (expiry, serial) = match self.item(row, shelf, level, zone) {
+                Some(item) => (item.expiry_date, item.serial.clone()),
+                None => (None, None),
+            };
+            self.locations.insert(id, expiry, serial.as_deref(), (row, shelf, level, zone));
+        }
+    }
+
     pub fn add_qty(
         &mut self,
         id: u64,
         list: &mut ProductList,
         mut qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
         start: (usize, usize, usize, usize),
     ) -> Result<(), Box<dyn Error>> {
         let (mut row, mut shelf, mut level, mut zone) = start;
         while qty > 0 {
             let placement = (shelf, level, zone);
-            match self.rows[row - 1].add_qty(id, list, &mut qty, expiry_date, placement) {
+            let mut placed = Vec::new();
+            let result = self.rows[row - 1].add_qty(id, list, &mut qty, delivery, placement, &mut placed);
+            self.index_placed(id, placed);
+            match result {
                 Ok(_) => {
-                    if row > self.rows.len() {
+                    row += 1;
+                    if qty > 0 && row > self.rows.len() {
                         let message = WarehouseError::message(EndOfRows, None);
                         return Err(WarehouseError::addition(message));
                     }
-                    row += 1;
                     shelf = 1;
                     level = 1;
                     zone = 1;
@@ -2028,33 +2579,39 @@ impl Warehouse {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_oversized_qty(
         &mut self,
         id: u64,
         list: &mut ProductList,
         mut qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
         zones_required: usize,
+        levels_required: usize,
         start: (usize, usize, usize, usize),
     ) -> Result<(), Box<dyn Error>> {
         let (mut row, mut shelf, mut level, mut zone) = start;
-        println!("{:?}", (row, shelf, level, zone));
         while qty > 0 {
             let placement = (shelf, level, zone);
-            match self.rows[row - 1].add_oversized_qty(
+            let mut placed = Vec::new();
+            let result = self.rows[row - 1].add_oversized_qty(
                 id,
                 list,
                 &mut qty,
-                expiry_date,
+                delivery,
                 zones_required,
+                levels_required,
                 placement,
-            ) {
+                &mut placed,
+            );
+            self.index_placed(id, placed);
+            match result {
                 Ok(_) => {
-                    if row > self.rows.len() {
+                    row += 1;
+                    if qty > 0 && row > self.rows.len() {
                         let message = WarehouseError::message(EndOfRows, None);
                         return Err(WarehouseError::addition(message));
                     }
-                    row += 1;
                     shelf = 1;
                     level = 1;
                     zone = 1;
@@ -2070,10 +2627,10 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
     ) -> Result<(), Box<dyn Error>> {
         if let Some((row, shelf, level, zone)) = self.find_first_contiguous_space(qty) {
-            self.add_qty(id, list, qty, expiry_date, (row, shelf, level, zone))?
+            self.add_qty(id, list, qty, delivery, (row, shelf, level, zone))?
         } else {
             let details = Some("Did not find contiguous space".to_string());
             let message = WarehouseError::message(InsufficientSpace, details);
@@ -2087,22 +2644,22 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
         zones_required: usize,
+        levels_required: usize,
     ) -> Result<(), Box<dyn Error>> {
         if let Some((row, shelf, level, zone)) =
-            self.find_first_contiguous_oversized_space(qty, zones_required)
+            self.find_first_contiguous_oversized_space(qty, zones_required, levels_required)
         {
-            println!("{:?}", (row, shelf, level, zone));
             self.add_oversized_qty(
                 id,
                 list,
                 qty,
-                expiry_date,
+                delivery,
                 zones_required,
+                levels_required,
                 (row, shelf, level, zone),
             )?;
-            Warehouse::print_item_list(&self.items());
         } else {
             let details = Some("Did not find contiguous space".to_string());
             let message = WarehouseError::message(InsufficientSpace, details);
@@ -2116,7 +2673,7 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
     ) -> Result<(), Box<dyn Error>> {
         let product = match list.product(id) {
             Some(product) => product,
@@ -2125,11 +2682,18 @@ impl Warehouse {
                 return Err(WarehouseError::addition(message));
             }
         };
-        match product.quality {
-            Oversized(zones_required) | OversizedAndFragile(zones_required, _) => {
-                self.place_contiguous_oversized_stock(id, list, qty, expiry_date, zones_required)
-            }
-            _ => self.place_contiguous_stock(id, list, qty, expiry_date),
+        let (zones_required, levels_required) = product.footprint();
+        if product.is_oversized() {
+            self.place_contiguous_oversized_stock(
+                id,
+                list,
+                qty,
+                delivery,
+                zones_required,
+                levels_required,
+            )
+        } else {
+            self.place_contiguous_stock(id, list, qty, delivery)
         }
     }
 
@@ -2198,16 +2762,17 @@ impl Warehouse {
         vacancy_map: &mut HashMap<(usize, usize), bool>,
         max_level: Option<usize>,
         zones_required: usize,
+        levels_required: usize,
     ) -> Option<(usize, usize, usize, usize)> {
         while let Some((row, shelf)) = self.diagonal_search(vacancy_map) {
-            if let Some((level, zone)) =
-                self.rows[row - 1].shelves[shelf - 1].find_oversized_vacant_zone(zones_required)
+            if let Some((level, zone)) = self.rows[row - 1].shelves[shelf - 1]
+                .find_oversized_vacant_zone(zones_required, levels_required)
             {
                 let levels = self.rows[row - 1].shelves[shelf - 1].levels.len();
                 let zones = self.rows[row - 1].shelves[shelf - 1].levels[level - 1]
                     .zones
                     .len();
-                if zone >= zones - zones_required && level == levels {
+                if zone >= zones - zones_required && level + levels_required - 1 == levels {
                     vacancy_map.insert((row, shelf), false);
                 } else if level > max_level.unwrap_or(levels) {
                     vacancy_map.insert((row, shelf), false);
@@ -2224,7 +2789,7 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         mut qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
     ) -> Result<(), Box<dyn Error>> {
         let mut vacancy_map = self.shelf_vacancy_map();
         let max_level = list.product(id).map(|p| p.max_level()).unwrap();
@@ -2232,11 +2797,9 @@ impl Warehouse {
             let place = self.find_closest_to_start(&mut vacancy_map, max_level);
             if let Some((row, shelf, level, zone)) = place {
                 let placement = (row, shelf, level, zone);
-                let item = match ProductItem::new(id, list, placement, expiry_date) {
-                    Ok(item) => item,
-                    Err(e) => return Err(e),
-                };
-                match self.add_item(row, shelf, level, zone, item) {
+                match delivery.place(id, list, placement, |item| {
+                    self.add_item(row, shelf, level, zone, item)
+                }) {
                     Ok(_) => {
                         qty -= 1;
                     }
@@ -2256,21 +2819,24 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         mut qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
         zones_required: usize,
+        levels_required: usize,
     ) -> Result<(), Box<dyn Error>> {
         let mut vacancy_map = self.shelf_vacancy_map();
         let max_level = list.product(id).map(|p| p.max_level()).unwrap();
         while qty > 0 {
-            let place =
-                self.find_oversized_closest_to_start(&mut vacancy_map, max_level, zones_required);
+            let place = self.find_oversized_closest_to_start(
+                &mut vacancy_map,
+                max_level,
+                zones_required,
+                levels_required,
+            );
             if let Some((row, shelf, level, zone)) = place {
                 let placement = (row, shelf, level, zone);
-                let item = match ProductItem::new(id, list, placement, expiry_date) {
-                    Ok(item) => item,
-                    Err(e) => return Err(e),
-                };
-                match self.add_oversized_item(row, shelf, level, zone, item) {
+                match delivery.place(id, list, placement, |item| {
+                    self.add_oversized_item(row, shelf, level, zone, item)
+                }) {
                     Ok(_) => {
                         qty -= 1;
                     }
@@ -2290,7 +2856,7 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
     ) -> Result<(), Box<dyn Error>> {
         let product = match list.product(id) {
             Some(product) => product,
@@ -2298,37 +2864,64 @@ impl Warehouse {
                 return Err(WarehouseError::placement(ProductNotListed.with_id(id)));
             }
         };
-        match product.quality {
-            Oversized(zones_required) | OversizedAndFragile(zones_required, _) => self
-                .place_oversized_stock_closest_to_start(id, list, qty, expiry_date, zones_required),
-            _ => self.place_stock_closest_to_start(id, list, qty, expiry_date),
+        let (zones_required, levels_required) = product.footprint();
+        if product.is_oversized() {
+            self.place_oversized_stock_closest_to_start(
+                id,
+                list,
+                qty,
+                delivery,
+                zones_required,
+                levels_required,
+            )
+        } else {
+            self.place_stock_closest_to_start(id, list, qty, delivery)
         }
     }
 
-    pub fn find_round_robin_continuation(
-        &self,
-        flat_map: String,
-    ) -> Option<(usize, usize, usize, usize)> {
-        if let Some(last_index) = flat_map.rfind('1') {
-            self.flat_map_position_to_zone(last_index)
-        } else {
-            Some((1, 1, 1, 1))
+    /// The zone right after the last occupied one.
+    pub fn find_round_robin_continuation(&self) -> Option<(usize, usize, usize, usize)> {
+        let levels: Vec<_> = self.levels().collect();
+        let Some(last) = levels.iter().rposition(|(_, level)| !level.is_empty()) else {
+            return Some((1, 1, 1, 1));
+        };
+        let ((row, shelf, level_number), level) = levels[last];
+        let zone = level.occupancy().last_occupied().unwrap_or_default() + 1;
+        if zone < level.check_capacity() {
+            return Some((row, shelf, level_number, zone + 1));
         }
+        levels[last + 1..]
+            .iter()
+            .find(|(_, level)| level.check_capacity() > 0)
+            .map(|((row, shelf, level), _)| (*row, *shelf, *level, 1))
     }
 
+    /// The slot right after the last one blocked for an item of this footprint.
     pub fn find_oversized_round_robin_continuation(
         &self,
-        flat_map: String,
         zones_required: usize,
+        levels_required: usize,
     ) -> Option<(usize, usize, usize, usize)> {
-        let pattern = "0".repeat(zones_required);
-        if let Some(last_index) = flat_map.rfind(pattern.as_str()) {
-            self.oversized_flat_map_position_to_zone(last_index, zones_required)
-        } else if self.is_empty() {
-            Some((1, 1, 1, 1))
-        } else {
-            None
+        let stacks: Vec<_> = self.level_stacks(levels_required).collect();
+        for (index, ((row, shelf, level), stack)) in stacks.iter().enumerate().rev() {
+            let slots = Warehouse::stack_slots(stack, zones_required);
+            if Warehouse::is_stack_free(stack, slots, zones_required) {
+                continue;
+            }
+            let blocked = (0..slots)
+                .rev()
+                .find(|&slot| !Warehouse::is_stack_slot_free(stack, slot, zones_required));
+            if let Some(slot) = blocked {
+                if slot + 1 < slots {
+                    return Some((*row, *shelf, *level, (slot + 1) * zones_required + 1));
+                }
+                return stacks[index + 1..]
+                    .iter()
+                    .find(|(_, stack)| Warehouse::stack_slots(stack, zones_required) > 0)
+                    .map(|((row, shelf, level), _)| (*row, *shelf, *level, 1));
+            }
         }
+        Some((1, 1, 1, 1))
     }
 
     pub fn place_stock_in_round_robin(
@@ -2336,11 +2929,10 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
     ) -> Result<(), Box<dyn Error>> {
-        let flat_map = self.flat_map();
-        if let Some(first_zone) = self.find_round_robin_continuation(flat_map) {
-            self.add_qty(id, list, qty, expiry_date, first_zone)?;
+        if let Some(first_zone) = self.find_round_robin_continuation() {
+            self.add_qty(id, list, qty, delivery, first_zone)?;
             Ok(())
         } else {
             let details = Some("Did not find place to continue round-robin".to_string());
@@ -2354,13 +2946,22 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
         zones_required: usize,
+        levels_required: usize,
     ) -> Result<(), Box<dyn Error>> {
-        let flat_map = self.oversized_flat_map(zones_required);
-        let first_zone = self.find_oversized_round_robin_continuation(flat_map, zones_required);
+        let first_zone =
+            self.find_oversized_round_robin_continuation(zones_required, levels_required);
         if let Some(first_zone) = first_zone {
-            self.add_oversized_qty(id, list, qty, expiry_date, zones_required, first_zone)?;
+            self.add_oversized_qty(
+                id,
+                list,
+                qty,
+                delivery,
+                zones_required,
+                levels_required,
+                first_zone,
+            )?;
             Ok(())
         } else {
             let details = Some("Did not find place to continue round-robin".to_string());
@@ -2374,7 +2975,7 @@ impl Warehouse {
         id: u64,
         list: &mut ProductList,
         qty: usize,
-        expiry_date: Option<NaiveDate>,
+        delivery: &mut Delivery,
     ) -> Result<(), Box<dyn Error>> {
         let product = match list.product(id) {
             Some(product) => product,
@@ -2382,10 +2983,18 @@ impl Warehouse {
                 return Err(WarehouseError::placement(ProductNotListed.with_id(id)));
             }
         };
-        match product.quality {
-            Oversized(zones_required) | OversizedAndFragile(zones_required, _) => self
-                .place_oversized_stock_in_round_robin(id, list, qty, expiry_date, zones_required),
-            _ => self.place_stock_in_round_robin(id, list, qty, expiry_date),
+        let (zones_required, levels_required) = product.footprint();
+        if product.is_oversized() {
+            self.place_oversized_stock_in_round_robin(
+                id,
+                list,
+                qty,
+                delivery,
+                zones_required,
+                levels_required,
+            )
+        } else {
+            self.place_stock_in_round_robin(id, list, qty, delivery)
         }
     }
 
@@ -2396,11 +3005,32 @@ impl Warehouse {
         list: &mut ProductList,
         expiry_date: Option<NaiveDate>,
     ) -> Result<(), Box<dyn Error>> {
-        if list.product(id).is_some() {
+        self.restock(id, qty, list, &mut Delivery::new(expiry_date))
+    }
+
+    pub fn restock(
+        &mut self,
+        id: u64,
+        qty: usize,
+        list: &mut ProductList,
+        delivery: &mut Delivery,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(product) = list.product(id) {
+            if delivery.expiry_date.is_none() && delivery.units.is_empty() {
+                let received = chrono::Local::now().naive_local().date();
+                delivery.expiry_date = product.expiry_from(received);
+            }
+            if !delivery.serials.is_empty() {
+                if delivery.serials.len() != qty {
+                    let message = WarehouseError::message(SerialCountMismatch, None);
+                    return Err(WarehouseError::placement(message));
+                }
+                self.check_serials(delivery.serials.make_contiguous())?;
+            }
             match self.strategy {
-                Contiguous => self.contiguous_placement(id, list, qty, expiry_date),
-                RoundRobin => self.round_robin_placement(id, list, qty, expiry_date),
-                ClosestToStart => self.closest_to_start_placement(id, list, qty, expiry_date),
+                Contiguous => self.contiguous_placement(id, list, qty, delivery),
+                RoundRobin => self.round_robin_placement(id, list, qty, delivery),
+                ClosestToStart => self.closest_to_start_placement(id, list, qty, delivery),
             }?;
             info!("{}", Restocked(format!("{} units of {}", qty, id)));
             Ok(())
@@ -2409,6 +3039,20 @@ impl Warehouse {
         }
     }
 
+    /// Removes the units of a product placed since `existing` was taken, undoing
+    /// a restock that failed part-way. Returns how many units were removed.
+    pub fn undo_restock(
+        &mut self,
+        id: u64,
+        existing: &BTreeSet<(usize, usize, usize, usize)>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let placed: Vec<_> = self.locations.get(id).filter(|placement| !existing.contains(placement)).collect();
+        for &(row, shelf, level, zone) in &placed {
+            self.remove_item(row, shelf, level, zone)?;
+        }
+        Ok(placed.len())
+    }
+
     pub fn sort_by_expiry_date(item_list: Vec<ProductItem>) -> Vec<ProductItem> {
         let mut items = item_list.clone();
         items.sort_by(|a, b| {
@@ -2435,10 +3079,12 @@ impl Warehouse {
     pub fn filter_expired_items(&self, expiry_date: NaiveDate) -> Vec<ProductItem> {
         self.items()
             .into_iter()
-            .filter(|item| item.expiry_date < Some(expiry_date))
+            .filter(|item| item.expiry_date.is_some_and(|date| date < expiry_date))
             .collect()
     }
 
+    /// Takes `qty` items from the end of `list`, or none of them: items already
+    /// taken are put back when one cannot be.
     pub fn take_stock(
         &mut self,
         mut qty: usize,
@@ -2446,38 +3092,71 @@ impl Warehouse {
     ) -> Result<Vec<ProductItem>, Box<dyn Error>> {
         let mut taken_items = Vec::new();
         while qty > 0 {
-            if let Some(item) = list.pop() {
-                let (row, shelf, level, zone) = item.placement;
-                match self.remove_item(row, shelf, level, zone) {
-                    Ok(_) => {
-                        info!("Taken item {}", item);
-                        taken_items.push(item);
-                        qty -= 1;
-                    }
-                    Err(e) => return Err(e),
+            let result = match list.pop() {
+                Some(item) => {
+                    let (row, shelf, level, zone) = item.placement;
+                    self.remove_item(row, shelf, level, zone).map(|_| item)
+                }
+                None => {
+                    let message = WarehouseError::message(InsufficientStock, None);
+                    Err(WarehouseError::remotion(message))
+                }
+            };
+            match result {
+                Ok(item) => {
+                    info!("Taken item {}", item);
+                    taken_items.push(item);
+                    qty -= 1;
+                }
+                Err(e) => {
+                    self.put_back(taken_items)?;
+                    return Err(e);
                 }
-            } else {
-                let message = WarehouseError::message(InsufficientStock, None);
-                return Err(WarehouseError::remotion(message));
             }
         }
         Ok(taken_items)
     }
 
-    pub fn remove_stock(&mut self, id: u64, qty: usize) -> Result<(), Box<dyn Error>> {
-        let mut list = self.items_with_id(id);
-        if list[0].expiry_date.is_some() {
-            list = Warehouse::sort_by_expiry_date(list);
-            list.reverse();
+    /// Puts items taken out with `take_stock` back at their placements.
+    pub fn put_back(&mut self, items: Vec<ProductItem>) -> Result<(), Box<dyn Error>> {
+        for item in items {
+            let (row, shelf, level, zone) = item.placement;
+            match item.is_oversized() {
+                true => self.add_oversized_item(row, shelf, level, zone, item)?,
+                false => self.add_item(row, shelf, level, zone, item)?,
+            }
         }
-        self.take_stock(qty, list)?;
+        Ok(())
+    }
+
+    pub fn pick_stock(&mut self, id: u64, qty: usize) -> Result<Vec<ProductItem>, Box<dyn Error>> {
+        let mut list: Vec<ProductItem> = self
+            .locations
+            .picking_order(id)
+            .filter_map(|(row, shelf, level, zone)| self.item(row, shelf, level, zone))
+            .filter(|item| item.is_available())
+            .take(qty)
+            .cloned()
+            .collect();
+        if list.len() < qty {
+            let message = WarehouseError::message(InsufficientStock, None);
+            return Err(WarehouseError::remotion(message));
+        }
+        list.reverse();
+        self.take_stock(qty, list)
+    }
+
+    pub fn remove_stock(&mut self, id: u64, qty: usize) -> Result<(), Box<dyn Error>> {
+        self.pick_stock(id, qty)?;
         info!("{}", Removed(format!("{} units of {}", qty, id)));
         Ok(())
     }
 
     pub fn remove_all_stock(&mut self, id: u64) -> Result<(), Box<dyn Error>> {
         let list = self.items_with_id(id);
-        self.remove_stock(id, list.len())?;
+        let qty = list.len();
+        self.take_stock(qty, list)?;
+        info!("{}", Removed(format!("{} units of {}", qty, id)));
         Ok(())
     }
 
@@ -2488,6 +3167,34 @@ impl Warehouse {
     }
 }
 
+fn percentage(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    used as f64 * 100.0 / total as f64
+}
+
+fn zone_utilization(occupied: usize, capacity: usize) -> String {
+    format!(
+        "{}/{} zones ({:.1}%)",
+        occupied,
+        capacity,
+        percentage(occupied as u64, capacity as u64)
+    )
+}
+
+fn load_utilization(load: u64, load_rating: Option<u64>) -> String {
+    match load_rating {
+        Some(load_rating) => format!(
+            "{} / {} ({:.1}%)",
+            format_weight(load),
+            format_weight(load_rating),
+            percentage(load, load_rating)
+        ),
+        None => format!("{} (unrated)", format_weight(load)),
+    }
+}
+
 impl Default for Warehouse {
     fn default() -> Self {
         let mut warehouse = Warehouse::new();